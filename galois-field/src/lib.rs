@@ -268,7 +268,10 @@ for<'a> &'a GF::CoeffType: Add<Output = GF::CoeffType>
       (t_next, t_now) = (t_now - &quotient * &t_next, t_next);
     }
 
-    GF::make_polynomial(t_now)
+    // The gcd is a constant, but only 1 in characteristic 2, so scale by its inverse
+    let gcd = r_now.coefficients[0].clone();
+    let one = GF::CoeffType::one();
+    GF::make_polynomial(&t_now * &Polynomial::<GF::CoeffType>::from(vec![&one / &gcd]))
   }
 }
 
@@ -362,6 +365,14 @@ mod tests {
     assert_eq!(test.inv(), rhs);
   }
 
+  #[test]
+  fn test_every_nonzero_element_has_an_inverse() {
+    // Unlike GF(2^m), the final remainder in GF(9) can be 2 rather than 1
+    for element in GF9::all_elements() {
+      assert_eq!(&element * &element.clone().inv(), PolyWithinGF::<GF9>::one());
+    }
+  }
+
   #[test]
   fn test_there_are_eight_nonzero_elements_in_GF9() {
     assert_eq!(GF9::all_elements().count(), 8);
@@ -58,20 +58,11 @@ where
     type Output = Polynomial<CoeffType>;
 
     fn sub(self, other: &Polynomial<CoeffType>) -> Polynomial<CoeffType> {
-        let longer;
-        let shorter;
-        if self.coefficients.len() < other.coefficients.len() {
-            longer = &other.coefficients;
-            shorter = &self.coefficients;
-        } else {
-            longer = &self.coefficients;
-            shorter = &other.coefficients;
-        }
-
-        let coefficients = longer
-            .iter()
-            .zip(shorter.iter().chain(iter::repeat(&CoeffType::zero())))
-            .map(|(left, right)| left - right)
+        // Unlike addition, the order matters, so pad whichever is shorter with zeros
+        let zero = CoeffType::zero();
+        let length = self.coefficients.len().max(other.coefficients.len());
+        let coefficients = (0..length)
+            .map(|index| self.coefficients.get(index).unwrap_or(&zero) - other.coefficients.get(index).unwrap_or(&zero))
             .collect::<Vec<CoeffType>>();
         let mut diff = Polynomial { coefficients };
         diff.reduce();
@@ -430,6 +421,22 @@ mod tests {
         assert_eq!(&lhs + &rhs, sum);
     }
 
+    #[test]
+    fn subtraction_from_shorter_polynomial() {
+        // Test that (x + 1) - (x^2 + 2) = (-x^2 + x - 1), which mod 3 is (2x^2 + x + 2)
+        type IM3 = IntMod<3>;
+        let lhs = Polynomial::<IM3> {
+            coefficients: vec![IM3::from(1), IM3::from(1)],
+        };
+        let rhs = Polynomial::<IM3> {
+            coefficients: vec![IM3::from(2), IM3::from(0), IM3::from(1)],
+        };
+        let difference = Polynomial::<IM3> {
+            coefficients: vec![IM3::from(2), IM3::from(1), IM3::from(2)],
+        };
+        assert_eq!(&lhs - &rhs, difference);
+    }
+
     #[test]
     fn addition_when_coeffs_are_mod_2() {
         // Test that (x^2 + x + 1) + (x^3 + x^2 + x) = (x^3 + 1)
@@ -0,0 +1,17 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use qr_generator::{lay_out_and_mask, CorrectionLevels, QRSymbolTypes};
+
+// Lays out and masks small, medium and the largest symbols. Error correction is left out, as at large
+// versions it costs far more than scoring the eight candidate masks.
+fn mask_evaluation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mask_evaluation");
+    for version in [1, 10, 40] {
+        group.bench_with_input(BenchmarkId::from_parameter(format!("v{}", version)), &version, |b, &version| {
+            b.iter(|| lay_out_and_mask(QRSymbolTypes::QRCode, version, CorrectionLevels::L))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, mask_evaluation);
+criterion_main!(benches);
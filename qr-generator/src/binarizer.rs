@@ -0,0 +1,177 @@
+use image::{DynamicImage, GrayImage};
+
+// Pixels are thresholded in square blocks of this size
+const BLOCK_SIZE: u32 = 8;
+// Blocks whose darkest and lightest pixels are closer than this are taken to be all one colour
+const MIN_DYNAMIC_RANGE: u32 = 24;
+
+// An image reduced to dark and light pixels
+#[derive(Clone)]
+pub(crate) struct BinaryImage {
+    width: u32,
+    height: u32,
+    dark: Vec<bool>,
+}
+
+impl BinaryImage {
+    // Thresholds each block of pixels against the average of the 5x5 blocks around it, so that shadows
+    // and uneven lighting across a photo don't swamp the symbol. Images too small for that share one
+    // threshold, chosen by Otsu's method. Transparent pixels are taken to be on white.
+    pub fn threshold(image: &DynamicImage) -> Self {
+        let grey = Self::flatten(image);
+        let (width, height) = grey.dimensions();
+        let (block_columns, block_rows) = (width.div_ceil(BLOCK_SIZE), height.div_ceil(BLOCK_SIZE));
+        if block_columns < 5 || block_rows < 5 {
+            let threshold = Self::otsu_threshold(&grey);
+            return Self {
+                width,
+                height,
+                dark: grey.pixels().map(|pixel| pixel.0[0] <= threshold).collect(),
+            };
+        }
+
+        let black_points = Self::black_points(&grey, block_columns, block_rows);
+        let mut dark = vec![false; (width * height) as usize];
+        for block_y in 0..block_rows {
+            for block_x in 0..block_columns {
+                let centre_x = block_x.clamp(2, block_columns - 3);
+                let centre_y = block_y.clamp(2, block_rows - 3);
+                let threshold = (centre_y - 2..=centre_y + 2)
+                    .flat_map(|y| (centre_x - 2..=centre_x + 2).map(move |x| (x, y)))
+                    .map(|(x, y)| black_points[(y * block_columns + x) as usize])
+                    .sum::<u32>()
+                    / 25;
+                for y in block_y * BLOCK_SIZE..((block_y + 1) * BLOCK_SIZE).min(height) {
+                    for x in block_x * BLOCK_SIZE..((block_x + 1) * BLOCK_SIZE).min(width) {
+                        dark[(y * width + x) as usize] = grey.get_pixel(x, y).0[0] as u32 <= threshold;
+                    }
+                }
+            }
+        }
+        Self { width, height, dark }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // Whether the pixel is dark, or None outside the image
+    pub fn get(&self, x: i64, y: i64) -> Option<bool> {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return None;
+        }
+        Some(self.dark[(y * self.width as i64 + x) as usize])
+    }
+
+    // Swaps dark and light, for symbols printed light on dark
+    pub fn inverted(&self) -> Self {
+        Self {
+            dark: self.dark.iter().map(|dark| !dark).collect(),
+            ..self.clone()
+        }
+    }
+
+    fn flatten(image: &DynamicImage) -> GrayImage {
+        let grey = image.to_luma_alpha8();
+        GrayImage::from_fn(grey.width(), grey.height(), |x, y| {
+            let [luma, alpha] = grey.get_pixel(x, y).0.map(u32::from);
+            image::Luma([((luma * alpha + 255 * (255 - alpha)) / 255) as u8])
+        })
+    }
+
+    // The average of each block, or for blocks of one colour, a guess which keeps them light unless the
+    // blocks above and to the left say they're dark
+    fn black_points(grey: &GrayImage, block_columns: u32, block_rows: u32) -> Vec<u32> {
+        let mut black_points = vec![0; (block_columns * block_rows) as usize];
+        for block_y in 0..block_rows {
+            for block_x in 0..block_columns {
+                let (mut sum, mut count, mut min, mut max) = (0, 0, u32::MAX, 0);
+                for y in block_y * BLOCK_SIZE..((block_y + 1) * BLOCK_SIZE).min(grey.height()) {
+                    for x in block_x * BLOCK_SIZE..((block_x + 1) * BLOCK_SIZE).min(grey.width()) {
+                        let luma = grey.get_pixel(x, y).0[0] as u32;
+                        (sum, count, min, max) = (sum + luma, count + 1, min.min(luma), max.max(luma));
+                    }
+                }
+
+                let mut black_point = sum / count;
+                if max - min <= MIN_DYNAMIC_RANGE {
+                    black_point = min / 2;
+                    if block_x > 0 && block_y > 0 {
+                        let index = |x: u32, y: u32| (y * block_columns + x) as usize;
+                        let neighbours = (black_points[index(block_x, block_y - 1)]
+                            + 2 * black_points[index(block_x - 1, block_y)]
+                            + black_points[index(block_x - 1, block_y - 1)])
+                            / 4;
+                        if min < neighbours {
+                            black_point = neighbours;
+                        }
+                    }
+                }
+                black_points[(block_y * block_columns + block_x) as usize] = black_point;
+            }
+        }
+        black_points
+    }
+
+    // The threshold which best separates the image's histogram into two classes
+    fn otsu_threshold(grey: &GrayImage) -> u8 {
+        let mut histogram = [0u64; 256];
+        for pixel in grey.pixels() {
+            histogram[pixel.0[0] as usize] += 1;
+        }
+        let total = grey.pixels().len() as f64;
+        let sum = histogram.iter().enumerate().map(|(luma, &count)| luma as f64 * count as f64).sum::<f64>();
+
+        let (mut below, mut below_sum) = (0.0, 0.0);
+        let (mut best, mut best_variance) = (127, 0.0);
+        for (luma, &count) in histogram.iter().enumerate() {
+            below += count as f64;
+            below_sum += luma as f64 * count as f64;
+            if below == 0.0 || below == total {
+                continue;
+            }
+            let above = total - below;
+            let difference = below_sum / below - (sum - below_sum) / above;
+            let variance = below * above * difference * difference;
+            if variance > best_variance {
+                (best, best_variance) = (luma as u8, variance);
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    #[test]
+    fn follows_uneven_lighting() {
+        // Light paper in a shadow which deepens to the right, with a dark square at each end. The square on
+        // the left is lighter than the paper on the right.
+        let image = GrayImage::from_fn(160, 64, |x, y| {
+            let paper = 250 - x * 6 / 5;
+            let in_square = (24..40).contains(&y) && ((16..32).contains(&x) || (128..144).contains(&x));
+            Luma([if in_square { paper as u8 / 3 } else { paper as u8 }])
+        });
+        assert!(image.get_pixel(20, 30).0[0] > image.get_pixel(150, 30).0[0]);
+
+        let binary = BinaryImage::threshold(&DynamicImage::ImageLuma8(image));
+        assert_eq!((binary.get(20, 30), binary.get(135, 30)), (Some(true), Some(true)));
+        assert_eq!((binary.get(20, 50), binary.get(150, 30)), (Some(false), Some(false)));
+        assert_eq!(binary.get(160, 0), None);
+    }
+
+    #[test]
+    fn thresholds_small_images_as_a_whole() {
+        let image = GrayImage::from_fn(20, 20, |x, _| Luma([if x < 10 { 90 } else { 180 }]));
+        let binary = BinaryImage::threshold(&DynamicImage::ImageLuma8(image));
+        assert_eq!((binary.get(9, 0), binary.get(10, 0)), (Some(true), Some(false)));
+        assert_eq!(binary.inverted().get(9, 0), Some(false));
+    }
+}
@@ -0,0 +1,199 @@
+// Enough 64-bit words for the 177 modules across a version 40 symbol
+pub(crate) const LINE_WORDS: usize = 3;
+
+// A line of modules, one bit each, with module x at bit x % 64 of word x / 64. Bits beyond the end of
+// the line are always clear.
+pub(crate) type Line = [u64; LINE_WORDS];
+
+// Modules packed as bits, true for dark, kept both row by row and column by column so that penalties can
+// be scored a whole word of modules at a time in either direction
+#[derive(Clone, Debug)]
+pub(crate) struct BitMatrix {
+    width: usize,
+    height: usize,
+    rows: Vec<Line>,
+    columns: Vec<Line>,
+}
+
+impl BitMatrix {
+    pub fn new(width: usize, height: usize) -> Self {
+        assert!(width <= LINE_WORDS * 64 && height <= LINE_WORDS * 64);
+        Self {
+            width,
+            height,
+            rows: vec![[0; LINE_WORDS]; height],
+            columns: vec![[0; LINE_WORDS]; width],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, dark: bool) {
+        if dark {
+            self.rows[y][x / 64] |= 1 << (x % 64);
+            self.columns[x][y / 64] |= 1 << (y % 64);
+        } else {
+            self.rows[y][x / 64] &= !(1 << (x % 64));
+            self.columns[x][y / 64] &= !(1 << (y % 64));
+        }
+    }
+
+    pub fn rows(&self) -> &[Line] {
+        &self.rows
+    }
+
+    pub fn columns(&self) -> &[Line] {
+        &self.columns
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.rows[y][x / 64] >> (x % 64) & 1 == 1
+    }
+
+    // Makes this a copy of the other matrix with the modules in area inverted wherever a mask pattern is
+    // set. The pattern repeats down and across the symbol, so it is given as its first few rows and
+    // columns, and each line is masked a word at a time.
+    pub fn mask_from(&mut self, other: &BitMatrix, area: &BitMatrix, pattern_rows: &[Line], pattern_columns: &[Line]) {
+        self.clone_from(other);
+        for (index, (line, area_line)) in self.rows.iter_mut().zip(&area.rows).enumerate() {
+            *line = Self::xor(line, &Self::and(area_line, &pattern_rows[index % pattern_rows.len()]));
+        }
+        for (index, (line, area_line)) in self.columns.iter_mut().zip(&area.columns).enumerate() {
+            *line = Self::xor(line, &Self::and(area_line, &pattern_columns[index % pattern_columns.len()]));
+        }
+    }
+
+    // Replaces the modules in area with those of the other matrix
+    pub fn overwrite(&mut self, area: &BitMatrix, other: &BitMatrix) {
+        let lines = self.rows.iter_mut().zip(area.rows.iter().zip(&other.rows));
+        for (line, (area_line, other_line)) in lines.chain(self.columns.iter_mut().zip(area.columns.iter().zip(&other.columns))) {
+            *line = Self::or(&Self::and(line, &Self::not(area_line)), &Self::and(other_line, area_line));
+        }
+    }
+
+    pub fn count_dark(&self) -> u32 {
+        self.rows.iter().map(Self::count).sum()
+    }
+
+    pub fn count(line: &Line) -> u32 {
+        line.iter().map(|word| word.count_ones()).sum()
+    }
+
+    pub fn and(a: &Line, b: &Line) -> Line {
+        Self::zip(a, b, |a, b| a & b)
+    }
+
+    pub fn or(a: &Line, b: &Line) -> Line {
+        Self::zip(a, b, |a, b| a | b)
+    }
+
+    pub fn xor(a: &Line, b: &Line) -> Line {
+        Self::zip(a, b, |a, b| a ^ b)
+    }
+
+    pub fn not(line: &Line) -> Line {
+        line.map(|word| !word)
+    }
+
+    // Moves each module towards the start of the line, so bit x holds module x + distance
+    pub fn ahead(line: &Line, distance: u32) -> Line {
+        let mut shifted = [0; LINE_WORDS];
+        for index in 0..LINE_WORDS {
+            shifted[index] = line[index] >> distance;
+            if index + 1 < LINE_WORDS && distance > 0 {
+                shifted[index] |= line[index + 1] << (64 - distance);
+            }
+        }
+        shifted
+    }
+
+    // Moves each module towards the end of the line, so bit x holds module x - distance, and the first
+    // distance bits are clear
+    pub fn behind(line: &Line, distance: u32) -> Line {
+        let mut shifted = [0; LINE_WORDS];
+        for index in 0..LINE_WORDS {
+            shifted[index] = line[index] << distance;
+            if index > 0 && distance > 0 {
+                shifted[index] |= line[index - 1] >> (64 - distance);
+            }
+        }
+        shifted
+    }
+
+    // A line with the first length bits set
+    pub fn first(length: usize) -> Line {
+        let mut line = [0; LINE_WORDS];
+        for (index, word) in line.iter_mut().enumerate() {
+            let bits = length.saturating_sub(index * 64).min(64);
+            *word = if bits == 64 { u64::MAX } else { (1 << bits) - 1 };
+        }
+        line
+    }
+
+    fn zip(a: &Line, b: &Line, f: impl Fn(u64, u64) -> u64) -> Line {
+        let mut line = [0; LINE_WORDS];
+        for (index, word) in line.iter_mut().enumerate() {
+            *word = f(a[index], b[index]);
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_rows_and_columns_in_step() {
+        let mut matrix = BitMatrix::new(177, 177);
+        matrix.set(70, 130, true);
+        assert_eq!((matrix.rows()[130][1], matrix.columns()[70][2]), (1 << 6, 1 << 2));
+        assert_eq!(matrix.rows()[70], [0; LINE_WORDS]);
+
+        assert!(matrix.get(70, 130) && !matrix.get(130, 70));
+    }
+
+    #[test]
+    fn masks_with_repeating_pattern() {
+        let mut unmasked = BitMatrix::new(130, 130);
+        unmasked.set(0, 0, true);
+        let mut area = BitMatrix::new(130, 130);
+        (0..130).for_each(|index| area.set(index, index, true));
+        // Every other row, repeating after two rows and columns
+        let mut pattern_rows = BitMatrix::new(130, 2);
+        let mut pattern_columns = BitMatrix::new(2, 130);
+        for index in 0..130 {
+            for offset in 0..2 {
+                pattern_rows.set(index, offset, offset % 2 == 0);
+                pattern_columns.set(offset, index, index % 2 == 0);
+            }
+        }
+
+        let mut masked = BitMatrix::new(130, 130);
+        masked.mask_from(&unmasked, &area, pattern_rows.rows(), pattern_columns.columns());
+        assert!(!masked.get(0, 0) && masked.get(128, 128) && !masked.get(127, 127) && !masked.get(2, 0));
+        assert_eq!(masked.count_dark(), 64);
+        assert!(masked.columns()[128][2] == 1 && masked.columns()[127] == [0; LINE_WORDS]);
+
+        let mut corner = BitMatrix::new(130, 130);
+        corner.set(0, 0, true);
+        corner.set(1, 0, true);
+        masked.overwrite(&corner, &unmasked);
+        assert!(masked.get(0, 0) && !masked.get(1, 0));
+        assert_eq!((masked.count_dark(), masked.columns()[0][0]), (65, 1));
+    }
+
+    #[test]
+    fn shifts_across_words() {
+        let line = [1 << 63, 1, 0];
+        assert_eq!(BitMatrix::ahead(&line, 1), [1 << 62 | 1 << 63, 0, 0]);
+        assert_eq!(BitMatrix::behind(&line, 1), [0, 1 | 1 << 1, 0]);
+        assert_eq!(BitMatrix::first(70), [u64::MAX, 0x3f, 0]);
+    }
+}
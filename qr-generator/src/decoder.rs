@@ -0,0 +1,766 @@
+use bitvec::{prelude::*, vec::BitVec};
+use encoding_rs::SHIFT_JIS;
+use image::DynamicImage;
+
+use crate::{
+    binarizer::BinaryImage,
+    detector,
+    eci::CharacterSets,
+    encoder::{Encoder, EncodingModes, Fnc1Modes},
+    error_correction::{CorrectionLevels, ErrorCorrector},
+    gs1::GROUP_SEPARATOR,
+    image_builder::ImageBuilder,
+    qr_errors::{DecodingError, QRError},
+    qr_types::{FinderLocations, QRFactory},
+    segment::Segment,
+    sizer::Sizer,
+    QRSymbolTypes,
+};
+
+const ALPHANUMERIC_CHARACTERS: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+// Valid format information codewords differ in at least 7 bits, so up to 3 wrong bits can be corrected
+const MAX_FORMAT_ERRORS: usize = 3;
+
+// A symbol read back from its modules: the data it holds, and how it was encoded
+#[derive(Clone, Debug)]
+pub struct DecodedQRCode {
+    pub qr_type: QRSymbolTypes,
+    pub version: u32,
+    pub correction_level: CorrectionLevels,
+    pub mask: u8,
+    // As the encoder records them, with ECI segments but without FNC1 or Structured Append headers
+    pub segments: Vec<Segment>,
+    pub fnc1: Option<Fnc1Modes>,
+    // The zero-based symbol index, number of symbols and parity of the complete data, for a symbol which is
+    // one of a linked Structured Append series
+    pub structured_append: Option<(u8, u8, u8)>,
+}
+
+impl DecodedQRCode {
+    // The data as text. Byte segments are read in the character set of the last ECI before them, or
+    // ISO-8859-1 if there is none. In FNC1 modes, % in alphanumeric segments reads as a group separator
+    // and %% as %.
+    pub fn text(&self) -> Result<String, QRError> {
+        let mut text = String::new();
+        let mut character_set = CharacterSets::Iso8859_1;
+        for segment in &self.segments {
+            match segment {
+                Segment::ECI(designator) => {
+                    character_set = CharacterSets::from_designator(*designator).ok_or_else(|| {
+                        DecodingError::new(&format!("ECI {} isn't a supported character set", designator))
+                    })?;
+                }
+                Segment::AlphaNumeric(chars) if self.fnc1.is_some() => text.push_str(&Self::unescape_alphanumeric(chars)),
+                Segment::Numeric(chars) | Segment::AlphaNumeric(chars) | Segment::Kanji(chars) => text.push_str(chars),
+                Segment::Byte(bytes) => text.push_str(&character_set.decode(bytes)),
+            }
+        }
+        Ok(text)
+    }
+
+    fn unescape_alphanumeric(chars: &str) -> String {
+        let mut unescaped = String::new();
+        let mut chars = chars.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '%' if chars.next_if_eq(&'%').is_some() => unescaped.push('%'),
+                '%' => unescaped.push(GROUP_SEPARATOR),
+                _ => unescaped.push(c),
+            }
+        }
+        unescaped
+    }
+}
+
+// Reads a symbol from its modules, given row by row from the top left with true for dark, and without a
+// quiet zone. The symbol type and version follow from the width.
+pub fn decode(modules: &[Vec<bool>]) -> Result<DecodedQRCode, QRError> {
+    let width = modules.len();
+    if modules.iter().any(|row| row.len() != width) {
+        return Err(DecodingError::new("Symbols are square, with as many modules in each row as there are rows").into());
+    }
+    let (qr_type, version) = match width {
+        11 | 13 | 15 | 17 => (QRSymbolTypes::MicroQRCode, (width as u32 - 9) / 2),
+        21..=177 if width % 4 == 1 => (QRSymbolTypes::QRCode, (width as u32 - 17) / 4),
+        _ => return Err(DecodingError::new(&format!("No QR Code or Micro QR Code is {} modules wide", width)).into()),
+    };
+
+    let decoder = Decoder { modules, qr_type, version };
+    let (correction_level, mask) = decoder.read_format_information()?;
+    let data = decoder.read_data(correction_level, mask)?;
+    let (segments, fnc1, structured_append) = decoder.parse_segments(data)?;
+
+    Ok(DecodedQRCode {
+        qr_type,
+        version,
+        correction_level,
+        mask,
+        segments,
+        fnc1,
+        structured_append,
+    })
+}
+
+struct Decoder<'a> {
+    modules: &'a [Vec<bool>],
+    qr_type: QRSymbolTypes,
+    version: u32,
+}
+
+impl<'a> Decoder<'a> {
+    // Finds the correction level and mask whose format information is closest to what the symbol holds.
+    // QR Codes carry two copies, either of which may be read.
+    fn read_format_information(&self) -> Result<(CorrectionLevels, u8), DecodingError> {
+        let copies = match self.qr_type {
+            QRSymbolTypes::QRCode => vec![
+                vec![FinderLocations::TopLeft],
+                vec![FinderLocations::TopRight, FinderLocations::BottomLeft],
+            ],
+            QRSymbolTypes::MicroQRCode => vec![vec![FinderLocations::TopLeft]],
+        };
+        let mask_count = QRFactory::build_code(self.qr_type, self.version).mask_functions().len();
+        let no_message = vec![];
+
+        let mut closest = None;
+        for correction_level in self.correction_levels() {
+            let image_builder = ImageBuilder::new(self.qr_type, self.version, &no_message, correction_level, None);
+            for mask in 0..mask_count {
+                for copy in &copies {
+                    let errors = image_builder
+                        .format_cells(ImageBuilder::mask_bits(mask, mask_count), copy.clone())
+                        .into_iter()
+                        .filter(|&(x, y, dark)| self.is_dark(x, y) != dark)
+                        .count();
+                    if closest.is_none_or(|(fewest, _, _)| errors < fewest) {
+                        closest = Some((errors, correction_level, mask as u8));
+                    }
+                }
+            }
+        }
+
+        match closest {
+            Some((errors, correction_level, mask)) if errors <= MAX_FORMAT_ERRORS => Ok((correction_level, mask)),
+            _ => Err(DecodingError::new("The format information is too damaged to read")),
+        }
+    }
+
+    fn correction_levels(&self) -> Vec<CorrectionLevels> {
+        [
+            CorrectionLevels::DetectionOnly,
+            CorrectionLevels::L,
+            CorrectionLevels::M,
+            CorrectionLevels::Q,
+            CorrectionLevels::H,
+        ]
+        .into_iter()
+        .filter(|level| Sizer::validate_version(&self.qr_type, self.version, level).is_ok())
+        .collect()
+    }
+
+    // Unmasks the message cells, reads them in placement order and corrects each block. Returns the
+    // data bits; in M1 and M3 codes the final data codeword is only 4 bits long.
+    fn read_data(&self, correction_level: CorrectionLevels, mask: u8) -> Result<BitVec<u8, Msb0>, QRError> {
+        let no_message = vec![];
+        let cells = ImageBuilder::new(self.qr_type, self.version, &no_message, correction_level, None).message_cells();
+        let mask_function = &QRFactory::build_code(self.qr_type, self.version).mask_functions()[mask as usize];
+        let mut bits = cells.into_iter().map(|(x, y)| self.is_dark(x, y) != mask_function(x, y));
+
+        let shape = Sizer::error_correction_shape(&self.qr_type, self.version, &correction_level);
+        let codeword_count = shape.iter().map(|(codewords, _, repeat)| codewords * repeat).sum::<usize>();
+        let data_codeword_count = Sizer::data_codeword_capacity(&self.qr_type, self.version, &correction_level);
+        let short_codeword = Sizer::has_short_final_codeword(&self.qr_type, self.version).then_some(data_codeword_count - 1);
+        let message = (0..codeword_count)
+            .map(|index| {
+                let bit_count = if Some(index) == short_codeword { 4 } else { 8 };
+                let codeword = bits.by_ref().take(bit_count).fold(0u8, |codeword, bit| codeword << 1 | bit as u8);
+                codeword << (8 - bit_count)
+            })
+            .collect::<Vec<u8>>();
+
+        let mut error_corrector = ErrorCorrector::from(&shape);
+        error_corrector.deinterleave(&message)?;
+        error_corrector.correct_errors()?;
+
+        let mut data = BitVec::<u8, Msb0>::from_vec(error_corrector.data_codewords());
+        data.truncate(Sizer::data_bit_capacity(&self.qr_type, self.version, &correction_level));
+        Ok(data)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn parse_segments(
+        &self,
+        data: BitVec<u8, Msb0>,
+    ) -> Result<(Vec<Segment>, Option<Fnc1Modes>, Option<(u8, u8, u8)>), DecodingError> {
+        let mut reader = DataReader { data, position: 0 };
+        let mut segments = vec![];
+        let mut fnc1 = None;
+        let mut structured_append = None;
+
+        // The terminator is all zeros, and is cut short when the data almost fills the symbol
+        let terminator_length = match self.qr_type {
+            QRSymbolTypes::MicroQRCode => 2 * self.version as usize + 1,
+            QRSymbolTypes::QRCode => 4,
+        };
+        while reader.remaining() > 0 && reader.peek(terminator_length.min(reader.remaining())) != 0 {
+            let mode = match self.qr_type {
+                QRSymbolTypes::QRCode => match reader.read(4)? {
+                    0b0001 => EncodingModes::Numeric,
+                    0b0010 => EncodingModes::AlphaNumeric,
+                    0b0100 => EncodingModes::Byte,
+                    0b1000 => EncodingModes::Kanji,
+                    0b0111 => {
+                        segments.push(Segment::ECI(reader.read_eci_designator()?));
+                        continue;
+                    }
+                    0b0011 => {
+                        let index = reader.read(4)? as u8;
+                        let total = reader.read(4)? as u8 + 1;
+                        structured_append = Some((index, total, reader.read(8)? as u8));
+                        continue;
+                    }
+                    0b0101 => {
+                        fnc1 = Some(Fnc1Modes::First);
+                        continue;
+                    }
+                    0b1001 => {
+                        fnc1 = Some(Fnc1Modes::Second(reader.read(8)? as u8));
+                        continue;
+                    }
+                    indicator => return Err(DecodingError::new(&format!("Unknown mode indicator {:04b}", indicator))),
+                },
+                QRSymbolTypes::MicroQRCode => match reader.read(Encoder::mode_indicator_bits(&self.qr_type, self.version))? {
+                    0 => EncodingModes::Numeric,
+                    1 => EncodingModes::AlphaNumeric,
+                    2 => EncodingModes::Byte,
+                    3 => EncodingModes::Kanji,
+                    indicator => return Err(DecodingError::new(&format!("Unknown mode indicator {:03b}", indicator))),
+                },
+            };
+
+            let char_count = reader.read(Encoder::character_count_bits(&self.qr_type, self.version, mode))? as usize;
+            segments.push(match mode {
+                EncodingModes::Numeric => Segment::Numeric(reader.read_numeric(char_count)?),
+                EncodingModes::AlphaNumeric => Segment::AlphaNumeric(reader.read_alphanumeric(char_count)?),
+                EncodingModes::Byte => Segment::Byte((0..char_count).map(|_| reader.read(8).map(|byte| byte as u8)).collect::<Result<_, _>>()?),
+                EncodingModes::Kanji => Segment::Kanji(reader.read_kanji(char_count)?),
+                EncodingModes::Dynamic => unreachable!(),
+            });
+        }
+
+        Ok((segments, fnc1, structured_append))
+    }
+
+    fn is_dark(&self, x: u32, y: u32) -> bool {
+        self.modules[y as usize][x as usize]
+    }
+}
+
+// Reads the data bitstream a field at a time
+struct DataReader {
+    data: BitVec<u8, Msb0>,
+    position: usize,
+}
+
+impl DataReader {
+    fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    fn peek(&self, bit_count: usize) -> u32 {
+        if bit_count == 0 {
+            0
+        } else {
+            self.data[self.position..self.position + bit_count].load_be()
+        }
+    }
+
+    fn read(&mut self, bit_count: usize) -> Result<u32, DecodingError> {
+        if bit_count > self.remaining() {
+            return Err(DecodingError::new("The data ends part way through a segment"));
+        }
+        let value = self.peek(bit_count);
+        self.position += bit_count;
+        Ok(value)
+    }
+
+    // The designator follows in 1, 2 or 3 bytes, marked by a leading 0, 10 or 110
+    fn read_eci_designator(&mut self) -> Result<u32, DecodingError> {
+        for value_bits in [7, 14, 21] {
+            if self.read(1)? == 0 {
+                return self.read(value_bits);
+            }
+        }
+        Err(DecodingError::new("ECI designators are at most 3 bytes long"))
+    }
+
+    // Digits come 3 to 10 bits, with a final 1 or 2 in 4 or 7 bits
+    fn read_numeric(&mut self, char_count: usize) -> Result<String, DecodingError> {
+        let mut digits = String::with_capacity(char_count);
+        let mut remaining = char_count;
+        while remaining > 0 {
+            let group = remaining.min(3);
+            let value = self.read([0, 4, 7, 10][group])?;
+            if value >= 10u32.pow(group as u32) {
+                return Err(DecodingError::new("Numeric data holds a value too large for its digits"));
+            }
+            digits.push_str(&format!("{:0width$}", value, width = group));
+            remaining -= group;
+        }
+        Ok(digits)
+    }
+
+    // Characters come in pairs in 11 bits, with a final single character in 6 bits
+    fn read_alphanumeric(&mut self, char_count: usize) -> Result<String, DecodingError> {
+        let mut chars = String::with_capacity(char_count);
+        let invalid = || DecodingError::new("Alphanumeric data holds a value with no character");
+        for _ in 0..char_count / 2 {
+            let value = self.read(11)? as usize;
+            if value >= 45 * 45 {
+                return Err(invalid());
+            }
+            chars.push(ALPHANUMERIC_CHARACTERS[value / 45] as char);
+            chars.push(ALPHANUMERIC_CHARACTERS[value % 45] as char);
+        }
+        if char_count % 2 == 1 {
+            let value = self.read(6)? as usize;
+            chars.push(*ALPHANUMERIC_CHARACTERS.get(value).ok_or_else(invalid)? as char);
+        }
+        Ok(chars)
+    }
+
+    // Each character is 13 bits, compacted from the double-byte Shift-JIS ranges 0x8140-0x9FFC and
+    // 0xE040-0xEBBF
+    fn read_kanji(&mut self, char_count: usize) -> Result<String, DecodingError> {
+        let mut kanji = String::with_capacity(char_count * 3);
+        for _ in 0..char_count {
+            let value = self.read(13)?;
+            let offset = ((value / 0xC0) << 8) | (value % 0xC0);
+            let shift_jis = if offset < 0x1F00 { offset + 0x8140 } else { offset + 0xC140 };
+            let bytes = [(shift_jis >> 8) as u8, shift_jis as u8];
+            let decoded = SHIFT_JIS
+                .decode_without_bom_handling_and_without_replacement(&bytes)
+                .ok_or_else(|| DecodingError::new("Kanji data holds a value with no character"))?;
+            kanji.push_str(&decoded);
+        }
+        Ok(kanji)
+    }
+}
+
+// Finds and reads a QR Code in an image, such as a photo or a scan. Symbols may be rotated, seen at an
+// angle, unevenly lit or printed light on dark. Micro QR Codes aren't found in images.
+pub fn decode_image(image: &DynamicImage) -> Result<DecodedQRCode, QRError> {
+    let image = BinaryImage::threshold(image);
+    let mut error = DecodingError::new("No QR Code found in the image").into();
+    for image in [image.clone(), image.inverted()] {
+        for location in detector::locate_symbols(&image) {
+            for dimension in location.dimensions() {
+                let Some(modules) = location.sample(&image, dimension) else {
+                    continue;
+                };
+                match decode(&modules) {
+                    Ok(decoded) => return Ok(decoded),
+                    Err(e) => error = e,
+                }
+            }
+        }
+    }
+    Err(error)
+}
+
+// Reads a QR Code from an image file, in any format the image crate opens, such as PNG or JPEG
+pub fn decode_image_file(path: &str) -> Result<DecodedQRCode, QRError> {
+    decode_image(&image::open(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{perspective_transform::PerspectiveTransform, Colour, OutputFormats, QRCode, QRGenerator};
+    use image::{GrayImage, Luma};
+
+    fn modules(qr_code: &QRCode) -> Vec<Vec<bool>> {
+        (0..qr_code.width()).map(|y| (0..qr_code.width()).map(|x| qr_code.is_dark(x, y)).collect()).collect()
+    }
+
+    fn round_trip(generator: &mut QRGenerator, data: &str) -> (QRCode, DecodedQRCode) {
+        let qr_code = generator.make_qr_code(data.to_string()).unwrap();
+        let decoded = decode(&modules(&qr_code)).unwrap();
+        assert_eq!(decoded.text().unwrap(), data);
+        assert_eq!(decoded.segments, qr_code.segments);
+        assert_eq!(
+            (decoded.qr_type, decoded.version, decoded.correction_level, decoded.mask),
+            (qr_code.qr_type, qr_code.version, qr_code.correction_level, qr_code.mask)
+        );
+        (qr_code, decoded)
+    }
+
+    // Each row's modules as hex digits, dark modules set, most significant first
+    fn from_hex(rows: &[&str], width: usize) -> Vec<Vec<bool>> {
+        rows.iter()
+            .map(|row| {
+                let digits = row.chars().map(|digit| digit.to_digit(16).unwrap());
+                digits.flat_map(|digit| (0..4).rev().map(move |bit| digit & (1 << bit) != 0)).take(width).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_every_mode() {
+        let (_, decoded) = round_trip(&mut QRGenerator::default(), "Hello, World! 0123456789012 点茗 HELLO WORLD");
+        let modes = decoded.segments.iter().map(|segment| segment.mode().unwrap()).collect::<Vec<_>>();
+        assert!([EncodingModes::Numeric, EncodingModes::AlphaNumeric, EncodingModes::Byte, EncodingModes::Kanji]
+            .iter()
+            .all(|mode| modes.contains(mode)));
+    }
+
+    #[test]
+    fn reads_every_correction_level_and_mask() {
+        for correction_level in [CorrectionLevels::L, CorrectionLevels::M, CorrectionLevels::Q, CorrectionLevels::H] {
+            for mask in 0..8 {
+                let mut generator = QRGenerator::default();
+                generator.options.correction_level = Some(correction_level);
+                generator.options.mask = Some(mask);
+                round_trip(&mut generator, "https://example.com/");
+            }
+        }
+    }
+
+    #[test]
+    fn reads_larger_versions_with_several_blocks() {
+        let mut generator = QRGenerator::default();
+        generator.options.version = Some(7);
+        generator.options.correction_level = Some(CorrectionLevels::H);
+        round_trip(&mut generator, "Version 7-H has five blocks, of two lengths");
+    }
+
+    #[test]
+    fn reads_micro_qr_codes() {
+        for (version, correction_level, data) in [
+            (1, CorrectionLevels::DetectionOnly, "12345"),
+            (2, CorrectionLevels::L, "AC-42"),
+            (3, CorrectionLevels::M, "Micro"),
+            (4, CorrectionLevels::Q, "点茗"),
+        ] {
+            let mut generator = QRGenerator::default();
+            generator.options.qr_type = Some(QRSymbolTypes::MicroQRCode);
+            generator.options.version = Some(version);
+            generator.options.correction_level = Some(correction_level);
+            round_trip(&mut generator, data);
+        }
+    }
+
+    #[test]
+    fn reads_eci_and_fnc1() {
+        let (_, decoded) = round_trip(&mut QRGenerator::default(), "€100");
+        assert_eq!(decoded.segments[0], Segment::ECI(26));
+
+        let mut generator = QRGenerator::default();
+        generator.options.fnc1 = Some(Fnc1Modes::First);
+        let (_, decoded) = round_trip(&mut generator, "10ABC%1\u{1D}2112345");
+        assert_eq!(decoded.fnc1, Some(Fnc1Modes::First));
+
+        generator.options.fnc1 = Some(Fnc1Modes::Second(37));
+        let (_, decoded) = round_trip(&mut generator, "AB-12");
+        assert_eq!(decoded.fnc1, Some(Fnc1Modes::Second(37)));
+    }
+
+    #[test]
+    fn reads_structured_append_header() {
+        let data = "Split across linked symbols. ".repeat(3);
+        let mut generator = QRGenerator::default();
+        generator.options.version = Some(1);
+        generator.options.correction_level = Some(CorrectionLevels::L);
+        let qr_codes = generator.make_structured_append_qr_codes(data.clone()).unwrap();
+
+        let decoded = qr_codes.iter().map(|qr_code| decode(&modules(qr_code)).unwrap()).collect::<Vec<_>>();
+        let text = decoded.iter().map(|decoded| decoded.text().unwrap()).collect::<String>();
+        assert_eq!(text, data);
+        let (index, total, _) = decoded[1].structured_append.unwrap();
+        assert_eq!((index, total as usize), (1, qr_codes.len()));
+    }
+
+    #[test]
+    fn corrects_damaged_modules() {
+        let mut generator = QRGenerator::default();
+        generator.options.version = Some(2);
+        generator.options.correction_level = Some(CorrectionLevels::H);
+        let qr_code = generator.make_qr_code("Damaged".to_string()).unwrap();
+        let mut damaged = modules(&qr_code);
+
+        // Version 2-H has a single block which can correct 14 codewords. Damage most of the bottom four
+        // rows, and 2 bits of one copy of the format information and the whole of the other.
+        let mut damaged_codewords = vec![];
+        for (y, row) in damaged.iter_mut().enumerate().skip(21) {
+            for (x, module) in row.iter_mut().enumerate().skip(9) {
+                *module = !*module;
+                let role = qr_code.matrix.role(x, y);
+                if !damaged_codewords.contains(&role) {
+                    damaged_codewords.push(role);
+                }
+            }
+        }
+        assert!((10..=14).contains(&damaged_codewords.len()));
+        damaged[8][0] = !damaged[8][0];
+        damaged[8][2] = !damaged[8][2];
+        for module in &mut damaged[8][17..] {
+            *module = !*module;
+        }
+        let decoded = decode(&damaged).unwrap();
+        assert_eq!(decoded.text().unwrap(), "Damaged");
+        assert_eq!(decoded.correction_level, CorrectionLevels::H);
+    }
+
+    #[test]
+    fn refuses_unreadable_symbols() {
+        assert!(decode(&vec![vec![false; 22]; 22]).is_err());
+        assert!(decode(&vec![vec![false; 21]; 20]).is_err());
+        // All light, so no format information close enough to any valid codeword
+        let error = decode(&vec![vec![false; 21]; 21]).unwrap_err();
+        assert_eq!(error.to_string(), "The format information is too damaged to read");
+
+        // Far more damaged codewords than version 2-H can correct, away from the format information
+        let mut generator = QRGenerator::default();
+        generator.options.version = Some(2);
+        generator.options.correction_level = Some(CorrectionLevels::H);
+        let mut damaged = modules(&generator.make_qr_code("Damaged".to_string()).unwrap());
+        for row in damaged.iter_mut().skip(9) {
+            for module in row.iter_mut().skip(9) {
+                *module = !*module;
+            }
+        }
+        let error = decode(&damaged).unwrap_err();
+        assert_eq!(error.to_string(), "The block has too many errors to correct");
+    }
+
+    #[test]
+    fn unescapes_fnc1_alphanumeric() {
+        assert_eq!(DecodedQRCode::unescape_alphanumeric("A%%B%C"), "A%B\u{1D}C");
+    }
+
+    fn render(generator: &mut QRGenerator, data: &str) -> DynamicImage {
+        let qr_code = generator.make_qr_code(data.to_string()).unwrap();
+        image::load_from_memory(&generator.render(&qr_code, OutputFormats::Png).unwrap()).unwrap()
+    }
+
+    // The image as a camera might see it on a grey desk: with its corners at the given places, shaded darker
+    // towards the bottom right and saved as a JPEG
+    fn photograph(image: &DynamicImage, corners: [(f64, f64); 4], size: u32) -> DynamicImage {
+        let image = image.to_luma8();
+        let (width, height) = (image.width() as f64, image.height() as f64);
+        let to_image = PerspectiveTransform::quadrilateral_to_quadrilateral(
+            corners,
+            [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)],
+        );
+        let photo = GrayImage::from_fn(size, size, |x, y| {
+            let (u, v) = to_image.transform(x as f64 + 0.5, y as f64 + 0.5);
+            let luma = if (0.0..width).contains(&u) && (0.0..height).contains(&v) {
+                image.get_pixel(u as u32, v as u32).0[0] as f64
+            } else {
+                140.0
+            };
+            Luma([(luma * (1.0 - 0.5 * (x + y) as f64 / (2 * size) as f64)) as u8])
+        });
+        let mut jpeg = vec![];
+        DynamicImage::ImageLuma8(photo)
+            .write_to(&mut std::io::Cursor::new(&mut jpeg), image::ImageOutputFormat::Jpeg(70))
+            .unwrap();
+        image::load_from_memory(&jpeg).unwrap()
+    }
+
+    #[test]
+    fn reads_rendered_images() {
+        let image = render(&mut QRGenerator::default(), "https://example.com/");
+        let decoded = decode_image(&image).unwrap();
+        assert_eq!((decoded.text().unwrap(), decoded.version), ("https://example.com/".to_string(), 2));
+
+        // Light on dark
+        let mut generator = QRGenerator::default();
+        generator.options.foreground = Some(Colour::WHITE);
+        generator.options.background = Some(Colour::rgb(0, 0, 40));
+        generator.options.version = Some(4);
+        assert_eq!(decode_image(&render(&mut generator, "Light on dark")).unwrap().text().unwrap(), "Light on dark");
+    }
+
+    #[test]
+    fn reads_photographed_symbols() {
+        let mut generator = QRGenerator::default();
+        generator.options.version = Some(5);
+        generator.options.pixels_per_module = Some(6);
+        let image = render(&mut generator, "Seen at an angle");
+        // Turned about 30 degrees and tilted away at the top
+        let photo = photograph(&image, [(200.0, 60.0), (450.0, 170.0), (370.0, 470.0), (70.0, 330.0)], 520);
+        assert_eq!(decode_image(&photo).unwrap().text().unwrap(), "Seen at an angle");
+
+        // Upside down, small and with several blocks, so the alignment patterns matter
+        let mut generator = QRGenerator::default();
+        generator.options.version = Some(10);
+        generator.options.pixels_per_module = Some(5);
+        let image = render(&mut generator, "Version 10 upside down");
+        let photo = photograph(&image, [(420.0, 400.0), (60.0, 410.0), (80.0, 60.0), (410.0, 80.0)], 480);
+        assert_eq!(decode_image(&photo).unwrap().text().unwrap(), "Version 10 upside down");
+    }
+
+    #[test]
+    fn reports_images_without_symbols() {
+        let blank = DynamicImage::ImageLuma8(GrayImage::from_pixel(200, 200, Luma([255])));
+        assert_eq!(decode_image(&blank).unwrap_err().to_string(), "No QR Code found in the image");
+        assert!(decode_image_file("no-such-image.png").is_err());
+    }
+
+    #[test]
+    fn reads_symbols_from_another_encoder() {
+        // Both made by the qrcode crate rather than this one, so the decoder can't share an encoder bug
+        let decoded = decode(&from_hex(&NUMERIC_1_M, 21)).unwrap();
+        assert_eq!(decoded.text().unwrap(), "01234567");
+        assert_eq!((decoded.version, decoded.correction_level), (1, CorrectionLevels::M));
+
+        // Version 25 checks the alignment pattern positions from the table in ISO/IEC 18004
+        let decoded = decode(&from_hex(&VERSION_25_L, 117)).unwrap();
+        assert_eq!(decoded.text().unwrap(), "ISO/IEC 18004 version 25");
+        assert_eq!((decoded.version, decoded.correction_level), (25, CorrectionLevels::L));
+    }
+
+    const NUMERIC_1_M: [&str; 21] = [
+        "fe5bf8",
+        "827a08",
+        "ba82e8",
+        "bac2e8",
+        "babae8",
+        "828a08",
+        "feabf8",
+        "009800",
+        "be4be0",
+        "15a960",
+        "2354f8",
+        "0841e0",
+        "1f9480",
+        "00be60",
+        "fe6b00",
+        "82be28",
+        "ba8960",
+        "bac900",
+        "bab4a0",
+        "8201b0",
+        "fef4a0",
+    ];
+
+    const VERSION_25_L: [&str; 117] = [
+        "fefe3edfcd84dfca04d84a04d8a3f8",
+        "82e0eb029959829f59849f59978a08",
+        "bada118031db0035db0435db19fae8",
+        "baa9c4f500ae7560ae7560a80a82e8",
+        "ba6e3edbea04dffa04dfbe02a022e8",
+        "82e8eb063e49838f5982e35fe4b208",
+        "feaaaaaaaaaaaaaaaaaaaaaaaaabf8",
+        "004a13863443018ddb0063df442000",
+        "ce463d0bfec98bff51ecbf51ac8978",
+        "70e63edfab9cdfca04b9ca04d9df28",
+        "db8690ed01a67d61a61b60a67a7580",
+        "10c21598745b00345b4435db25a0d0",
+        "6bcefb129ed18c9ed18c9f37ed0a78",
+        "20e7bec7cb84d9cb84d9ca62b85f28",
+        "cb9674fb61b77b60a67b60c01b7580",
+        "70ca938634428435db04359f4420d0",
+        "5bc6bd0cfec80a9f57ec9f57ec9278",
+        "10e7dedbab9d5fca02b9ca02b9cf28",
+        "db86c0ed01b67d61b01b70a01b7580",
+        "514385987443003447442ddf4420d0",
+        "6a4cef12fec9ecfec9ec8737ec8a60",
+        "21e624c7ab9cb9ab9cb9d262b9de28",
+        "ca9876eb01b70b01a61b61c01b7500",
+        "704e8d9e7442dc745b44341f4431d0",
+        "5bc6b914fec8149eb7ec98b7ec9378",
+        "11e7c8c3ab9d41cbe2b9cde2b9c738",
+        "db1ad66b01b77b61d11b76c01b6d98",
+        "69cd841e7442843406c4299f4420c8",
+        "67249994fece6cfece6c8737ecea60",
+        "296630c3ab9b39ab9b39d262b9b828",
+        "df98d76be1b10bf9b01b3fd11b1f80",
+        "68cf2d9a3446dc8c474422074478d0",
+        "6aa67976bec875a8afeceaaeec8af8",
+        "28a6e8222b9d218dfab9a3fab9c8d8",
+        "df9a968be1b70affd10b3fc01b6ff8",
+        "652d245f1442dc9006dc501f442228",
+        "67a478179eae7496ae7492b7eaf0a0",
+        "29e6f1c4ebfb2063fb2181e2bfbfb8",
+        "d618876851d10bc9d11b4bd1051898",
+        "65492d9e0c06dc0406c47606c065c8",
+        "a620717606ce7508ae6cf6ae6cb760",
+        "6522e025739b21fdfb39e1fb319a58",
+        "969a8689c1b10a57d10a2bd01b3ff8",
+        "a52f2c5f1446dc9006ddd607446228",
+        "e7c0707798ae7490ae7556af8af0a0",
+        "e902e1a4edfb2065fb2061fadfbf98",
+        "177a868856d10b4fd10b2bd16c99f8",
+        "644f2d5e0986dc9006dc5606996048",
+        "abc07076072e7490ae72d6ae7676c0",
+        "6d42e125727b2065fb27e1fb24bfd8",
+        "97128689d1d10b4fd10c2bd11d1ff8",
+        "a45f2d5e0c06dc9006d9d606c66228",
+        "efc8747600ae7490ae7556a80af0a0",
+        "e55ae52575fb2065fb2061fd5fbfd8",
+        "070a8089d6c10b4fd10a2bd76c1ff8",
+        "444f2d5e099edc9006ddd60299e228",
+        "9fd87273e13674f8ae133eae737fa0",
+        "08d2e1263463218dfb4663fb4638d8",
+        "8a82869ab6d10baed16c2bd10d0af8",
+        "f8cfad422986dd898699a2069878a8",
+        "5fd0f46be12e72f92e733ec812efa0",
+        "0dca253c147b27947b26779d47b0d8",
+        "8a8a208c96c00d9fd10c7fb76c1878",
+        "554e6d5bc99f58c806d98a4299efa8",
+        "6bd8f271e137f468a81328a8136da0",
+        "1dd361241462a195fd4677fd4630d8",
+        "8a833e9cf6c10b9ec76c7fd76c1878",
+        "754f3d45a99edcc99a998a0299efb0",
+        "5bd1e66f81361269361328c8136db8",
+        "0dcdb73a546347946346779d4628d8",
+        "8a8a349896c07d9ed16c7eb76c1078",
+        "55427343c99f00c986998bc299f7a8",
+        "6bdae269e137ea6948132948136da0",
+        "1dd96b3c1462bf941d46761d4630c8",
+        "9a8b3b9cf6c00d9ea66c7fb76c1860",
+        "454334c5a99f58c9db198a4299efb0",
+        "7b99db6f81319269319338c8131db8",
+        "3cfdb33a5464c79464c66f9d4648d8",
+        "9b8b9318f6c67dfec76c66a66c6078",
+        "4463d4c5a99b00a99a9993da19b7a8",
+        "7b5a020f81378a0f50132951136ca0",
+        "3d38cb5a5462dfd205467604c631a8",
+        "9fabfa7ff6c07df8a67c7eb76c1f80",
+        "4883f502299f018ddb01e3c299e8d0",
+        "7a981beaa1518ba9518b2b48151af8",
+        "38f8d2ba3404de8c04de221d4058c8",
+        "9f8df31fe6a67dfea66c7ea66a6fe0",
+        "48e5f4c6f1db0199db19d9db15b1b0",
+        "fb5e1a0a59318bb7519313518b0038",
+        "783cdb598c64df6a04c67e04d60da8",
+        "5a2ff27db6c67c68a67d14a76c7f00",
+        "0407f506e99b007ddb0027da99f050",
+        "bebe1b8a47518b57518aad50750778",
+        "7c9cdad99204de9a04df9805204a28",
+        "1aaff27da1a67db0a67c70a61af880",
+        "00e7f506f45b01e5db01c1db44b5f0",
+        "ff1e1b8a58d18bcf518d0b518f8058",
+        "7c3cdad88d84df0a04d83e04ddcda8",
+        "5a07f37c26a67c28a67b74a66c7f00",
+        "042ff48771db00fddb0441db1bb050",
+        "be861e0bdf518bd7518a8b57f50778",
+        "7ca4db598a04df1a04dfbe02a04a28",
+        "3b0ff47da1b67c30a67d74a01b7880",
+        "31b7f506f44300e5db0041df4435d0",
+        "ff0e1d8a5ec98bcf51ec8b51ec8178",
+        "fda4dad88b9cdf1a04b9be0499ca28",
+        "af0ef76c21a67c31a61b74a61a7c80",
+        "25aff49f745b00e45b4441db25b1d0",
+        "6317be13fed18dfed18cbf37ed0ff8",
+        "00a4bb422b84d98b84d9a262b858a8",
+        "fe0ff57aa1b77aa8a67b2ac01b7a80",
+        "82b7d4863442858ddb04639f4438d0",
+        "ba8e5c0bfec80bff57ecbf57ec8ff8",
+        "ba25bb5fcb9d5fca02b9ca02b9df38",
+        "ba0fdf6d61b67d61b01b60a01b7580",
+        "82af4c9834430034474435df4420c8",
+        "fe9422129ec9ec9ec9ec9f37ec8a78",
+    ];
+}
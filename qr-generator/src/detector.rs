@@ -0,0 +1,421 @@
+use crate::{binarizer::BinaryImage, perspective_transform::PerspectiveTransform, qr_types::QRFactory, QRSymbolTypes};
+
+// Through its centre in any direction, a finder pattern is dark-light-dark-light-dark in these proportions
+const FINDER_RATIOS: [f64; 5] = [1.0, 1.0, 3.0, 1.0, 1.0];
+// An alignment pattern's centre module, with the light ring either side of it
+const ALIGNMENT_RATIOS: [f64; 3] = [1.0, 1.0, 1.0];
+// Row scans skip enough rows to cross the centre of a finder pattern in a version 40 symbol filling the
+// image three times
+const MAX_MODULES: u32 = 177;
+// How far from the expected place to look for the alignment pattern, in modules
+const ALIGNMENT_ALLOWANCES: [f64; 4] = [4.0, 8.0, 16.0, 32.0];
+
+// A run of pixels of one colour along a row
+#[derive(Clone, Copy)]
+struct Run {
+    dark: bool,
+    start: i64,
+    length: u32,
+}
+
+// The centre of a finder pattern, and how many row scans found it
+#[derive(Clone, Copy, Debug)]
+struct FinderPattern {
+    x: f64,
+    y: f64,
+    module_size: f64,
+    count: u32,
+}
+
+impl FinderPattern {
+    fn distance(&self, other: &FinderPattern) -> f64 {
+        (self.x - other.x).hypot(self.y - other.y)
+    }
+}
+
+// Three finder patterns which look like the corners of one symbol, and the size of the symbol they suggest
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct SymbolLocation {
+    top_left: FinderPattern,
+    top_right: FinderPattern,
+    bottom_left: FinderPattern,
+    module_size: f64,
+    dimension: u32,
+}
+
+impl SymbolLocation {
+    // The estimated width in modules, then the widths a version either side, in case the estimate is off
+    pub fn dimensions(&self) -> Vec<u32> {
+        [self.dimension, self.dimension + 4, self.dimension.wrapping_sub(4)]
+            .into_iter()
+            .filter(|dimension| (21..=177).contains(dimension))
+            .collect()
+    }
+
+    // Reads the colour of each module of a symbol of the given width, row by row from the top left.
+    // Symbols from version 2 have an alignment pattern near the bottom right corner which pins down the
+    // perspective; otherwise the bottom right is taken to complete a parallelogram with the finders.
+    pub fn sample(&self, image: &BinaryImage, dimension: u32) -> Option<Vec<Vec<bool>>> {
+        let (top_left, top_right, bottom_left) = (self.top_left, self.top_right, self.bottom_left);
+        let size = dimension as f64;
+        let bottom_right = (top_right.x - top_left.x + bottom_left.x, top_right.y - top_left.y + bottom_left.y);
+
+        let mut transform = self.transform(size, (size - 3.5, bottom_right));
+        if dimension > 21 {
+            // The alignment pattern's centre is three modules in from the finder patterns' outer centres
+            let ratio = 1.0 - 3.0 / (size - 7.0);
+            let expected = (
+                top_left.x + ratio * (bottom_right.0 - top_left.x),
+                top_left.y + ratio * (bottom_right.1 - top_left.y),
+            );
+            // Data can look like an alignment pattern's middle, and from version 7 the candidates may include
+            // the next alignment pattern along, so each is checked by sampling every alignment pattern
+            // through the transform it gives
+            if let Some(found) = ALIGNMENT_ALLOWANCES.iter().find_map(|allowance| {
+                find_alignment_patterns(image, expected, self.module_size, allowance * self.module_size)
+                    .into_iter()
+                    .map(|found| self.transform(size, (size - 6.5, found)))
+                    .find(|transform| Self::has_alignment_patterns(image, transform, dimension))
+            }) {
+                transform = found;
+            }
+        }
+
+        (0..dimension)
+            .map(|y| (0..dimension).map(|x| Self::sample_module(image, &transform, x as f64, y as f64)).collect())
+            .collect()
+    }
+
+    // Maps the module grid onto the image, through the finder patterns' centres and the given point
+    // towards the bottom right, which is that many modules from the top left both ways
+    fn transform(&self, size: f64, (modules, corner): (f64, (f64, f64))) -> PerspectiveTransform {
+        let (top_left, top_right, bottom_left) = (self.top_left, self.top_right, self.bottom_left);
+        PerspectiveTransform::quadrilateral_to_quadrilateral(
+            [(3.5, 3.5), (size - 3.5, 3.5), (modules, modules), (3.5, size - 3.5)],
+            [(top_left.x, top_left.y), (top_right.x, top_right.y), corner, (bottom_left.x, bottom_left.y)],
+        )
+    }
+
+    fn sample_module(image: &BinaryImage, transform: &PerspectiveTransform, x: f64, y: f64) -> Option<bool> {
+        let (x, y) = transform.transform(x + 0.5, y + 0.5);
+        image.get(x.floor() as i64, y.floor() as i64)
+    }
+
+    // Whether at least nine in ten of the alignment patterns' modules sample as they should
+    fn has_alignment_patterns(image: &BinaryImage, transform: &PerspectiveTransform, dimension: u32) -> bool {
+        let locations = QRFactory::build_code(QRSymbolTypes::QRCode, (dimension - 17) / 4).alignment_locations();
+        let matching = locations
+            .iter()
+            .flat_map(|&(x, y)| (-2i32..=2).flat_map(move |dy| (-2i32..=2).map(move |dx| (x, y, dx, dy))))
+            .filter(|&(x, y, dx, dy)| {
+                let dark = dx.abs().max(dy.abs()) != 1;
+                let (x, y) = (x as f64 + dx as f64, y as f64 + dy as f64);
+                Self::sample_module(image, transform, x, y) == Some(dark)
+            })
+            .count();
+        matching * 10 >= locations.len() * 25 * 9
+    }
+}
+
+// Finds sets of three finder patterns which could be a QR Code, most likely first. Micro QR Codes, with
+// only one finder pattern, aren't found.
+pub(crate) fn locate_symbols(image: &BinaryImage) -> Vec<SymbolLocation> {
+    let patterns = find_finder_patterns(image);
+    // Patterns found by more than one row scan are much less likely to be chance
+    let confirmed = patterns.iter().copied().filter(|pattern| pattern.count > 1).collect::<Vec<_>>();
+    let patterns = if confirmed.len() >= 3 { confirmed } else { patterns };
+
+    let mut locations = vec![];
+    for (i, a) in patterns.iter().enumerate() {
+        for (j, b) in patterns.iter().enumerate().skip(i + 1) {
+            for c in patterns.iter().skip(j + 1) {
+                if let Some(location) = locate_symbol(image, [*a, *b, *c]) {
+                    locations.push(location);
+                }
+            }
+        }
+    }
+    locations.sort_by(|a, b| a.1.total_cmp(&b.1));
+    locations.into_iter().map(|(location, _)| location).collect()
+}
+
+// Orders three finder patterns as the corners of a symbol, if they could be one, with a score for how far
+// they are from a right-angled isosceles triangle
+fn locate_symbol(image: &BinaryImage, patterns: [FinderPattern; 3]) -> Option<(SymbolLocation, f64)> {
+    let sizes = patterns.map(|pattern| pattern.module_size);
+    if sizes.iter().cloned().fold(f64::MIN, f64::max) > 2.0 * sizes.iter().cloned().fold(f64::MAX, f64::min) {
+        return None;
+    }
+
+    // The top left corner is opposite the longest side
+    let [a, b, c] = patterns;
+    let (mut bottom_left, top_left, mut top_right) = [(b, a, c), (a, b, c), (a, c, b)]
+        .into_iter()
+        .max_by(|(p, _, q), (r, _, s)| p.distance(q).total_cmp(&r.distance(s)))?;
+    let (short, long) = {
+        let (first, second) = (top_left.distance(&top_right), top_left.distance(&bottom_left));
+        (first.min(second), first.max(second))
+    };
+    let hypotenuse = bottom_left.distance(&top_right);
+    let score = (long - short) / long + (hypotenuse * hypotenuse - short * short - long * long).abs() / (hypotenuse * hypotenuse);
+    if score > 0.5 {
+        return None;
+    }
+
+    // Going clockwise from the bottom left, with y down the image
+    let cross = (top_right.x - top_left.x) * (bottom_left.y - top_left.y) - (top_right.y - top_left.y) * (bottom_left.x - top_left.x);
+    if cross < 0.0 {
+        (bottom_left, top_right) = (top_right, bottom_left);
+    }
+
+    let module_size = [(top_left, top_right), (top_right, top_left), (top_left, bottom_left), (bottom_left, top_left)]
+        .iter()
+        .filter_map(|(from, to)| finder_width_towards(image, from, to))
+        .map(|width| width / 7.0)
+        .fold((0.0, 0), |(sum, count), size| (sum + size, count + 1));
+    let module_size = match module_size {
+        (_, 0) => sizes.iter().sum::<f64>() / 3.0,
+        (sum, count) => sum / count as f64,
+    };
+
+    let modules = (top_left.distance(&top_right) + top_left.distance(&bottom_left)) / 2.0 / module_size + 7.0;
+    let dimension = ((modules - 1.0) / 4.0).round() as u32 * 4 + 1;
+    if !(21..=177).contains(&dimension) {
+        return None;
+    }
+    Some((SymbolLocation { top_left, top_right, bottom_left, module_size, dimension }, score))
+}
+
+// Scans rows for the 1:1:3:1:1 runs through the middle of a finder pattern, and checks each one up and
+// down, across again and diagonally. Patterns found by several rows are merged.
+fn find_finder_patterns(image: &BinaryImage) -> Vec<FinderPattern> {
+    let mut patterns: Vec<FinderPattern> = vec![];
+    let skip = (3 * image.height() / (4 * MAX_MODULES)).max(1);
+    for y in (0..image.height() as i64).step_by(skip as usize) {
+        let runs = row_runs(image, y, 0, image.width() as i64);
+        for window in runs.windows(5).filter(|window| window[0].dark) {
+            let lengths = window.iter().map(|run| run.length).collect::<Vec<_>>();
+            if !matches_ratios(&lengths, &FINDER_RATIOS, 0.5) {
+                continue;
+            }
+            let centre = window[2].start + window[2].length as i64 / 2;
+            if let Some(pattern) = check_finder_pattern(image, centre, y, lengths.iter().sum()) {
+                match patterns.iter_mut().find(|found| {
+                    (found.x - pattern.x).abs() <= found.module_size
+                        && (found.y - pattern.y).abs() <= found.module_size
+                        && (found.module_size - pattern.module_size).abs() <= found.module_size.max(1.0)
+                }) {
+                    Some(found) => {
+                        let count = found.count as f64;
+                        *found = FinderPattern {
+                            x: (found.x * count + pattern.x) / (count + 1.0),
+                            y: (found.y * count + pattern.y) / (count + 1.0),
+                            module_size: (found.module_size * count + pattern.module_size) / (count + 1.0),
+                            count: found.count + 1,
+                        };
+                    }
+                    None => patterns.push(pattern),
+                }
+            }
+        }
+    }
+    patterns
+}
+
+fn check_finder_pattern(image: &BinaryImage, x: i64, y: i64, width: u32) -> Option<FinderPattern> {
+    let (vertical, (_, centre_y)) = runs_through(image, (x, y), (0, 1), 5, width)?;
+    let height = vertical.iter().sum::<u32>();
+    // As tall as it is wide, within 40%
+    if !matches_ratios(&vertical, &FINDER_RATIOS, 0.5) || 5 * height.abs_diff(width) >= 2 * width {
+        return None;
+    }
+    let (horizontal, (centre_x, _)) = runs_through(image, (x, centre_y as i64), (1, 0), 5, width)?;
+    if !matches_ratios(&horizontal, &FINDER_RATIOS, 0.5) {
+        return None;
+    }
+    let (diagonal, _) = runs_through(image, (centre_x as i64, centre_y as i64), (1, 1), 5, width)?;
+    if !matches_ratios(&diagonal, &FINDER_RATIOS, 0.75) {
+        return None;
+    }
+    Some(FinderPattern {
+        x: centre_x,
+        y: centre_y,
+        module_size: (horizontal.iter().sum::<u32>() + height) as f64 / 14.0,
+        count: 1,
+    })
+}
+
+// Looks for the centres of alignment patterns within the allowance of where one is expected, nearest first
+fn find_alignment_patterns(image: &BinaryImage, (x, y): (f64, f64), module_size: f64, allowance: f64) -> Vec<(f64, f64)> {
+    let (left, right) = ((x - allowance).max(0.0) as i64, ((x + allowance) as i64).min(image.width() as i64));
+    let (top, bottom) = ((y - allowance).max(0.0) as i64, ((y + allowance) as i64).min(image.height() as i64));
+    let max_run = (module_size * 2.0).ceil() as u32;
+
+    let mut found: Vec<(f64, f64)> = vec![];
+    for row in top..bottom {
+        // Runs reach a little past the sides, so as not to cut short those of patterns at the edges
+        let margin = (module_size * 2.0).ceil() as i64;
+        let runs = row_runs(image, row, (left - margin).max(0), (right + margin).min(image.width() as i64));
+        for window in runs.windows(3).filter(|window| !window[0].dark && (left..right).contains(&window[1].start)) {
+            let lengths = window.iter().map(|run| run.length).collect::<Vec<_>>();
+            let total = lengths.iter().sum::<u32>() as f64;
+            // Rows cross the pattern at an angle and perspective changes the size of modules across the
+            // symbol, so sizes are only roughly checked
+            if !matches_ratios(&lengths, &ALIGNMENT_RATIOS, 0.5) || !(module_size / 2.0..module_size * 2.0).contains(&(total / 3.0)) {
+                continue;
+            }
+            let centre = window[1].start + window[1].length as i64 / 2;
+            let Some((vertical, (_, centre_y))) = runs_through(image, (centre, row), (0, 1), 3, max_run) else {
+                continue;
+            };
+            let centre_x = window[1].start as f64 + window[1].length as f64 / 2.0;
+            // Each pattern is crossed by several rows
+            if matches_ratios(&vertical, &ALIGNMENT_RATIOS, 0.5)
+                && !found.iter().any(|other| (other.0 - centre_x).abs() < module_size && (other.1 - centre_y).abs() < module_size)
+            {
+                found.push((centre_x, centre_y));
+            }
+        }
+    }
+    found.sort_by(|a, b| (a.0 - x).hypot(a.1 - y).total_cmp(&(b.0 - x).hypot(b.1 - y)));
+    found
+}
+
+// The runs of each colour along part of a row
+fn row_runs(image: &BinaryImage, y: i64, left: i64, right: i64) -> Vec<Run> {
+    let mut runs: Vec<Run> = vec![];
+    for x in left..right {
+        let dark = image.get(x, y) == Some(true);
+        match runs.last_mut() {
+            Some(run) if run.dark == dark => run.length += 1,
+            _ => runs.push(Run { dark, start: x, length: 1 }),
+        }
+    }
+    runs
+}
+
+// Whether each run is in proportion to the total, within the tolerance of a module either way
+fn matches_ratios(lengths: &[u32], ratios: &[f64], tolerance: f64) -> bool {
+    let total = lengths.iter().sum::<u32>() as f64;
+    let modules = ratios.iter().sum::<f64>();
+    if total < modules {
+        return false;
+    }
+    let module_size = total / modules;
+    lengths
+        .iter()
+        .zip(ratios)
+        .all(|(&length, ratio)| (length as f64 - ratio * module_size).abs() < ratio * module_size * tolerance)
+}
+
+// The runs of alternating colour along a line through a pixel, with the run holding it in the middle and
+// half the rest either side, and the midpoint of the middle run. Runs longer than max_run, or which the
+// edge of the image cuts off before the outermost, rule the line out.
+fn runs_through(
+    image: &BinaryImage,
+    (x, y): (i64, i64),
+    (dx, dy): (i64, i64),
+    run_count: usize,
+    max_run: u32,
+) -> Option<(Vec<u32>, (f64, f64))> {
+    let colour = image.get(x, y)?;
+    let side_runs = run_count / 2;
+    // The pixels of the middle run beyond (x, y) in one direction, then the runs after it
+    let measure = |sign: i64| {
+        let mut runs = vec![0; side_runs + 1];
+        let (mut run, mut current) = (0, colour);
+        for step in 1.. {
+            let Some(dark) = image.get(x + sign * dx * step, y + sign * dy * step) else {
+                break;
+            };
+            if dark != current {
+                (run, current) = (run + 1, dark);
+                if run > side_runs {
+                    break;
+                }
+            }
+            runs[run] += 1;
+            if runs[run] > max_run {
+                return None;
+            }
+        }
+        (run >= side_runs).then_some(runs)
+    };
+    let (before, after) = (measure(-1)?, measure(1)?);
+
+    let lengths = before[1..]
+        .iter()
+        .rev()
+        .chain([before[0] + 1 + after[0]].iter())
+        .chain(&after[1..])
+        .copied()
+        .collect();
+    let offset = (after[0] as f64 - before[0] as f64) / 2.0;
+    Some((lengths, (x as f64 + 0.5 + dx as f64 * offset, y as f64 + 0.5 + dy as f64 * offset)))
+}
+
+// The width of a finder pattern along the line from its centre towards another finder pattern, measured
+// out to the light beyond its dark ring on either side
+fn finder_width_towards(image: &BinaryImage, from: &FinderPattern, to: &FinderPattern) -> Option<f64> {
+    let length = from.distance(to);
+    let direction = ((to.x - from.x) / length, (to.y - from.y) / length);
+    let edge = |sign: f64| {
+        let mut dark = true;
+        let mut transitions = 0;
+        let mut step = 0.0;
+        while step < length {
+            let (x, y) = (from.x + sign * direction.0 * step, from.y + sign * direction.1 * step);
+            let pixel = image.get(x.floor() as i64, y.floor() as i64)?;
+            if pixel != dark {
+                (dark, transitions) = (pixel, transitions + 1);
+                if transitions == 3 {
+                    return Some(step);
+                }
+            }
+            step += 0.5;
+        }
+        None
+    };
+    Some(edge(1.0)? + edge(-1.0)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::QRGenerator;
+    use image::{DynamicImage, GrayImage, Luma};
+
+    #[test]
+    fn locates_finder_patterns_and_dimension() {
+        let mut generator = QRGenerator::default();
+        generator.options.version = Some(3);
+        let qr_code = generator.make_qr_code("Finder patterns".to_string()).unwrap();
+        // 5 pixels a module with a 4 module quiet zone
+        let size = (qr_code.width() + 8) * 5;
+        let image = GrayImage::from_fn(size as u32, size as u32, |x, y| {
+            let (x, y) = ((x / 5) as i64 - 4, (y / 5) as i64 - 4);
+            let inside = (0..qr_code.width() as i64).contains(&x) && (0..qr_code.width() as i64).contains(&y);
+            Luma([if inside && qr_code.is_dark(x as usize, y as usize) { 0 } else { 255 }])
+        });
+        let image = BinaryImage::threshold(&DynamicImage::ImageLuma8(image));
+
+        let location = locate_symbols(&image)[0];
+        // Finder pattern centres are 3.5 modules into the symbol
+        let near = |pattern: FinderPattern, (x, y): (f64, f64)| (pattern.x - x).abs() < 0.5 && (pattern.y - y).abs() < 0.5;
+        assert!(near(location.top_left, (37.5, 37.5)));
+        assert!(near(location.top_right, (37.5 + 22.0 * 5.0, 37.5)));
+        assert!(near(location.bottom_left, (37.5, 37.5 + 22.0 * 5.0)));
+        assert_eq!((location.dimension, location.module_size.round()), (29, 5.0));
+
+        let modules = location.sample(&image, 29).unwrap();
+        assert!((0..29).all(|y| (0..29).all(|x| modules[y][x] == qr_code.is_dark(x, y))));
+    }
+
+    #[test]
+    fn checks_run_ratios() {
+        assert!(matches_ratios(&[3, 3, 9, 3, 3], &FINDER_RATIOS, 0.5));
+        assert!(matches_ratios(&[2, 4, 8, 3, 3], &FINDER_RATIOS, 0.5));
+        assert!(!matches_ratios(&[3, 3, 3, 3, 3], &FINDER_RATIOS, 0.5));
+        assert!(!matches_ratios(&[2, 6, 2], &ALIGNMENT_RATIOS, 0.5));
+    }
+}
@@ -0,0 +1,210 @@
+use bitvec::{prelude::*, vec::BitVec};
+use encoding_rs::{
+    Encoding, ISO_8859_10, ISO_8859_13, ISO_8859_14, ISO_8859_15, ISO_8859_16, ISO_8859_2,
+    ISO_8859_3, ISO_8859_4, ISO_8859_5, ISO_8859_6, ISO_8859_7, ISO_8859_8, SHIFT_JIS,
+    WINDOWS_1254, WINDOWS_874,
+};
+
+use crate::qr_errors::EncodingError;
+
+// Character sets which can be selected with an ECI (Extended Channel Interpretation) segment. Without
+// an ECI, byte mode data is interpreted as ISO-8859-1.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum CharacterSets {
+    Iso8859_1,
+    Iso8859_2,
+    Iso8859_3,
+    Iso8859_4,
+    Iso8859_5,
+    Iso8859_6,
+    Iso8859_7,
+    Iso8859_8,
+    Iso8859_9,
+    Iso8859_10,
+    Iso8859_11,
+    Iso8859_13,
+    Iso8859_14,
+    Iso8859_15,
+    Iso8859_16,
+    ShiftJIS,
+    UTF8,
+}
+
+impl CharacterSets {
+    // The ECI assignment number, from the AIM ECI register
+    pub fn eci_designator(&self) -> u32 {
+        match self {
+            CharacterSets::Iso8859_1 => 3,
+            CharacterSets::Iso8859_2 => 4,
+            CharacterSets::Iso8859_3 => 5,
+            CharacterSets::Iso8859_4 => 6,
+            CharacterSets::Iso8859_5 => 7,
+            CharacterSets::Iso8859_6 => 8,
+            CharacterSets::Iso8859_7 => 9,
+            CharacterSets::Iso8859_8 => 10,
+            CharacterSets::Iso8859_9 => 11,
+            CharacterSets::Iso8859_10 => 12,
+            CharacterSets::Iso8859_11 => 13,
+            // There is no ISO-8859-12, so ECI 14 is unassigned
+            CharacterSets::Iso8859_13 => 15,
+            CharacterSets::Iso8859_14 => 16,
+            CharacterSets::Iso8859_15 => 17,
+            CharacterSets::Iso8859_16 => 18,
+            CharacterSets::ShiftJIS => 20,
+            CharacterSets::UTF8 => 26,
+        }
+    }
+
+    // The character set an ECI designator selects. Designator 1 is an older assignment for ISO-8859-1.
+    pub(crate) fn from_designator(designator: u32) -> Option<Self> {
+        let character_set = match designator {
+            1 | 3 => CharacterSets::Iso8859_1,
+            4 => CharacterSets::Iso8859_2,
+            5 => CharacterSets::Iso8859_3,
+            6 => CharacterSets::Iso8859_4,
+            7 => CharacterSets::Iso8859_5,
+            8 => CharacterSets::Iso8859_6,
+            9 => CharacterSets::Iso8859_7,
+            10 => CharacterSets::Iso8859_8,
+            11 => CharacterSets::Iso8859_9,
+            12 => CharacterSets::Iso8859_10,
+            13 => CharacterSets::Iso8859_11,
+            15 => CharacterSets::Iso8859_13,
+            16 => CharacterSets::Iso8859_14,
+            17 => CharacterSets::Iso8859_15,
+            18 => CharacterSets::Iso8859_16,
+            20 => CharacterSets::ShiftJIS,
+            26 => CharacterSets::UTF8,
+            _ => return None,
+        };
+        Some(character_set)
+    }
+
+    // Reads bytes in this character set, replacing any which don't form a character
+    pub(crate) fn decode(&self, bytes: &[u8]) -> String {
+        match self {
+            CharacterSets::Iso8859_1 => bytes.iter().map(|&b| char::from(b)).collect(),
+            CharacterSets::UTF8 => String::from_utf8_lossy(bytes).into_owned(),
+            _ => self.encoding().decode_without_bom_handling(bytes).0.into_owned(),
+        }
+    }
+
+    // Returns the bytes representing the character in this character set, or None if it can't be represented
+    pub(crate) fn encode_char(&self, c: char) -> Option<Vec<u8>> {
+        match self {
+            CharacterSets::Iso8859_1 => u8::try_from(c as u32).ok().map(|b| vec![b]),
+            CharacterSets::UTF8 => {
+                let mut byte_space = [0; 4];
+                Some(c.encode_utf8(&mut byte_space).as_bytes().to_vec())
+            }
+            // encoding_rs only has the Windows supersets of these; their extra characters live in the
+            // 0x80-0x9F range, which ISO-8859 reserves for control codes
+            CharacterSets::Iso8859_9 | CharacterSets::Iso8859_11 => {
+                Self::encode_with(self.encoding(), c).filter(|bytes| {
+                    c.is_control() || bytes.iter().all(|&b| !(0x80..0xA0).contains(&b))
+                })
+            }
+            _ => Self::encode_with(self.encoding(), c),
+        }
+    }
+
+    fn encoding(&self) -> &'static Encoding {
+        match self {
+            CharacterSets::Iso8859_2 => ISO_8859_2,
+            CharacterSets::Iso8859_3 => ISO_8859_3,
+            CharacterSets::Iso8859_4 => ISO_8859_4,
+            CharacterSets::Iso8859_5 => ISO_8859_5,
+            CharacterSets::Iso8859_6 => ISO_8859_6,
+            CharacterSets::Iso8859_7 => ISO_8859_7,
+            CharacterSets::Iso8859_8 => ISO_8859_8,
+            CharacterSets::Iso8859_9 => WINDOWS_1254,
+            CharacterSets::Iso8859_10 => ISO_8859_10,
+            CharacterSets::Iso8859_11 => WINDOWS_874,
+            CharacterSets::Iso8859_13 => ISO_8859_13,
+            CharacterSets::Iso8859_14 => ISO_8859_14,
+            CharacterSets::Iso8859_15 => ISO_8859_15,
+            CharacterSets::Iso8859_16 => ISO_8859_16,
+            CharacterSets::ShiftJIS => SHIFT_JIS,
+            CharacterSets::Iso8859_1 | CharacterSets::UTF8 => unreachable!(),
+        }
+    }
+
+    fn encode_with(encoding: &'static Encoding, c: char) -> Option<Vec<u8>> {
+        let mut byte_space = [0; 4];
+        let (bytes, _, had_errors) = encoding.encode(c.encode_utf8(&mut byte_space));
+        if had_errors {
+            None
+        } else {
+            Some(bytes.into_owned())
+        }
+    }
+}
+
+// An ECI segment: the 0111 mode indicator, followed by the designator in 1, 2 or 3 bytes
+pub(crate) fn eci_segment(designator: u32) -> Result<BitVec<u8, Msb0>, EncodingError> {
+    let mut segment = bitvec![u8, Msb0; 0, 1, 1, 1];
+    let (prefix, value_bits): (BitVec<u8, Msb0>, usize) = match designator {
+        0..=127 => (bitvec![u8, Msb0; 0], 7),
+        128..=16383 => (bitvec![u8, Msb0; 1, 0], 14),
+        16384..=999999 => (bitvec![u8, Msb0; 1, 1, 0], 21),
+        _ => return Err(EncodingError::new("ECI designators range from 0 to 999999")),
+    };
+    segment.extend_from_bitslice(&prefix);
+    let mut bits = bitarr![u32, Msb0; 0; 21];
+    bits[0..value_bits].store_be(designator);
+    segment.extend_from_bitslice(&bits[0..value_bits]);
+
+    Ok(segment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_single_byte_designator() {
+        assert_eq!(eci_segment(26).unwrap(), bitvec![u8, Msb0; 0, 1, 1, 1, 0, 0, 0, 1, 1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn encodes_two_byte_designator() {
+        // 0111 10 00000100000000
+        let segment = eci_segment(256).unwrap();
+        assert_eq!(segment.len(), 20);
+        assert_eq!(segment, bitvec![u8, Msb0; 0, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn encodes_three_byte_designator() {
+        let segment = eci_segment(999999).unwrap();
+        assert_eq!(segment.len(), 28);
+        assert_eq!(segment[4..7], bits![u8, Msb0; 1, 1, 0]);
+        assert_eq!(segment[7..].load_be::<u32>(), 999999);
+    }
+
+    #[test]
+    fn cannot_encode_oversized_designator() {
+        assert!(eci_segment(1000000).is_err());
+    }
+
+    #[test]
+    fn encodes_characters_in_selected_set() {
+        assert_eq!(CharacterSets::Iso8859_1.encode_char('é'), Some(vec![0xE9]));
+        assert_eq!(CharacterSets::Iso8859_1.encode_char('€'), None);
+        assert_eq!(CharacterSets::Iso8859_15.encode_char('€'), Some(vec![0xA4]));
+        assert_eq!(CharacterSets::Iso8859_5.encode_char('Ж'), Some(vec![0xB6]));
+        assert_eq!(CharacterSets::Iso8859_9.encode_char('ğ'), Some(vec![0xF0]));
+        assert_eq!(CharacterSets::Iso8859_9.encode_char('€'), None);
+        assert_eq!(CharacterSets::ShiftJIS.encode_char('点'), Some(vec![0x93, 0x5F]));
+        assert_eq!(CharacterSets::UTF8.encode_char('€'), Some(vec![0xE2, 0x82, 0xAC]));
+    }
+
+    #[test]
+    fn decodes_bytes_in_designated_set() {
+        assert_eq!(CharacterSets::from_designator(17).unwrap().decode(&[0xA4]), "€");
+        assert_eq!(CharacterSets::from_designator(3).unwrap().decode(&[0xE9]), "é");
+        assert_eq!(CharacterSets::from_designator(20).unwrap().decode(&[0x93, 0x5F]), "点");
+        assert_eq!(CharacterSets::from_designator(26).unwrap().decode(&[0xE2, 0x82, 0xAC]), "€");
+        assert_eq!(CharacterSets::from_designator(14), None);
+    }
+}
@@ -1,11 +1,20 @@
 use crate::{
-    error_correction::CorrectionLevels, qr_errors::EncodingError, sizer::Sizer, QRError,
-    QRGenerator, QRSymbolTypes,
+    eci::{self, CharacterSets},
+    error_correction::CorrectionLevels, gs1::GROUP_SEPARATOR, qr_errors::EncodingError,
+    segment::Segment, segmenter::{self, SegmentationStrategies}, sizer::Sizer, Options, QRError, QRGenerator, QRSymbolTypes,
 };
 use bitvec::{prelude::*, vec::BitVec};
 use core::iter::Peekable;
+use encoding_rs::SHIFT_JIS;
 use itertools::Itertools;
-use std::collections::HashMap;
+
+// FNC1 in first position marks GS1 data; in second position it marks data following an AIM-assigned
+// industry specification, identified by an application indicator: 00-99, or a letter's ASCII value + 100
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum Fnc1Modes {
+    First,
+    Second(u8),
+}
 
 #[derive(Eq, PartialEq, Clone, Copy, PartialOrd, Debug)]
 pub enum EncodingModes {
@@ -40,8 +49,13 @@ impl DistToNextType {
 pub struct Encoder<'a> {
     generator: &'a QRGenerator,
     input_data: String,
+    input_segments: Option<Vec<Segment>>,
     pub output_data: BitVec<u8, Msb0>,
+    // The segments making up output_data, as they appear in the symbol
+    pub segments: Vec<Segment>,
 
+    character_set: Option<CharacterSets>,
+    structured_append: Option<(u8, u8, u8)>,
     size_estimate: u32,
     change_distances: Vec<DistToNextType>,
 }
@@ -51,44 +65,125 @@ impl<'a> Encoder<'a> {
         Self {
             generator,
             input_data,
+            input_segments: None,
             output_data: bitvec![u8, Msb0;],
+            segments: vec![],
+            character_set: None,
+            structured_append: None,
             size_estimate: 0,
             change_distances: vec![],
         }
     }
 
-    // For the moment, we'll ignore Kanji encoding
-    pub fn encode_data_into_byte_stream(&mut self) -> Result<(), QRError> {
-        if self.generator.options.qr_type == Some(QRSymbolTypes::MicroQRCode) {
-            panic!("Unimplemented select_initial_encoding for Micro QR codes");
+    // Encodes hand-built segments exactly as given, rather than choosing modes for text
+    pub fn from_segments(generator: &'a QRGenerator, input_segments: Vec<Segment>) -> Self {
+        Self {
+            input_segments: Some(input_segments),
+            ..Self::new(generator, String::new())
         }
+    }
+
+    // Marks this symbol as one of a linked Structured Append series. Index is zero-based.
+    pub fn set_structured_append(&mut self, index: u8, total: u8, parity: u8) {
+        self.structured_append = Some((index, total, parity));
+    }
+
+    pub fn encode_data_into_byte_stream(&mut self) -> Result<(), QRError> {
         self.validate_data_stream_vs_options()?;
 
+        if let Some((index, total, parity)) = self.structured_append {
+            self.output_data.append(&mut Self::structured_append_header(index, total, parity)?);
+        }
+
+        self.character_set = Self::select_character_set(&self.generator.options, &self.input_data)?;
+        if let Some(character_set) = self.character_set {
+            self.output_data.append(&mut eci::eci_segment(character_set.eci_designator())?);
+            self.segments.push(Segment::ECI(character_set.eci_designator()));
+        }
+
         self.estimate_size();
-        self.change_distances = Self::calculate_change_distances(&self.input_data);
+        if let Some(fnc1) = self.generator.options.fnc1 {
+            self.output_data.append(&mut Self::fnc1_indicator(fnc1)?);
+        }
+
+        if let Some(segments) = self.input_segments.take() {
+            let encoded = self.encode_segments(&segments);
+            self.input_segments = Some(segments);
+            encoded?;
+        } else {
+            self.encode_text()?;
+        }
+
+        self.output_data.append(&mut self.terminator());
+        self.output_data
+            .append(&mut self.padding_to_codeword_boundary());
+        self.output_data.append(&mut self.padding_codewords()?);
+        Ok(())
+    }
+
+    fn encode_segments(&mut self, segments: &[Segment]) -> Result<(), EncodingError> {
+        for segment in segments {
+            let (mode, (mut bits, char_count)) = match segment {
+                Segment::ECI(designator) => {
+                    self.output_data.append(&mut eci::eci_segment(*designator)?);
+                    self.segments.push(segment.clone());
+                    continue;
+                }
+                Segment::Numeric(digits) => (EncodingModes::Numeric, Self::numeric_bits(digits.chars())),
+                Segment::AlphaNumeric(chars) => (EncodingModes::AlphaNumeric, Self::alphanumeric_bits(chars.chars())),
+                Segment::Byte(bytes) => (EncodingModes::Byte, (bytes.view_bits::<Msb0>().to_bitvec(), bytes.len())),
+                Segment::Kanji(kanji) => (EncodingModes::Kanji, Self::kanji_bits(kanji.chars())),
+            };
+            self.segments.push(segment.clone());
+            self.output_data.append(&mut self.sequence_preamble(mode, char_count));
+            self.output_data.append(&mut bits);
+        }
+        Ok(())
+    }
 
-        let mut current_encoding = self
+    fn encode_text(&mut self) -> Result<(), EncodingError> {
+        self.change_distances = Self::calculate_fnc1_change_distances(&self.input_data, self.fnc1());
+
+        let mode = self
             .generator
             .options
             .mode
             .unwrap_or(EncodingModes::Dynamic);
-        let dynamic_mode = current_encoding == EncodingModes::Dynamic;
-        if current_encoding == EncodingModes::Dynamic {
-            current_encoding = self.select_initial_encoding();
+        if mode == EncodingModes::Dynamic
+            && self.generator.options.segmentation.unwrap_or_default() == SegmentationStrategies::Optimal
+        {
+            self.encode_optimal_segments()?;
+        } else {
+            self.encode_runs(mode)?;
         }
+        Ok(())
+    }
 
-        let mut input_iter = self
-            .input_data
-            .chars()
-            .zip(self.change_distances.iter())
-            .peekable();
+    // Encodes the data in the requested mode, or in Dynamic mode using the Annex J heuristic
+    fn encode_runs(&mut self, mode: EncodingModes) -> Result<(), EncodingError> {
+        let dynamic_mode = mode == EncodingModes::Dynamic;
+        let mut current_encoding = if dynamic_mode {
+            self.select_initial_encoding()
+        } else {
+            mode
+        };
+
+        let chars = self.input_data.chars().collect::<Vec<char>>();
+        let mut input_iter = chars.iter().copied().zip(self.change_distances.iter()).peekable();
+        let mut run_start = 0;
         while input_iter.peek().is_some() {
             let (next_encoding, mut bit_run, char_count) = match current_encoding {
-                EncodingModes::Numeric => Self::encode_numeric_run(&mut input_iter, dynamic_mode)?,
+                EncodingModes::Numeric => self.encode_numeric_run(&mut input_iter, dynamic_mode)?,
                 EncodingModes::AlphaNumeric => self.encode_alphanumeric_run(&mut input_iter, dynamic_mode)?,
                 EncodingModes::Byte => self.encode_byte_run(&mut input_iter, dynamic_mode)?,
+                EncodingModes::Kanji => self.encode_kanji_run(&mut input_iter, dynamic_mode)?,
                 _ => unreachable!(),
             };
+            let run_end = chars.len() - input_iter.len();
+            let run = chars[run_start..run_end].iter().collect();
+            run_start = run_end;
+            let segment = self.run_segment(current_encoding, run, &bit_run);
+            self.segments.push(segment);
             self.output_data
                 .append(&mut self.sequence_preamble(current_encoding, char_count));
             self.output_data.append(&mut bit_run);
@@ -96,19 +191,81 @@ impl<'a> Encoder<'a> {
                 current_encoding = next_encoding;
             }
         }
+        Ok(())
+    }
 
-        self.output_data.append(&mut self.terminator());
-        self.output_data
-            .append(&mut self.padding_to_codeword_boundary());
-        self.output_data.append(&mut self.padding_codewords()?);
+    // Encodes the data in Dynamic mode, split into the segments giving the shortest bitstream for the
+    // symbol's version
+    fn encode_optimal_segments(&mut self) -> Result<(), EncodingError> {
+        let qr_type = self.generator.options.qr_type.unwrap();
+        let version = self.generator.options.version.unwrap_or(self.size_estimate);
+        let (segments, _) = segmenter::optimal_segments(
+            &self.input_data,
+            &qr_type,
+            version,
+            self.character_set,
+            self.fnc1(),
+        )?;
+
+        for (mode, run) in segments {
+            let distances = Self::calculate_fnc1_change_distances(&run, self.fnc1());
+            let run_chars = run.clone();
+            let mut input_iter = run.chars().zip(distances.iter()).peekable();
+            let (_, mut bit_run, char_count) = match mode {
+                EncodingModes::Numeric => self.encode_numeric_run(&mut input_iter, false)?,
+                EncodingModes::AlphaNumeric => self.encode_alphanumeric_run(&mut input_iter, false)?,
+                EncodingModes::Byte => self.encode_byte_run(&mut input_iter, false)?,
+                EncodingModes::Kanji => self.encode_kanji_run(&mut input_iter, false)?,
+                _ => unreachable!(),
+            };
+            self.segments.push(self.run_segment(mode, run_chars, &bit_run));
+            self.output_data.append(&mut self.sequence_preamble(mode, char_count));
+            self.output_data.append(&mut bit_run);
+        }
         Ok(())
     }
 
+    // The segment a run of the input data became: byte runs as their encoded bytes, and alphanumeric
+    // runs with any FNC1 escaping applied
+    fn run_segment(&self, mode: EncodingModes, run: String, bit_run: &BitVec<u8, Msb0>) -> Segment {
+        match mode {
+            EncodingModes::Numeric => Segment::Numeric(run),
+            EncodingModes::AlphaNumeric => Segment::AlphaNumeric(
+                run.chars().flat_map(|c| Self::escape_alphanumeric(c, self.fnc1())).collect(),
+            ),
+            EncodingModes::Byte => Segment::Byte(bit_run.clone().into_vec()),
+            EncodingModes::Kanji => Segment::Kanji(run),
+            EncodingModes::Dynamic => unreachable!(),
+        }
+    }
+
     fn validate_data_stream_vs_options(&self) -> Result<(), EncodingError> {
-        if self.input_data.is_empty() {
+        let no_data = match &self.input_segments {
+            Some(segments) => segments.iter().all(|segment| segment.char_count() == 0),
+            None => self.input_data.is_empty(),
+        };
+        if no_data {
             return Err(EncodingError::new("No data to encode"));
         }
 
+        if self.generator.options.fnc1.is_some()
+            && self.generator.options.qr_type == Some(QRSymbolTypes::MicroQRCode)
+        {
+            return Err(EncodingError::new("MicroQR codes don't support FNC1 modes"));
+        }
+
+        if let (Some(qr_type), Some(version), Some(correction)) = (
+            self.generator.options.qr_type.as_ref(),
+            self.generator.options.version,
+            self.generator.options.correction_level.as_ref(),
+        ) {
+            Sizer::validate_version(qr_type, version, correction)?;
+        }
+
+        if let Some(segments) = &self.input_segments {
+            return self.validate_segments(segments);
+        }
+
         // MicroQR Codes limit the data types they can handle
         if let Some(version) = self.generator.options.version {
             if self.generator.options.qr_type == Some(QRSymbolTypes::MicroQRCode) {
@@ -138,12 +295,20 @@ impl<'a> Encoder<'a> {
             .unwrap_or(EncodingModes::Dynamic);
 
         // If mode has been specified and the data stream is not consistent with that, error
+        if requested_mode == EncodingModes::Kanji {
+            if self.input_data.chars().any(|c| !Self::is_qr_kanji(c)) {
+                return Err(EncodingError::new(
+                    "Can't encode characters outside the Shift-JIS Kanji set when in Kanji mode",
+                ));
+            }
+            return Ok(());
+        }
         if requested_mode != EncodingModes::Byte
             && requested_mode != EncodingModes::Dynamic
             && self
                 .input_data
                 .chars()
-                .any(|c| !Self::is_qr_alphanumeric(c))
+                .any(|c| !Self::is_fnc1_alphanumeric(c, self.fnc1()))
         {
             return Err(EncodingError::new(
                 "Can't encode non-alphanumeric characters when not in Byte mode",
@@ -160,19 +325,94 @@ impl<'a> Encoder<'a> {
         Ok(())
     }
 
-    fn select_initial_encoding(&self) -> EncodingModes {
-        if self.generator.options.qr_type == Some(QRSymbolTypes::MicroQRCode) {
-            // TODO: Implement Micro QRs here and elsewhere
-            panic!("Unimplemented select_initial_encoding for Micro QR codes");
+    // Segments carry their own modes, so a requested mode other than Dynamic must match them all
+    fn validate_segments(&self, segments: &[Segment]) -> Result<(), EncodingError> {
+        let requested_mode = self.generator.options.mode.unwrap_or(EncodingModes::Dynamic);
+        for segment in segments {
+            segment.validate()?;
+            if requested_mode != EncodingModes::Dynamic
+                && segment.mode().is_some_and(|mode| mode != requested_mode)
+            {
+                return Err(EncodingError::new("Segments must all be in the requested mode"));
+            }
+            if let (Some(qr_type), Some(version)) =
+                (self.generator.options.qr_type.as_ref(), self.generator.options.version)
+            {
+                segment.bit_length(qr_type, version)?;
+            }
+        }
+        Ok(())
+    }
+
+    // 0011, then the 4-bit symbol index, 4-bit (total - 1) and the 8-bit parity of the complete data
+    fn structured_append_header(index: u8, total: u8, parity: u8) -> Result<BitVec<u8, Msb0>, EncodingError> {
+        if !(1..=16).contains(&total) || index >= total {
+            return Err(EncodingError::new("Structured Append supports up to 16 symbols"));
+        }
+        let mut header = bitvec![u8, Msb0; 0, 0, 1, 1];
+        let mut bits = bitarr![u8, Msb0; 0; 16];
+        bits[0..4].store(index);
+        bits[4..8].store(total - 1);
+        bits[8..16].store(parity);
+        header.extend_from_bitslice(&bits[0..16]);
+
+        Ok(header)
+    }
+
+    // The parity byte is the XOR of every byte of the complete data, as it is represented in the symbols
+    pub(crate) fn structured_append_parity(options: &Options, data: &str) -> u8 {
+        let character_set = options.eci.unwrap_or(CharacterSets::Iso8859_1);
+        data.chars()
+            .flat_map(|c| {
+                character_set
+                    .encode_char(c)
+                    .or_else(|| CharacterSets::ShiftJIS.encode_char(c))
+                    .unwrap_or_default()
+            })
+            .fold(0, |parity, byte| parity ^ byte)
+    }
+
+    // Returns the character set to announce with an ECI segment, if any. Without one, byte mode data is
+    // read as ISO-8859-1, so UTF-8 is announced when byte mode data can't be represented that way.
+    // Kanji are always encoded in Kanji mode when they can be, so never need an ECI.
+    pub(crate) fn select_character_set(options: &Options, data: &str) -> Result<Option<CharacterSets>, EncodingError> {
+        let micro = options.qr_type == Some(QRSymbolTypes::MicroQRCode);
+        if let Some(character_set) = options.eci {
+            if micro {
+                return Err(EncodingError::new("MicroQR codes don't support ECI segments"));
+            }
+            return Ok(Some(character_set));
+        }
+
+        let mode = options.mode.unwrap_or(EncodingModes::Dynamic);
+        let needs_utf8 = mode != EncodingModes::Kanji
+            && data.chars().any(|c| {
+                CharacterSets::Iso8859_1.encode_char(c).is_none()
+                    && !(mode == EncodingModes::Dynamic && Self::is_qr_kanji(c))
+            });
+        match (needs_utf8, micro) {
+            (false, _) => Ok(None),
+            (true, false) => Ok(Some(CharacterSets::UTF8)),
+            (true, true) => Err(EncodingError::new(
+                "MicroQR codes don't support ECI segments; byte data must be ISO-8859-1",
+            )),
         }
+    }
 
+    // Micro QR codes follow the same rules, using the thresholds for the smallest QR versions. The data
+    // has already been validated against the modes the Micro QR version supports.
+    fn select_initial_encoding(&self) -> EncodingModes {
         let first_char = self.input_data.chars().nth(0).unwrap();
         // J2 a) 1) - If initial data is Byte, start in Byte mode
-        if !Self::is_qr_alphanumeric(first_char) {
+        if Self::fnc1_char_type(first_char, self.fnc1()) == CharacterTypes::Byte {
             return EncodingModes::Byte;
         }
 
-        // J2 a) 2) - IGNORED: Kanji mode first character
+        // J2 a) 2) - If data starts with a Kanji character, start in Kanji mode
+        if Self::is_qr_kanji(first_char) {
+            return EncodingModes::Kanji;
+        }
+
         if !first_char.is_ascii_digit() {
             // J2 a) 3) - If data starts Alphanumeric: start in Byte mode if a Byte within [6,7,8] chars; else AN
             let dist_to_byte: usize = if self.size_estimate <= 9 {
@@ -220,6 +460,7 @@ impl<'a> Encoder<'a> {
     }
 
     fn encode_numeric_run<'b, Input>(
+        &self,
         input: &mut Peekable<Input>,
         dynamic: bool
     ) -> Result<(EncodingModes, BitVec<u8, Msb0>, usize), EncodingError>
@@ -227,11 +468,27 @@ impl<'a> Encoder<'a> {
         Input: Iterator<Item = (char, &'b DistToNextType)>,
     {
         let numbers = input.peeking_take_while(|(c, _)| c.is_ascii_digit());
+        let (encoded_numbers, char_count) = Self::numeric_bits(numbers.map(|(c, _)| c));
+
+        let next_mode = if let Some(&(c, _)) = input.peek() {
+            if !dynamic {
+                return Err(EncodingError::new("Need to change mode, but not dynamic"));
+            }
+            Self::fnc1_char_type(c, self.fnc1())
+        } else {
+            // Doesn't matter
+            EncodingModes::Numeric
+        };
+        Ok((next_mode, encoded_numbers, char_count))
+    }
+
+    // Digits are encoded 3 to 10 bits, with a final 1 or 2 in 4 or 7 bits
+    fn numeric_bits(numbers: impl Iterator<Item = char>) -> (BitVec<u8, Msb0>, usize) {
         let mut char_count = 0usize;
         let mut encoded_numbers = bitvec![u8, Msb0;];
 
         for chunk in &numbers.chunks(3) {
-            let digits_str: String = chunk.map(|(c, _)| c).collect();
+            let digits_str: String = chunk.collect();
             let chunk_size = digits_str.len();
             char_count += chunk_size;
 
@@ -248,17 +505,7 @@ impl<'a> Encoder<'a> {
             bits[0..bit_count].store(value);
             encoded_numbers.extend_from_bitslice(&bits[0..bit_count]);
         }
-
-        let next_mode = if let Some(&(c, _)) = input.peek() {
-            if !dynamic {
-                return Err(EncodingError::new("Need to change mode, but not dynamic"));
-            }
-            Self::char_type(c)
-        } else {
-            // Doesn't matter
-            EncodingModes::Numeric
-        };
-        Ok((next_mode, encoded_numbers, char_count))
+        (encoded_numbers, char_count)
     }
 
     fn encode_alphanumeric_run<'b, Input>(
@@ -279,14 +526,41 @@ impl<'a> Encoder<'a> {
                 && distances.alpha_numeric.unwrap_or(usize::MAX)
                     .min(distances.byte.unwrap_or(usize::MAX))
                     .min(distances.end) >= min_dist_to_non_num;
-            Self::is_qr_alphanumeric(c)
+            Self::is_fnc1_alphanumeric(c, self.fnc1())
                 && (!should_switch_down || !dynamic)
         });
+        let fnc1 = self.fnc1();
+        let escaped = alphanums.flat_map(|(c, _)| Self::escape_alphanumeric(c, fnc1));
+        let (encoded_alphanums, char_count) = Self::alphanumeric_bits(escaped);
+
+        let next_mode = if let Some(&(c, _)) = input.peek() {
+            if !dynamic {
+                return Err(EncodingError::new("Need to change mode, but not dynamic"))
+            }
+            Self::fnc1_char_type(c, self.fnc1())
+        } else {
+            // Doesn't matter
+            EncodingModes::Numeric
+        };
+        Ok((next_mode, encoded_alphanums, char_count))
+    }
+
+    // In FNC1 modes, % encodes the group separator (FNC1), so a literal % is escaped as %%
+    fn escape_alphanumeric(c: char, fnc1: bool) -> Vec<char> {
+        match c {
+            '%' if fnc1 => vec!['%', '%'],
+            GROUP_SEPARATOR => vec!['%'],
+            _ => vec![c],
+        }
+    }
+
+    // Characters are encoded in pairs to 11 bits, with a final single character in 6 bits
+    fn alphanumeric_bits(alphanums: impl Iterator<Item = char>) -> (BitVec<u8, Msb0>, usize) {
         let mut char_count = 0usize;
         let mut encoded_alphanums = bitvec![u8, Msb0;];
 
         for chunk in &alphanums.chunks(2) {
-            let chunk_vec: Vec<char> = chunk.map(|(c, _)| c).collect();
+            let chunk_vec: Vec<char> = chunk.collect();
             let chunk_size = chunk_vec.len();
             char_count += chunk_size;
 
@@ -302,17 +576,7 @@ impl<'a> Encoder<'a> {
                 encoded_alphanums.extend_from_bitslice(&bits[0..11]);
             }
         }
-
-        let next_mode = if let Some(&(c, _)) = input.peek() {
-            if !dynamic {
-                return Err(EncodingError::new("Need to change mode, but not dynamic"))
-            }
-            Self::char_type(c)
-        } else {
-            // Doesn't matter
-            EncodingModes::Numeric
-        };
-        Ok((next_mode, encoded_alphanums, char_count))
+        (encoded_alphanums, char_count)
     }
 
     fn encode_byte_run<'b, Input>(
@@ -339,7 +603,7 @@ impl<'a> Encoder<'a> {
             (27..) => 8,
         };
         let change_to_alphanum = |c, distances: &DistToNextType| -> bool {
-            Self::is_qr_alphanumeric(c)
+            Self::is_fnc1_alphanumeric(c, self.fnc1())
                 && distances.byte.unwrap_or(usize::MAX).min(distances.end) >= min_dist_alphanum_to_byte
         };
         let change_to_numeric = |c: char, distances: &DistToNextType| -> bool {
@@ -352,19 +616,22 @@ impl<'a> Encoder<'a> {
         let bytes = input.peeking_take_while(|&(c, distances)| {
             let should_change_to_alphanum = change_to_alphanum(c, distances);
             let should_change_to_numeric = change_to_numeric(c, distances);
-            !(should_change_to_alphanum || should_change_to_numeric) || !dynamic
+            let should_change_to_kanji = Self::is_qr_kanji(c);
+            !(should_change_to_alphanum || should_change_to_numeric || should_change_to_kanji) || !dynamic
         });
 
         let mut byte_count = 0usize;
         let mut encoded_bytes = bitvec![u8, Msb0;];
 
+        let character_set = self.character_set.unwrap_or(CharacterSets::Iso8859_1);
         for (char, _) in bytes {
-            let mut byte_space = [0; 4];
-            let bytes = char.encode_utf8(&mut byte_space);
+            let bytes = character_set.encode_char(char).ok_or(EncodingError::new(
+                "Can't represent character in the selected ECI character set",
+            ))?;
             byte_count += bytes.len();
 
             let mut bits = bitarr![u16, Msb0; 0; 8];
-            for byte in bytes.bytes() {
+            for byte in bytes {
                 bits[0..8].store(byte);
                 encoded_bytes.extend_from_bitslice(&bits[0..8]);
             }
@@ -374,7 +641,9 @@ impl<'a> Encoder<'a> {
             if !dynamic {
                 return Err(EncodingError::new("Need to change mode, but not dynamic"))
             }
-            if change_to_alphanum(c, distances) {
+            if Self::is_qr_kanji(c) {
+                EncodingModes::Kanji
+            } else if change_to_alphanum(c, distances) {
                 EncodingModes::AlphaNumeric
             } else if change_to_numeric(c, distances) {
                 EncodingModes::Numeric
@@ -388,6 +657,44 @@ impl<'a> Encoder<'a> {
         Ok((next_mode, encoded_bytes, byte_count))
     }
 
+    fn encode_kanji_run<'b, Input>(
+        &self,
+        input: &mut Peekable<Input>,
+        dynamic: bool
+    ) -> Result<(EncodingModes, BitVec<u8, Msb0>, usize), EncodingError>
+    where
+        Input: Iterator<Item = (char, &'b DistToNextType)>,
+    {
+        let kanji = input.peeking_take_while(|&(c, _)| Self::is_qr_kanji(c));
+        let (encoded_kanji, char_count) = Self::kanji_bits(kanji.map(|(c, _)| c));
+
+        let next_mode = if let Some(&(c, _)) = input.peek() {
+            if !dynamic {
+                return Err(EncodingError::new("Need to change mode, but not dynamic"));
+            }
+            Self::fnc1_char_type(c, self.fnc1())
+        } else {
+            // Doesn't matter
+            EncodingModes::Kanji
+        };
+        Ok((next_mode, encoded_kanji, char_count))
+    }
+
+    // Each character is encoded in 13 bits
+    fn kanji_bits(kanji: impl Iterator<Item = char>) -> (BitVec<u8, Msb0>, usize) {
+        let mut char_count = 0usize;
+        let mut encoded_kanji = bitvec![u8, Msb0;];
+
+        for c in kanji {
+            char_count += 1;
+
+            let mut bits = bitarr![u16, Msb0; 0; 13];
+            bits[0..13].store(Self::qr_kanji_value(c));
+            encoded_kanji.extend_from_bitslice(&bits[0..13]);
+        }
+        (encoded_kanji, char_count)
+    }
+
     fn sequence_preamble(&self, encoding: EncodingModes, char_count: usize) -> BitVec<u8, Msb0> {
         let mut sequence_preamble = bitvec![u8, Msb0;];
 
@@ -420,24 +727,14 @@ impl<'a> Encoder<'a> {
         };
 
         let len_indicator_len: usize = match self.generator.options.qr_type {
-            Some(QRSymbolTypes::MicroQRCode) => {
-                (match encoding {
-                    EncodingModes::Numeric => 2 + self.generator.options.version.unwrap(),
-                    EncodingModes::AlphaNumeric => 1 + self.generator.options.version.unwrap(),
-                    EncodingModes::Byte => 1 + self.generator.options.version.unwrap(),
-                    EncodingModes::Kanji => self.generator.options.version.unwrap(),
-                    _ => unreachable!(),
-                }) as usize
-            }
-            Some(QRSymbolTypes::QRCode) => *match encoding {
-                EncodingModes::Numeric => HashMap::from([(9, 10), (26, 12), (40, 14)]),
-                EncodingModes::AlphaNumeric => HashMap::from([(9, 9), (26, 11), (40, 13)]),
-                EncodingModes::Byte => HashMap::from([(9, 8), (26, 16), (40, 16)]),
-                EncodingModes::Kanji => HashMap::from([(9, 8), (26, 10), (40, 12)]),
-                _ => unreachable!(),
+            Some(QRSymbolTypes::MicroQRCode) => Self::character_count_bits(
+                &QRSymbolTypes::MicroQRCode,
+                self.generator.options.version.unwrap(),
+                encoding,
+            ),
+            Some(QRSymbolTypes::QRCode) => {
+                Self::character_count_bits(&QRSymbolTypes::QRCode, self.size_estimate, encoding)
             }
-            .get(&self.size_estimate)
-            .unwrap() as usize,
             _ => unreachable!(),
         };
         let mut len_indicator = bitvec![u16, Msb0; 0; len_indicator_len];
@@ -447,6 +744,41 @@ impl<'a> Encoder<'a> {
         sequence_preamble
     }
 
+    pub(crate) fn mode_indicator_bits(qr_type: &QRSymbolTypes, version: u32) -> usize {
+        match qr_type {
+            QRSymbolTypes::MicroQRCode => (version - 1) as usize,
+            QRSymbolTypes::QRCode => 4,
+        }
+    }
+
+    pub(crate) fn character_count_bits(qr_type: &QRSymbolTypes, version: u32, encoding: EncodingModes) -> usize {
+        match qr_type {
+            QRSymbolTypes::MicroQRCode => {
+                (match encoding {
+                    EncodingModes::Numeric => 2 + version,
+                    EncodingModes::AlphaNumeric => 1 + version,
+                    EncodingModes::Byte => 1 + version,
+                    EncodingModes::Kanji => version,
+                    _ => unreachable!(),
+                }) as usize
+            }
+            QRSymbolTypes::QRCode => {
+                let widths = match encoding {
+                    EncodingModes::Numeric => [10, 12, 14],
+                    EncodingModes::AlphaNumeric => [9, 11, 13],
+                    EncodingModes::Byte => [8, 16, 16],
+                    EncodingModes::Kanji => [8, 10, 12],
+                    _ => unreachable!(),
+                };
+                match version {
+                    (0..=9) => widths[0],
+                    (10..=26) => widths[1],
+                    (27..) => widths[2],
+                }
+            }
+        }
+    }
+
     fn terminator(&self) -> BitVec<u8, Msb0> {
         let terminator_len = match self.generator.options.qr_type {
             Some(QRSymbolTypes::MicroQRCode) => match self.generator.options.version {
@@ -460,12 +792,16 @@ impl<'a> Encoder<'a> {
             _ => unreachable!(),
         };
 
+        // The terminator is truncated if the data already (almost) fills the symbol
+        let terminator_len = terminator_len.min(self.data_bit_capacity().saturating_sub(self.output_data.len()));
+
         bitvec![u8, Msb0; 0; terminator_len]
     }
 
     fn padding_to_codeword_boundary(&self) -> BitVec<u8, Msb0> {
         let remainder = self.output_data.len() % 8;
-        let pad_len = 8 - remainder;
+        // Don't pad past the end of the final 4-bit codeword in an M1 or M3 code
+        let pad_len = ((8 - remainder) % 8).min(self.data_bit_capacity().saturating_sub(self.output_data.len()));
 
         bitvec![u8, Msb0; 0; pad_len]
     }
@@ -476,33 +812,38 @@ impl<'a> Encoder<'a> {
             self.generator.options.version.unwrap(),
             self.generator.options.correction_level.as_ref().unwrap(),
         );
+        let bit_capacity = self.data_bit_capacity();
 
-        // Allow up to 4 bits more than the data capacity - that would just indicate a complete-full M1 or M3 code
-        if self.output_data.len() > (total_codewords * 8) + 4 {
+        if self.output_data.len() > bit_capacity {
             return Err(EncodingError::new("Too much data for specified code size."));
         }
 
-        let padding_amount = total_codewords - self.output_data.len() / 8;
+        let padding_amount = bit_capacity / 8 - self.output_data.len() / 8;
         let mut padding_codewords = bitvec![u8, Msb0;];
         for pad in 0..padding_amount {
-            if pad == padding_amount - 1
-                && self.generator.options.qr_type == Some(QRSymbolTypes::MicroQRCode)
-                && (self.generator.options.version == Some(1)
-                    || self.generator.options.version == Some(3))
-            {
-                padding_codewords.append(&mut bitvec![u8, Msb0; 0, 0, 0, 0]);
-            } else {
-                padding_codewords.append(&mut match pad % 2 {
-                    0 => bitvec![u8, Msb0; 1, 1, 1, 0, 1, 1, 0, 0],
-                    1 => bitvec![u8, Msb0; 0, 0, 0, 1, 0, 0, 0, 1],
-                    _ => unreachable!(),
-                });
-            }
+            padding_codewords.append(&mut match pad % 2 {
+                0 => bitvec![u8, Msb0; 1, 1, 1, 0, 1, 1, 0, 0],
+                1 => bitvec![u8, Msb0; 0, 0, 0, 1, 0, 0, 0, 1],
+                _ => unreachable!(),
+            });
         }
 
+        // In M1 and M3 codes, the final data codeword is only 4 bits long, and is padded with 0000 if
+        // unused. Fill out the rest of its byte with zeros, ready for error correction.
+        let full_length = self.output_data.len() + padding_codewords.len();
+        padding_codewords.append(&mut bitvec![u8, Msb0; 0; total_codewords * 8 - full_length]);
+
         Ok(padding_codewords)
     }
 
+    fn data_bit_capacity(&self) -> usize {
+        Sizer::data_bit_capacity(
+            self.generator.options.qr_type.as_ref().unwrap(),
+            self.generator.options.version.unwrap(),
+            self.generator.options.correction_level.as_ref().unwrap(),
+        )
+    }
+
     // Get a rough guess of how large a QR-code this will be. It doesn't need to be exact -
     // we only care about the thresholds <=9, <=26 and over. Assume we're going to use Byte
     // mode.
@@ -517,9 +858,13 @@ impl<'a> Encoder<'a> {
                     None | Some(CorrectionLevels::DetectionOnly) => unreachable!(),
                 };
 
-            if (small_st..=small_end).contains(&self.input_data.len()) {
+            let data_len = match &self.input_segments {
+                Some(segments) => segments.iter().map(Segment::char_count).sum(),
+                None => self.input_data.len(),
+            };
+            if (small_st..=small_end).contains(&data_len) {
                 9
-            } else if (med_st..=med_end).contains(&self.input_data.len()) {
+            } else if (med_st..=med_end).contains(&data_len) {
                 26
             } else {
                 40
@@ -533,12 +878,17 @@ impl<'a> Encoder<'a> {
         };
     }
 
+    #[cfg(test)]
     fn calculate_change_distances(input_data: &String) -> Vec<DistToNextType> {
+        Self::calculate_fnc1_change_distances(input_data, false)
+    }
+
+    fn calculate_fnc1_change_distances(input_data: &str, fnc1: bool) -> Vec<DistToNextType> {
         let mut byte_rindex: Option<usize> = None;
-        // let mut kanji_rindex: Option<usize> = None; // Not supporting Kanji
+        let mut kanji_rindex: Option<usize> = None;
         let mut alphanum_rindex: Option<usize> = None;
         let mut numeric_rindex: Option<usize> = None;
-        let input_len = input_data.len();
+        let input_len = input_data.chars().count();
         let mut distances = vec![
             DistToNextType {
                 ..Default::default()
@@ -547,21 +897,23 @@ impl<'a> Encoder<'a> {
         ];
 
         for (from_end, c) in input_data.chars().rev().enumerate() {
-            match Self::char_type(c) {
+            match Self::fnc1_char_type(c, fnc1) {
                 CharacterTypes::Byte => byte_rindex = Some(from_end),
+                CharacterTypes::Kanji => kanji_rindex = Some(from_end),
                 CharacterTypes::AlphaNumeric => alphanum_rindex = Some(from_end),
                 CharacterTypes::Numeric => numeric_rindex = Some(from_end),
                 _ => unreachable!(),
             };
 
             let byte = byte_rindex.map(|rix| from_end - rix);
+            let kanji = kanji_rindex.map(|rix| from_end - rix);
             let alpha_numeric = alphanum_rindex.map(|rix| from_end - rix);
             let numeric = numeric_rindex.map(|rix| from_end - rix);
 
             distances[input_len - from_end - 1] = DistToNextType {
                 numeric,
                 alpha_numeric,
-                kanji: None,
+                kanji,
                 byte,
                 end: from_end + 1
             };
@@ -574,6 +926,43 @@ impl<'a> Encoder<'a> {
         ('0'..='9').contains(&c) || ('A'..='Z').contains(&c) || " $%*+-./:".contains(c)
     }
 
+    // In FNC1 modes the group separator can be encoded in alphanumeric mode, as %
+    pub(crate) fn is_fnc1_alphanumeric(c: char, fnc1: bool) -> bool {
+        Self::is_qr_alphanumeric(c) || (fnc1 && c == GROUP_SEPARATOR)
+    }
+
+    fn fnc1_char_type(c: char, fnc1: bool) -> CharacterTypes {
+        if fnc1 && c == GROUP_SEPARATOR {
+            CharacterTypes::AlphaNumeric
+        } else {
+            Self::char_type(c)
+        }
+    }
+
+    fn fnc1(&self) -> bool {
+        self.generator.options.fnc1.is_some()
+    }
+
+    // FNC1 in first position (GS1) is 0101; in second position (AIM) it's 1001 followed by the
+    // application indicator
+    fn fnc1_indicator(fnc1: Fnc1Modes) -> Result<BitVec<u8, Msb0>, EncodingError> {
+        match fnc1 {
+            Fnc1Modes::First => Ok(bitvec![u8, Msb0; 0, 1, 0, 1]),
+            Fnc1Modes::Second(indicator) => {
+                if !matches!(indicator, 0..=99 | 165..=190 | 197..=222) {
+                    return Err(EncodingError::new(
+                        "FNC1 application indicators must be 00-99, or a letter's ASCII value + 100",
+                    ));
+                }
+                let mut bits = bitvec![u8, Msb0; 1, 0, 0, 1];
+                let mut indicator_bits = bitarr![u8, Msb0; 0; 8];
+                indicator_bits[0..8].store(indicator);
+                bits.extend_from_bitslice(&indicator_bits[0..8]);
+                Ok(bits)
+            }
+        }
+    }
+
     fn qr_alphanumeric_value(c: char) -> u32 {
         match c {
             ('0'..='9') | ('A'..='Z') => c.to_digit(36).unwrap() as u32,
@@ -590,11 +979,37 @@ impl<'a> Encoder<'a> {
         }
     }
 
-    fn char_type(c: char) -> CharacterTypes {
+    // Kanji mode covers the double-byte Shift-JIS ranges 0x8140-0x9FFC and 0xE040-0xEBBF
+    pub(crate) fn is_qr_kanji(c: char) -> bool {
+        matches!(Self::shift_jis_value(c), Some(0x8140..=0x9FFC) | Some(0xE040..=0xEBBF))
+    }
+
+    fn qr_kanji_value(c: char) -> u16 {
+        let shift_jis = Self::shift_jis_value(c).expect("Non-kanji character in kanji run");
+        let offset = match shift_jis {
+            0x8140..=0x9FFC => shift_jis - 0x8140,
+            0xE040..=0xEBBF => shift_jis - 0xC140,
+            _ => unreachable!("Non-kanji character in kanji run")
+        };
+        (offset >> 8) * 0xC0 + (offset & 0xFF)
+    }
+
+    fn shift_jis_value(c: char) -> Option<u16> {
+        let mut char_space = [0; 4];
+        let (bytes, _, unmappable) = SHIFT_JIS.encode(c.encode_utf8(&mut char_space));
+        if unmappable || bytes.len() != 2 {
+            return None;
+        }
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(crate) fn char_type(c: char) -> CharacterTypes {
         if c.is_ascii_digit() {
             CharacterTypes::Numeric
         } else if Self::is_qr_alphanumeric(c) {
             CharacterTypes::AlphaNumeric
+        } else if Self::is_qr_kanji(c) {
+            CharacterTypes::Kanji
         } else {
             CharacterTypes::Byte
         }
@@ -662,6 +1077,245 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn cannot_use_unsupported_correction_level_in_micro_qr() {
+        let generator = QRGenerator {
+            options: Options {
+                qr_type: Some(QRSymbolTypes::MicroQRCode),
+                version: Some(2),
+                correction_level: Some(CorrectionLevels::Q),
+                ..Default::default()
+            },
+        };
+        let encoder = Encoder::new(&generator, "123".to_string());
+        let result = encoder.validate_data_stream_vs_options();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn encodes_m1_micro_qr_with_short_final_codeword() {
+        let generator = QRGenerator {
+            options: Options {
+                qr_type: Some(QRSymbolTypes::MicroQRCode),
+                version: Some(1),
+                correction_level: Some(CorrectionLevels::DetectionOnly),
+                ..Default::default()
+            },
+        };
+        let mut encoder = Encoder::new(&generator, "12345".to_string());
+        encoder.encode_data_into_byte_stream().unwrap();
+
+        // 3-bit count, 10 + 7 bits of data, no room for a terminator; the final codeword is 4 bits
+        assert_eq!(encoder.output_data.into_vec(), vec![0b10100011, 0b11011010, 0b11010000]);
+    }
+
+    #[test]
+    fn encodes_latin1_bytes_without_eci() {
+        let generator = QRGenerator {
+            options: Options {
+                qr_type: Some(QRSymbolTypes::QRCode),
+                version: Some(1),
+                mode: Some(EncodingModes::Byte),
+                correction_level: Some(CorrectionLevels::H),
+                ..Default::default()
+            },
+        };
+        let mut encoder = Encoder::new(&generator, "é".to_string());
+        encoder.encode_data_into_byte_stream().unwrap();
+
+        // 0100 00000001 11101001 0000
+        assert_eq!(encoder.output_data.into_vec()[0..3], [0b01000000, 0b00011110, 0b10010000]);
+    }
+
+    #[test]
+    fn adds_utf8_eci_when_not_latin1() {
+        let generator = QRGenerator {
+            options: Options {
+                qr_type: Some(QRSymbolTypes::QRCode),
+                version: Some(1),
+                correction_level: Some(CorrectionLevels::H),
+                ..Default::default()
+            },
+        };
+        let mut encoder = Encoder::new(&generator, "€".to_string());
+        encoder.encode_data_into_byte_stream().unwrap();
+
+        // 0111 00011010, then 0100 00000011 and E2 82 AC
+        assert_eq!(
+            encoder.output_data.into_vec()[0..6],
+            [0b01110001, 0b10100100, 0b00000011, 0xE2, 0x82, 0xAC]
+        );
+    }
+
+    #[test]
+    fn encodes_with_requested_eci() {
+        let generator = QRGenerator {
+            options: Options {
+                qr_type: Some(QRSymbolTypes::QRCode),
+                version: Some(1),
+                correction_level: Some(CorrectionLevels::H),
+                eci: Some(CharacterSets::Iso8859_15),
+                ..Default::default()
+            },
+        };
+        let mut encoder = Encoder::new(&generator, "€".to_string());
+        encoder.encode_data_into_byte_stream().unwrap();
+
+        // 0111 00010001, then 0100 00000001 10100100
+        assert_eq!(
+            encoder.output_data.into_vec()[0..4],
+            [0b01110001, 0b00010100, 0b00000001, 0b10100100]
+        );
+    }
+
+    #[test]
+    fn cannot_encode_characters_outside_requested_eci() {
+        let generator = QRGenerator {
+            options: Options {
+                qr_type: Some(QRSymbolTypes::QRCode),
+                version: Some(1),
+                correction_level: Some(CorrectionLevels::H),
+                eci: Some(CharacterSets::Iso8859_5),
+                ..Default::default()
+            },
+        };
+        let mut encoder = Encoder::new(&generator, "é".to_string());
+        assert!(encoder.encode_data_into_byte_stream().is_err());
+    }
+
+    #[test]
+    fn cannot_use_eci_in_micro_qr() {
+        let options = Options {
+            qr_type: Some(QRSymbolTypes::MicroQRCode),
+            ..Default::default()
+        };
+        assert!(Encoder::select_character_set(&options, "€").is_err());
+        assert_eq!(Encoder::select_character_set(&options, "é").unwrap(), None);
+    }
+
+    #[test]
+    fn encodes_structured_append_header() {
+        let generator = QRGenerator {
+            options: Options {
+                qr_type: Some(QRSymbolTypes::QRCode),
+                version: Some(1),
+                mode: Some(EncodingModes::Numeric),
+                correction_level: Some(CorrectionLevels::H),
+                ..Default::default()
+            },
+        };
+        let mut encoder = Encoder::new(&generator, "1".to_string());
+        encoder.set_structured_append(2, 4, 0xA5);
+        encoder.encode_data_into_byte_stream().unwrap();
+
+        // 0011 0010 0011 10100101, then the numeric segment
+        assert_eq!(encoder.output_data.into_vec()[0..3], [0b00110010, 0b00111010, 0b01010001]);
+    }
+
+    #[test]
+    fn cannot_append_more_than_16_symbols() {
+        assert!(Encoder::structured_append_header(0, 17, 0).is_err());
+        assert!(Encoder::structured_append_header(3, 3, 0).is_err());
+    }
+
+    #[test]
+    fn structured_append_parity_covers_encoded_bytes() {
+        let options = Options::default();
+        assert_eq!(Encoder::structured_append_parity(&options, "AB"), 0x41 ^ 0x42);
+        assert_eq!(Encoder::structured_append_parity(&options, "é点"), 0xE9 ^ 0x93 ^ 0x5F);
+    }
+
+    #[test]
+    fn escapes_percent_and_group_separator_in_fnc1_alphanumeric() {
+        let generator = QRGenerator {
+            options: Options {
+                qr_type: Some(QRSymbolTypes::QRCode),
+                version: Some(1),
+                mode: Some(EncodingModes::AlphaNumeric),
+                correction_level: Some(CorrectionLevels::H),
+                fnc1: Some(Fnc1Modes::First),
+                ..Default::default()
+            },
+        };
+        let mut encoder = Encoder::new(&generator, "A%\u{1D}".to_string());
+        encoder.encode_data_into_byte_stream().unwrap();
+
+        // 0101, then 0010 000000100 and "A%", "%%" (the escaped literal %, then FNC1)
+        assert_eq!(
+            encoder.output_data[0..39],
+            bits![u8, Msb0; 0, 1, 0, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0,
+                0, 0, 1, 1, 1, 1, 0, 1, 0, 0, 0, 1, 1, 0, 1, 1, 0, 1, 0, 1, 0, 0]
+        );
+    }
+
+    #[test]
+    fn encodes_fnc1_second_position_with_application_indicator() {
+        let generator = QRGenerator {
+            options: Options {
+                qr_type: Some(QRSymbolTypes::QRCode),
+                version: Some(1),
+                mode: Some(EncodingModes::Byte),
+                correction_level: Some(CorrectionLevels::H),
+                fnc1: Some(Fnc1Modes::Second(b'a' + 100)),
+                ..Default::default()
+            },
+        };
+        let mut encoder = Encoder::new(&generator, "%\u{1D}".to_string());
+        encoder.encode_data_into_byte_stream().unwrap();
+
+        // 1001 11000101, then 0100 00000010 and the bytes unescaped
+        assert_eq!(
+            encoder.output_data.into_vec()[0..5],
+            [0b10011100, 0b01010100, 0b00000010, 0b00100101, 0b00011101]
+        );
+    }
+
+    #[test]
+    fn cannot_use_invalid_application_indicator() {
+        assert!(Encoder::fnc1_indicator(Fnc1Modes::Second(100)).is_err());
+        assert!(Encoder::fnc1_indicator(Fnc1Modes::Second(99)).is_ok());
+    }
+
+    #[test]
+    fn encodes_m2_micro_qr_as_iso_example() {
+        // Example from ISO 18004 Annex I.3
+        let generator = QRGenerator {
+            options: Options {
+                qr_type: Some(QRSymbolTypes::MicroQRCode),
+                version: Some(2),
+                correction_level: Some(CorrectionLevels::L),
+                ..Default::default()
+            },
+        };
+        let mut encoder = Encoder::new(&generator, "01234567".to_string());
+        encoder.encode_data_into_byte_stream().unwrap();
+
+        assert_eq!(
+            encoder.output_data.into_vec(),
+            vec![0b01000000, 0b00011000, 0b10101100, 0b11000011, 0b00000000]
+        );
+    }
+
+    #[test]
+    fn pads_m3_micro_qr_with_half_codeword() {
+        let generator = QRGenerator {
+            options: Options {
+                qr_type: Some(QRSymbolTypes::MicroQRCode),
+                version: Some(3),
+                correction_level: Some(CorrectionLevels::M),
+                ..Default::default()
+            },
+        };
+        let mut encoder = Encoder::new(&generator, "1".to_string());
+        encoder.encode_data_into_byte_stream().unwrap();
+
+        // 2-bit mode, 5-bit count, 4 bits of data, 7-bit terminator, then pad codewords and a 0000 final codeword
+        assert_eq!(
+            encoder.output_data.into_vec(),
+            vec![0b00000010, 0b00100000, 0b00000000, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x00]
+        );
+    }
+
     #[test]
     fn cannot_encode_non_numeric_in_numeric_mode() {
         let generator = QRGenerator {
@@ -946,7 +1600,7 @@ mod tests {
             .chars()
             .zip(encoder.change_distances.iter())
             .peekable();
-        let (_, encoded_run, _) = Encoder::encode_numeric_run(&mut input);
+        let (_, encoded_run, _) = encoder.encode_numeric_run(&mut input, false).unwrap();
 
         assert_eq!(
             encoded_run.as_bitslice(),
@@ -954,6 +1608,81 @@ mod tests {
         );
     }
 
+    #[test]
+    fn encodes_kanji_run_correctly() {
+        // Example from ISO 18004 7.4.6: 点 (0x935F) and 茗 (0xE4AA)
+        let generator = QRGenerator::default();
+        let mut encoder = Encoder::new(&generator, "点茗".to_string());
+        encoder.change_distances = Encoder::calculate_change_distances(&encoder.input_data);
+        let mut input = encoder
+            .input_data
+            .chars()
+            .zip(encoder.change_distances.iter())
+            .peekable();
+        let (_, encoded_run, char_count) = encoder.encode_kanji_run(&mut input, false).unwrap();
+
+        assert_eq!(char_count, 2);
+        assert_eq!(
+            encoded_run.as_bitslice(),
+            bits![u8, Msb0; 0, 1, 1, 0, 1, 1, 0, 0, 1, 1, 1, 1, 1, 1, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0]
+        );
+    }
+
+    #[test]
+    fn when_first_character_kanji_starts_in_kanji() {
+        let generator = QRGenerator::default();
+        let mut encoder = Encoder::new(&generator, "点茗ABC".to_string());
+        encoder.change_distances = Encoder::calculate_change_distances(&encoder.input_data);
+        let mode = encoder.select_initial_encoding();
+        assert_eq!(mode, EncodingModes::Kanji);
+    }
+
+    #[test]
+    fn kanji_change_distances_count_characters_not_bytes() {
+        let distances = Encoder::calculate_change_distances(&"A点".to_string());
+        assert_eq!(
+            &distances[..],
+            &[
+                DistToNextType {
+                    byte: None,
+                    alpha_numeric: Some(0),
+                    numeric: None,
+                    kanji: Some(1),
+                    end: 2
+                },
+                DistToNextType {
+                    byte: None,
+                    alpha_numeric: None,
+                    numeric: None,
+                    kanji: Some(0),
+                    end: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn cannot_encode_non_kanji_in_kanji_mode() {
+        let generator = QRGenerator {
+            options: Options {
+                mode: Some(EncodingModes::Kanji),
+                ..Default::default()
+            },
+        };
+        let encoder = Encoder::new(&generator, "点A".to_string());
+        let result = encoder.validate_data_stream_vs_options();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn kanji_set_excludes_half_width_and_unmapped_characters() {
+        assert!(Encoder::is_qr_kanji('点'));
+        assert!(Encoder::is_qr_kanji('あ'));
+        assert!(!Encoder::is_qr_kanji('ｱ'));
+        assert!(!Encoder::is_qr_kanji('A'));
+        assert!(!Encoder::is_qr_kanji('é'));
+    }
+
     #[test]
     fn alphanumeric_includes_all_legit_characters() {
         let string = "A B$C%0.1/2*X+Y-Z:";
@@ -964,4 +1693,109 @@ mod tests {
         let string = "abcpqr";
         assert!(string.chars().all(|c| !Encoder::is_qr_alphanumeric(c)));
     }
+
+    #[test]
+    fn dynamic_mode_encodes_optimal_segments() {
+        let generator = QRGenerator {
+            options: Options {
+                qr_type: Some(QRSymbolTypes::QRCode),
+                version: Some(1),
+                correction_level: Some(CorrectionLevels::Q),
+                ..Default::default()
+            },
+        };
+        let mut encoder = Encoder::new(&generator, "a0123456789012".to_string());
+        encoder.encode_data_into_byte_stream().unwrap();
+
+        // 0100 00000001 01100001, then 0001 0000001101 and the digits
+        assert_eq!(encoder.output_data.into_vec()[0..4], [0b01000000, 0b00010110, 0b00010001, 0b00000011]);
+    }
+
+    #[test]
+    fn sizes_dynamic_data_by_optimal_segmentation() {
+        let options = Options {
+            qr_type: Some(QRSymbolTypes::QRCode),
+            correction_level: Some(CorrectionLevels::Q),
+            ..Default::default()
+        };
+        assert_eq!(Sizer::calculate_version(&options, &"0109501101530003".to_string()).unwrap(), 1);
+        // 131 bytes overflow version 9, and need the wider character count indicator of version 10
+        assert_eq!(Sizer::calculate_version(&options, &"a".repeat(131)).unwrap(), 10);
+        let options = Options { segmentation: Some(SegmentationStrategies::AnnexJ), ..options };
+        assert_eq!(Sizer::calculate_version(&options, &"0109501101530003".to_string()).unwrap(), 2);
+    }
+
+    #[test]
+    fn encodes_binary_data_as_one_byte_segment() {
+        let generator = QRGenerator {
+            options: Options {
+                qr_type: Some(QRSymbolTypes::QRCode),
+                version: Some(1),
+                correction_level: Some(CorrectionLevels::H),
+                ..Default::default()
+            },
+        };
+        let mut encoder = Encoder::from_segments(&generator, vec![Segment::Byte(vec![0x00, 0xFF, 0x1D])]);
+        encoder.encode_data_into_byte_stream().unwrap();
+
+        // 0100 00000011 00000000 11111111 00011101 0000
+        assert_eq!(encoder.output_data.into_vec()[0..5], [0x40, 0x30, 0x0F, 0xF1, 0xD0]);
+    }
+
+    #[test]
+    fn cannot_encode_binary_data_outside_byte_mode() {
+        let generator = QRGenerator {
+            options: Options {
+                qr_type: Some(QRSymbolTypes::QRCode),
+                version: Some(1),
+                mode: Some(EncodingModes::Numeric),
+                correction_level: Some(CorrectionLevels::H),
+                ..Default::default()
+            },
+        };
+        let mut encoder = Encoder::from_segments(&generator, vec![Segment::Byte(b"123".to_vec())]);
+        assert!(encoder.encode_data_into_byte_stream().is_err());
+    }
+
+    #[test]
+    fn sizes_byte_data_by_encoded_length() {
+        let options = Options {
+            qr_type: Some(QRSymbolTypes::MicroQRCode),
+            mode: Some(EncodingModes::Byte),
+            correction_level: Some(CorrectionLevels::L),
+            ..Default::default()
+        };
+        // Nine ISO-8859-1 bytes, though eighteen in UTF-8
+        assert_eq!(Sizer::calculate_version(&options, &"é".repeat(9)).unwrap(), 3);
+        let segments = vec![Segment::Byte("é".repeat(5).into_bytes())];
+        assert_eq!(Sizer::calculate_segments_version(&options, &segments).unwrap(), 4);
+    }
+
+    #[test]
+    fn encodes_segments_exactly_as_given() {
+        let generator = QRGenerator {
+            options: Options {
+                qr_type: Some(QRSymbolTypes::QRCode),
+                version: Some(1),
+                correction_level: Some(CorrectionLevels::M),
+                ..Default::default()
+            },
+        };
+        // The ISO/IEC 18004 example: 0001 0000001000 0000001100 0101011001 1000011, then 0000 and padding
+        let mut encoder = Encoder::from_segments(&generator, vec![Segment::Numeric("01234567".to_string())]);
+        encoder.encode_data_into_byte_stream().unwrap();
+        assert_eq!(
+            encoder.output_data.into_vec(),
+            [0x10, 0x20, 0x0C, 0x56, 0x61, 0x80, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11]
+        );
+
+        // Even where another mode would be shorter
+        let mut encoder = Encoder::from_segments(
+            &generator,
+            vec![Segment::Byte(b"1".to_vec()), Segment::ECI(26), Segment::AlphaNumeric("A".to_string())],
+        );
+        encoder.encode_data_into_byte_stream().unwrap();
+        // 0100 00000001 00110001, 0111 00011010, 0010 000000001 001010
+        assert_eq!(encoder.output_data.into_vec()[0..6], [0x40, 0x13, 0x17, 0x1A, 0x20, 0x09]);
+    }
 }
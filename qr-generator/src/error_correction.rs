@@ -1,6 +1,6 @@
 use reed_solomon::{Polynomial, IntMod, ReedSolomonEncoder, GaloisField};
 
-use crate::qr_errors::EncodingError;
+use crate::qr_errors::{DecodingError, EncodingError};
 
 #[derive(PartialEq, PartialOrd, Eq, Ord, Debug, Clone, Copy)]
 pub enum CorrectionLevels {
@@ -67,9 +67,72 @@ impl ErrorCorrector {
         }
     }
 
+    // The block each data codeword of the interleaved sequence belongs to. Blocks take turns, skipping
+    // the shorter blocks once they run out.
+    pub fn data_codeword_blocks(&self) -> Vec<usize> {
+        let longest = self.blocks.iter().map(|block| block.data_codeword_count).max().unwrap_or(0);
+        (0..longest)
+            .flat_map(|word| {
+                self.blocks
+                    .iter()
+                    .enumerate()
+                    .filter(move |(_, block)| word < block.data_codeword_count)
+                    .map(|(number, _)| number)
+            })
+            .collect()
+    }
+
+    // As data_codeword_blocks, for the error correction codewords. Every block has the same number.
+    pub fn ec_codeword_blocks(&self) -> Vec<usize> {
+        let ec_count = self.blocks.iter().map(|block| block.error_correction_codeword_count).sum::<usize>();
+        (0..ec_count).map(|index| index % self.blocks.len()).collect()
+    }
+
+    pub fn ec_codeword_counts(&self) -> Vec<usize> {
+        self.blocks.iter().map(|block| block.error_correction_codeword_count).collect()
+    }
+
     pub fn interleave(&self) -> BlockInterleaver {
         BlockInterleaver::new(self)
     }
+
+    // Splits a codeword sequence read from a symbol back into its blocks, undoing interleave
+    pub fn deinterleave(&mut self, message: &[u8]) -> Result<(), DecodingError> {
+        let data_blocks = self.data_codeword_blocks();
+        let ec_blocks = self.ec_codeword_blocks();
+        if message.len() != data_blocks.len() + ec_blocks.len() {
+            return Err(DecodingError::new(&format!(
+                "Expected {} codewords in the symbol, found {}",
+                data_blocks.len() + ec_blocks.len(),
+                message.len()
+            )));
+        }
+
+        for block in self.blocks.iter_mut() {
+            block.data_codewords.clear();
+            block.ec_codewords.clear();
+        }
+        let (data_codewords, ec_codewords) = message.split_at(data_blocks.len());
+        for (&codeword, &block) in data_codewords.iter().zip(&data_blocks) {
+            self.blocks[block].data_codewords.push(codeword);
+        }
+        for (&codeword, &block) in ec_codewords.iter().zip(&ec_blocks) {
+            self.blocks[block].ec_codewords.push(codeword);
+        }
+        Ok(())
+    }
+
+    pub fn correct_errors(&mut self) -> Result<(), DecodingError> {
+        for ec_block in self.blocks.iter_mut() {
+            ec_block.correct_errors()?;
+        }
+        Ok(())
+    }
+
+    // The data codewords of every block, in order, as they were before interleaving
+    pub fn data_codewords(&self) -> Vec<u8> {
+        self.blocks.iter().flat_map(|block| block.data_codewords.iter().copied()).collect()
+    }
 }
 
 impl ErrorCorrectionBlock {
@@ -82,6 +145,19 @@ impl ErrorCorrectionBlock {
         let encoded_polys: Vec<Element> = rs.encode(block_polys, self.error_correction_codeword_count);
         self.ec_codewords = encoded_polys.into_iter().skip(self.data_codeword_count).map(|cw| u32::from(cw) as u8).collect();
     }
+
+    pub fn correct_errors(&mut self) -> Result<(), DecodingError> {
+        type Element = Polynomial<IntMod<2>>;
+        type GF256 = GaloisField<2, 8, 285, 2>;
+        let rs = ReedSolomonEncoder::<GF256>::new();
+
+        let block_polys = self.data_codewords.iter().chain(&self.ec_codewords).map(|&cw| Element::from(cw as u32)).collect::<Vec<Element>>();
+        let corrected = rs
+            .decode(block_polys, self.error_correction_codeword_count)
+            .map_err(|err| DecodingError::new(&format!("{err}")))?;
+        self.data_codewords = corrected.data.into_iter().map(|cw| u32::from(cw) as u8).collect();
+        Ok(())
+    }
 }
 
 enum BlockType {
@@ -153,4 +229,18 @@ impl<'a> Iterator for BlockInterleaver<'a> {
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sizer::Sizer, QRSymbolTypes};
+
+    #[test]
+    fn refuses_to_deinterleave_wrong_number_of_codewords() {
+        // 1-M has 16 data and 10 error correction codewords
+        let mut error_corrector = ErrorCorrector::from(&Sizer::error_correction_shape(&QRSymbolTypes::QRCode, 1, &CorrectionLevels::M));
+        assert!(error_corrector.deinterleave(&[0; 26]).is_ok());
+        let error = error_corrector.deinterleave(&[0; 25]).unwrap_err();
+        assert_eq!(error.to_string(), "Expected 26 codewords in the symbol, found 25");
+    }
+}
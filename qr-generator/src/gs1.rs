@@ -0,0 +1,259 @@
+use crate::qr_errors::EncodingError;
+
+// Separates variable-length element strings in FNC1 data. Encoded as FNC1: % in alphanumeric mode, or
+// 0x1D in byte mode.
+pub const GROUP_SEPARATOR: char = '\u{1D}';
+
+// The data allowed after an Application Identifier: between min and max characters, the first
+// `numeric` of which must be digits (the rest from the GS1 character set)
+struct AIFormat {
+    min: usize,
+    max: usize,
+    numeric: usize,
+    check_digit: bool,
+    date: bool,
+}
+
+impl AIFormat {
+    const fn numeric(len: usize) -> Self {
+        Self { min: len, max: len, numeric: len, check_digit: false, date: false }
+    }
+    const fn numeric_up_to(max: usize) -> Self {
+        Self { min: 1, max, numeric: max, check_digit: false, date: false }
+    }
+    const fn alphanumeric_up_to(max: usize) -> Self {
+        Self { min: 1, max, numeric: 0, check_digit: false, date: false }
+    }
+    const fn gtin(len: usize) -> Self {
+        Self { min: len, max: len, numeric: len, check_digit: true, date: false }
+    }
+    const fn date() -> Self {
+        Self { min: 6, max: 6, numeric: 6, check_digit: false, date: true }
+    }
+}
+
+// Converts a human-readable GS1 element string, e.g. "(01)09501101530003(17)250101(10)AB-123", into
+// the form encoded after an FNC1 in first position: AIs followed by their data, with a group separator
+// after each variable-length element except the last. Brackets in the data are written \( and \).
+pub fn parse_element_string(input: &str) -> Result<String, EncodingError> {
+    let mut elements = Vec::<(&str, String)>::new();
+    let mut rest = input;
+    while !rest.is_empty() {
+        let ai_end = match (rest.strip_prefix('('), rest.find(')')) {
+            (Some(_), Some(ai_end)) => ai_end,
+            _ => return Err(EncodingError::new("GS1 element strings must be of the form (AI)data(AI)data...")),
+        };
+        let ai = &rest[1..ai_end];
+        if !(2..=4).contains(&ai.len()) || !ai.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(EncodingError::new(&format!(
+                "GS1 Application Identifiers are 2-4 digits, not ({}). Write brackets in the data as \\( and \\)",
+                ai
+            )));
+        }
+
+        let mut data = String::new();
+        let mut chars = rest[ai_end + 1..].chars();
+        loop {
+            let remaining = chars.as_str();
+            match chars.next() {
+                None | Some('(') => {
+                    rest = remaining;
+                    break;
+                }
+                Some('\\') if remaining[1..].starts_with(['(', ')']) => data.extend(chars.next()),
+                Some(c) => data.push(c),
+            }
+        }
+        elements.push((ai, data));
+    }
+    if elements.is_empty() {
+        return Err(EncodingError::new("No GS1 elements to encode"));
+    }
+
+    let mut element_string = String::new();
+    for (index, (ai, data)) in elements.iter().enumerate() {
+        validate_element(ai, data)?;
+        element_string.push_str(ai);
+        element_string.push_str(data);
+        if index < elements.len() - 1 && !has_predefined_length(ai) {
+            element_string.push(GROUP_SEPARATOR);
+        }
+    }
+
+    Ok(element_string)
+}
+
+fn validate_element(ai: &str, data: &str) -> Result<(), EncodingError> {
+    let format = ai_format(ai).ok_or_else(|| {
+        EncodingError::new(&format!("Unknown GS1 Application Identifier ({})", ai))
+    })?;
+
+    let len = data.chars().count();
+    if len < format.min || len > format.max {
+        return Err(EncodingError::new(&format!(
+            "GS1 AI ({}) takes {}-{} characters of data",
+            ai, format.min, format.max
+        )));
+    }
+    if data.chars().take(format.numeric).any(|c| !c.is_ascii_digit()) {
+        return Err(EncodingError::new(&format!("GS1 AI ({}) data must be numeric", ai)));
+    }
+    if data.chars().any(|c| !is_gs1_character(c)) {
+        return Err(EncodingError::new(&format!(
+            "GS1 AI ({}) data contains characters outside the GS1 character set",
+            ai
+        )));
+    }
+    if format.check_digit && !has_valid_check_digit(data) {
+        return Err(EncodingError::new(&format!("GS1 AI ({}) has an incorrect check digit", ai)));
+    }
+    if format.date && !is_valid_date(data) {
+        return Err(EncodingError::new(&format!("GS1 AI ({}) must be a YYMMDD date", ai)));
+    }
+
+    Ok(())
+}
+
+fn ai_format(ai: &str) -> Option<AIFormat> {
+    if !ai.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let format = match ai {
+        "00" => AIFormat::gtin(18),
+        "01" | "02" => AIFormat::gtin(14),
+        "10" | "21" | "22" | "254" | "420" => AIFormat::alphanumeric_up_to(20),
+        "11" | "12" | "13" | "15" | "16" | "17" => AIFormat::date(),
+        "20" => AIFormat::numeric(2),
+        "240" | "241" | "250" | "251" | "400" | "401" | "403" | "8004" | "90" => {
+            AIFormat::alphanumeric_up_to(30)
+        }
+        "253" => AIFormat { min: 13, max: 30, numeric: 13, check_digit: false, date: false },
+        "30" | "37" => AIFormat::numeric_up_to(8),
+        "402" => AIFormat::gtin(17),
+        "410" | "411" | "412" | "413" | "414" | "415" | "416" | "417" => AIFormat::gtin(13),
+        "421" => AIFormat { min: 4, max: 12, numeric: 3, check_digit: false, date: false },
+        "422" | "424" | "426" => AIFormat::numeric(3),
+        "8003" => AIFormat { min: 14, max: 30, numeric: 14, check_digit: false, date: false },
+        "8020" => AIFormat::alphanumeric_up_to(25),
+        "91" | "92" | "93" | "94" | "95" | "96" | "97" | "98" | "99" => AIFormat::alphanumeric_up_to(90),
+        // Trade measures, with the decimal point position as the last digit of the AI
+        _ if ai.len() == 4 && ("31"..="36").contains(&&ai[0..2]) => AIFormat::numeric(6),
+        _ if ai.len() == 4 && matches!(&ai[0..3], "390" | "392") => AIFormat::numeric_up_to(15),
+        _ if ai.len() == 4 && matches!(&ai[0..3], "391" | "393") => {
+            AIFormat { min: 4, max: 18, numeric: 18, check_digit: false, date: false }
+        }
+        _ => return None,
+    };
+    Some(format)
+}
+
+// Elements starting with these digits have a fixed length, so don't need a separator
+fn has_predefined_length(ai: &str) -> bool {
+    matches!(
+        &ai[0..2],
+        "00" | "01" | "02" | "03" | "04" | "11" | "12" | "13" | "14" | "15" | "16" | "17" | "18" | "19"
+            | "20" | "31" | "32" | "33" | "34" | "35" | "36" | "41"
+    )
+}
+
+// GS1 data is restricted to the invariant subset of ISO/IEC 646
+fn is_gs1_character(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!\"%&'()*+,-./:;<=>?_".contains(c)
+}
+
+// GS1 mod-10 check digit, weighting digits 3 and 1 alternately from the right
+fn has_valid_check_digit(data: &str) -> bool {
+    let digits = data.bytes().map(|b| (b - b'0') as u32).collect::<Vec<u32>>();
+    let (check, body) = digits.split_last().unwrap();
+    let sum: u32 = body
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(index, digit)| if index % 2 == 0 { digit * 3 } else { *digit })
+        .sum();
+    (10 - sum % 10) % 10 == *check
+}
+
+// YYMMDD, where a day of 00 means the end of the month. GS1 years run from 2000 to 2099 at the
+// latest, within which every fourth year is a leap year.
+fn is_valid_date(data: &str) -> bool {
+    let year = data[0..2].parse::<u32>().unwrap();
+    let month = data[2..4].parse::<u32>().unwrap();
+    let day = data[4..6].parse::<u32>().unwrap();
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 => 29,
+        2 => 28,
+        _ => return false,
+    };
+    day <= days_in_month
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separates_variable_length_elements() {
+        assert_eq!(
+            parse_element_string("(01)09501101530003(10)AB-123(17)250101").unwrap(),
+            "010950110153000310AB-123\u{1D}17250101"
+        );
+    }
+
+    #[test]
+    fn no_separator_after_last_element() {
+        assert_eq!(parse_element_string("(10)ABC(21)12345").unwrap(), "10ABC\u{1D}2112345");
+    }
+
+    #[test]
+    fn accepts_measure_ais() {
+        assert_eq!(parse_element_string("(3103)000189(21)X").unwrap(), "310300018921X");
+    }
+
+    #[test]
+    fn rejects_incorrect_check_digit() {
+        assert!(parse_element_string("(01)09501101530004").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_ai() {
+        assert!(parse_element_string("(89)123").is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_length_and_characters() {
+        assert!(parse_element_string("(01)0950110153000").is_err());
+        assert!(parse_element_string("(17)2513XX").is_err());
+        assert!(parse_element_string("(17)251301").is_err());
+        assert!(parse_element_string("(10)ABC#").is_err());
+    }
+
+    #[test]
+    fn rejects_days_past_the_end_of_the_month() {
+        assert!(parse_element_string("(17)250231").is_err());
+        assert!(parse_element_string("(17)250229").is_err());
+        assert!(parse_element_string("(17)250431").is_err());
+        assert!(parse_element_string("(17)240229").is_ok());
+        assert!(parse_element_string("(17)250430").is_ok());
+        assert!(parse_element_string("(17)250200").is_ok());
+    }
+
+    #[test]
+    fn reads_escaped_brackets_in_data() {
+        assert_eq!(parse_element_string("(10)AB\\(1\\)(21)X)").unwrap(), "10AB(1)\u{1D}21X)");
+        let error = parse_element_string("(10)AB(1)").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "GS1 Application Identifiers are 2-4 digits, not (1). Write brackets in the data as \\( and \\)"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_element_strings() {
+        assert!(parse_element_string("01095011015300003").is_err());
+        assert!(parse_element_string("(01").is_err());
+        assert!(parse_element_string("").is_err());
+    }
+}
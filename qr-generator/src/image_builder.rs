@@ -1,35 +1,53 @@
 use image::{GrayImage, ImageBuffer, Luma, imageops};
 use polynomial_arithmetic::{Polynomial, IntMod, One, Zero};
 
+use std::{cmp::Reverse, iter};
+
 use crate::{
     QRSymbolTypes,
-    qr_types::{QRSymbol, QRFactory, FinderLocations}, error_correction::CorrectionLevels
+    qr_types::{QRSymbol, QRFactory, FinderLocations}, error_correction::CorrectionLevels, sizer::Sizer,
+    qr_matrix::{Module, ModuleRoles, QRMatrix}, mask_scores::MaskScores, bit_matrix::BitMatrix
 };
+// Every mask pattern repeats after 12 rows and columns, the period of (i / 2 + j / 3) % 2 and of i * j mod 6
+const MASK_PERIOD: usize = 12;
+
 pub struct ImageBuilder<'a> {
+    qr_type: QRSymbolTypes,
     qr_code: Box<dyn QRSymbol>,
     message: &'a Vec<u8>,
     loud_region: Option<GrayImage>,
-    correction_level: CorrectionLevels
+    correction_level: CorrectionLevels,
+    mask: u8,
+    // A mask to use regardless of how it scores
+    forced_mask: Option<u8>,
+    mask_scores: Vec<MaskScores>,
+    // The role of each module, row by row, kept alongside the image as the patterns are placed
+    roles: Vec<ModuleRoles>
 }
 
 impl<'a> ImageBuilder<'a> {
-    pub fn new(qr_type: QRSymbolTypes, version: u32, message: &'a Vec<u8>, correction_level: CorrectionLevels) -> Self {
+    pub fn new(
+        qr_type: QRSymbolTypes,
+        version: u32,
+        message: &'a Vec<u8>,
+        correction_level: CorrectionLevels,
+        forced_mask: Option<u8>
+    ) -> Self {
         Self {
+            qr_type,
             qr_code: QRFactory::build_code(qr_type, version),
             message,
             loud_region: None,
-            correction_level
+            correction_level,
+            mask: 0,
+            forced_mask,
+            mask_scores: vec![],
+            roles: vec![]
         }
     }
 
     pub fn build_qr_image(&mut self) {
-        let dimension = self.qr_code.module_width();
-        self.loud_region = Some(ImageBuffer::from_pixel(dimension, dimension, Luma([128])));
-
-        self.add_timing_patterns(self.qr_code.timing_coord());
-        self.add_finder_patterns(self.qr_code.finder_locations());
-        self.add_alignment_patterns(self.qr_code.alignment_locations());
-        self.reserve_format_and_version_space(self.qr_code.finder_locations(), self.qr_code.include_version_locations());
+        self.add_function_patterns();
         self.add_message_stream();
         let chosen_mask = self.mask_data_area();
         self.add_format_information(chosen_mask, self.qr_code.finder_locations());
@@ -39,15 +57,70 @@ impl<'a> ImageBuilder<'a> {
         self.recolour_function_pixels();
     }
 
+    // The cells holding the message, in the order its bits are placed, for reading a symbol back
+    pub fn message_cells(&mut self) -> Vec<(u32, u32)> {
+        self.add_function_patterns();
+        MessageCells::new(self.get_image(), self.qr_code.timing_coord()).collect()
+    }
+
     pub fn get_image(&self) -> &GrayImage {
         self.loud_region.as_ref().unwrap()
     }
 
+    // The finished modules with their roles, which the image alone doesn't record
+    pub fn get_matrix(&self) -> QRMatrix {
+        let image = self.get_image();
+        let modules = image
+            .enumerate_pixels()
+            .zip(self.roles.iter())
+            .map(|((_, _, pixel), &role)| Module { dark: pixel.0[0] < 128, role })
+            .collect();
+        QRMatrix::new(image.width() as usize, modules)
+    }
+
+    // The number of the mask chosen by build_qr_image
+    pub fn mask(&self) -> u8 {
+        self.mask
+    }
+
+    // How each candidate mask scored, indexed by mask number
+    pub fn mask_scores(&self) -> Vec<MaskScores> {
+        self.mask_scores.clone()
+    }
+
     fn white() -> Luma<u8> { Luma([255]) }
     fn black() -> Luma<u8> { Luma([0]) }
     fn fn_white() -> Luma<u8> { Luma([200]) }
     fn fn_black() -> Luma<u8> { Luma([50]) }
 
+    fn set_role(&mut self, x: u32, y: u32, role: ModuleRoles) {
+        let width = self.qr_code.module_width();
+        if x < width && y < width {
+            self.roles[(y * width + x) as usize] = role;
+        }
+    }
+
+    fn set_region_role(&mut self, left: i64, top: i64, size: (i64, i64), role: ModuleRoles) {
+        for y in top..top + size.1 {
+            for x in left..left + size.0 {
+                if x >= 0 && y >= 0 {
+                    self.set_role(x as u32, y as u32, role);
+                }
+            }
+        }
+    }
+
+    fn add_function_patterns(&mut self) {
+        let dimension = self.qr_code.module_width();
+        self.loud_region = Some(ImageBuffer::from_pixel(dimension, dimension, Luma([128])));
+        self.roles = vec![ModuleRoles::Remainder; (dimension * dimension) as usize];
+
+        self.add_timing_patterns(self.qr_code.timing_coord());
+        self.add_finder_patterns(self.qr_code.finder_locations());
+        self.add_alignment_patterns(self.qr_code.alignment_locations());
+        self.reserve_format_and_version_space(self.qr_code.finder_locations(), self.qr_code.include_version_locations());
+    }
+
     fn add_timing_patterns(&mut self, timing_coord: u32) {
         let buffer = self.loud_region.as_mut().unwrap();
         let horiz: GrayImage = ImageBuffer::from_fn(buffer.width(), 1, |x, _| {
@@ -60,22 +133,26 @@ impl<'a> ImageBuilder<'a> {
 
         imageops::overlay(buffer, &horiz, 0, timing_coord as i64);
         imageops::overlay(buffer, &imageops::rotate90(&horiz), timing_coord as i64, 0);
+
+        let width = self.qr_code.module_width() as i64;
+        self.set_region_role(0, timing_coord as i64, (width, 1), ModuleRoles::Timing);
+        self.set_region_role(timing_coord as i64, 0, (1, width), ModuleRoles::Timing);
     }
 
     fn add_finder_patterns(&mut self, locations: Vec<FinderLocations>) {
-        let buffer = self.loud_region.as_mut().unwrap();
+        let width = self.qr_code.module_width() as i64;
         for location in locations {
-            match location {
-                FinderLocations::TopLeft => {
-                    Self::add_finder_pattern(buffer, 0, 0, Self::fn_white(), Self::fn_black())
-                }
-                FinderLocations::BottomLeft => {
-                    Self::add_finder_pattern(buffer, 0, buffer.height() as i64 - 7, Self::fn_white(), Self::fn_black())
-                }
-                FinderLocations::TopRight => {
-                    Self::add_finder_pattern(buffer, buffer.width() as i64 - 7, 0, Self::fn_white(), Self::fn_black())
-                }
-            }
+            let (left, top) = match location {
+                FinderLocations::TopLeft => (0, 0),
+                FinderLocations::BottomLeft => (0, width - 7),
+                FinderLocations::TopRight => (width - 7, 0),
+            };
+            let buffer = self.loud_region.as_mut().unwrap();
+            Self::add_finder_pattern(buffer, left, top, Self::fn_white(), Self::fn_black());
+
+            // The separator is the light ring around the finder, where it lies inside the symbol
+            self.set_region_role(left - 1, top - 1, (9, 9), ModuleRoles::Separator);
+            self.set_region_role(left, top, (7, 7), ModuleRoles::Finder);
         }
     }
 
@@ -96,6 +173,10 @@ impl<'a> ImageBuilder<'a> {
     }
 
     fn add_alignment_patterns(&mut self, locations: Vec<(u32, u32)>) {
+        for &(cx, cy) in locations.iter() {
+            self.set_region_role(cx as i64 - 2, cy as i64 - 2, (5, 5), ModuleRoles::Alignment);
+        }
+
         let buffer = self.loud_region.as_mut().unwrap();
         for (cx, cy) in locations {
             let five: GrayImage = ImageBuffer::from_pixel(5, 5, Self::fn_black());
@@ -107,8 +188,8 @@ impl<'a> ImageBuilder<'a> {
     }
 
     fn reserve_format_and_version_space(&mut self, locations: Vec<FinderLocations>, include_versions: bool) {
-        let buffer = self.loud_region.as_mut().unwrap();
         for location in locations {
+            let buffer = self.loud_region.as_mut().unwrap();
             match location {
                 FinderLocations::TopLeft => {
                     for n in 0..9 {
@@ -120,6 +201,9 @@ impl<'a> ImageBuilder<'a> {
                     for n in 0..8 {
                         buffer.put_pixel(8, buffer.height() - 1 - n, Self::fn_black());
                     }
+                    // The top of these isn't covered by format information, and stays dark
+                    let dark_module_y = buffer.height() - 8;
+                    self.set_role(8, dark_module_y, ModuleRoles::DarkModule);
                 }
                 FinderLocations::TopRight => {
                     for n in 0..8 {
@@ -130,6 +214,7 @@ impl<'a> ImageBuilder<'a> {
         }
 
         if include_versions {
+            let buffer = self.loud_region.as_mut().unwrap();
             let tr_region: GrayImage = ImageBuffer::from_pixel(3, 6, Self::fn_black());
             let bl_region: GrayImage = ImageBuffer::from_pixel(6, 3, Self::fn_black());
             imageops::overlay(buffer, &tr_region, buffer.width() as i64 - 11, 0);
@@ -138,54 +223,119 @@ impl<'a> ImageBuilder<'a> {
     }
 
     fn add_message_stream(&mut self) {
-        let bits = self.message.iter().flat_map(|n| {
-            (0..8).map(move |b| (n >> (7 - b)) % 2)
+        // M1 and M3 codes only place the top 4 bits of their final data codeword. They have a single
+        // block, so the data codewords aren't interleaved.
+        let short_codeword = if Sizer::has_short_final_codeword(&self.qr_type, self.qr_code.version()) {
+            Some(Sizer::data_codeword_capacity(&self.qr_type, self.qr_code.version(), &self.correction_level) - 1)
+        } else {
+            None
+        };
+        let data_codeword_count =
+            Sizer::data_codeword_capacity(&self.qr_type, self.qr_code.version(), &self.correction_level);
+        let bits = self.message.iter().enumerate().flat_map(|(index, n)| {
+            let bit_count = if Some(index) == short_codeword { 4 } else { 8 };
+            let role = if index < data_codeword_count {
+                ModuleRoles::Data(index)
+            } else {
+                ModuleRoles::ErrorCorrection(index - data_codeword_count)
+            };
+            (0..bit_count).map(move |b| ((n >> (7 - b)) % 2, role))
         });
+
+        // Any cells left over after the message are remainder bits, which are always 0
         let loud_copy = self.loud_region.as_ref().unwrap().clone();
         let cells = MessageCells::new(&loud_copy, self.qr_code.timing_coord());
-        for (bit, (x, y)) in bits.zip(cells) {
+        for ((x, y), (bit, role)) in cells.zip(bits.chain(iter::repeat((0, ModuleRoles::Remainder)))) {
             let colour = if bit == 1 { Self::black() } else { Self::white() };
             self.loud_region.as_mut().unwrap().put_pixel(x, y, colour);
+            self.set_role(x, y, role);
         }
     }
 
-    // Returns mask identifier, MSB first
+    // Scores each mask with its format and version information in place, as the finished symbol will be
+    // read, then applies the best scoring or forced mask. Returns the mask identifier, MSB first.
+    //
+    // Candidates are scored on packed bits rather than the image. The unmasked symbol, with its version
+    // information, is packed once; each candidate then inverts its pattern over the data modules a word
+    // at a time and overwrites the format modules.
     fn mask_data_area(&mut self) -> Vec<u8> {
         let mask_candidates = self.qr_code.mask_functions();
-
-        let (mask_number, mask) = mask_candidates.iter().enumerate().max_by_key(|(_, mask)| {
-            let mut loud_copy = self.loud_region.as_ref().unwrap().clone();
-            Self::apply_mask(&mut loud_copy, mask);
-            self.qr_code.score_masked_image(&loud_copy)
-        }).unwrap();
-
-        // Found best mask; apply it to the real image
-        Self::apply_mask(self.loud_region.as_mut().unwrap(), mask);
-
-        if mask_candidates.len() > 4 {
-            vec![(mask_number as u8 >> 2) % 2,
-                (mask_number as u8 >> 1) % 2,
-                mask_number as u8 % 2]
-        } else {
-            vec![(mask_number as u8 >> 1) % 2,
-                mask_number as u8 % 2]
+        let image = self.get_image();
+        let width = image.width() as usize;
+        let mut unmasked = BitMatrix::new(width, width);
+        let mut data_area = BitMatrix::new(width, width);
+        for (x, y, pixel) in image.enumerate_pixels() {
+            unmasked.set(x as usize, y as usize, pixel.0[0] < 128);
+            if *pixel == Self::white() || *pixel == Self::black() {
+                data_area.set(x as usize, y as usize, true);
+            }
+        }
+        if self.qr_code.include_version_locations() {
+            for (x, y, dark) in self.version_cells() {
+                unmasked.set(x as usize, y as usize, dark);
+            }
+        }
+        let mut format_area = BitMatrix::new(width, width);
+        for (x, y, _) in self.format_cells(Self::mask_bits(0, mask_candidates.len()), self.qr_code.finder_locations()) {
+            format_area.set(x as usize, y as usize, true);
         }
-    }
 
-    fn apply_mask(image: &mut GrayImage, mask_fn: &dyn Fn(u32, u32) -> bool) {
-        for (x, y, pixel) in image.enumerate_pixels_mut() {
-            if *pixel == Self::white() || *pixel == Self::black() {
-                // Not a function pixel
-                if (*pixel == Self::black()) == mask_fn(x, y) {
-                    *pixel = Self::white();
-                } else {
-                    *pixel = Self::black();
+        // Every mask is scored, even when one is forced, so the scores can be reported
+        let mut candidates = vec![BitMatrix::new(width, width); mask_candidates.len()];
+        self.mask_scores = mask_candidates.iter().zip(candidates.iter_mut()).enumerate().map(|(mask_number, (mask, candidate))| {
+            // Each mask pattern repeats after MASK_PERIOD rows and columns, so only those are worked out
+            let mut pattern_rows = BitMatrix::new(width, MASK_PERIOD);
+            let mut pattern_columns = BitMatrix::new(MASK_PERIOD, width);
+            for offset in 0..MASK_PERIOD {
+                for index in 0..width {
+                    pattern_rows.set(index, offset, mask(index as u32, offset as u32));
+                    pattern_columns.set(offset, index, mask(offset as u32, index as u32));
                 }
             }
+            candidate.mask_from(&unmasked, &data_area, pattern_rows.rows(), pattern_columns.columns());
+
+            let mut format = BitMatrix::new(width, width);
+            for (x, y, dark) in self.format_cells(Self::mask_bits(mask_number, mask_candidates.len()), self.qr_code.finder_locations()) {
+                format.set(x as usize, y as usize, dark);
+            }
+            // Only the data modules are copied to the image, so the format modules can be written in place
+            candidate.overwrite(&format_area, &format);
+            self.qr_code.score_masked_image(candidate)
+        }).collect();
+        let mask_number = match self.forced_mask {
+            Some(mask_number) => mask_number as usize,
+            // Ties go to the lowest numbered mask, as reference encoders choose
+            None => self.mask_scores.iter().enumerate().min_by_key(|(_, scores)| Reverse(scores.rank())).unwrap().0,
+        };
+
+        // Found best mask; copy its data modules to the real image
+        let masked = &candidates[mask_number];
+        for (x, y, pixel) in self.loud_region.as_mut().unwrap().enumerate_pixels_mut() {
+            if data_area.get(x as usize, y as usize) {
+                *pixel = if masked.get(x as usize, y as usize) { Self::black() } else { Self::white() };
+            }
         }
+        self.mask = mask_number as u8;
+
+        Self::mask_bits(mask_number, mask_candidates.len())
+    }
+
+    // QR Code masks are identified by 3 bits and Micro QR Code masks by 2
+    pub(crate) fn mask_bits(mask_number: usize, mask_count: usize) -> Vec<u8> {
+        let bit_count = if mask_count > 4 { 3 } else { 2 };
+        (0..bit_count).rev().map(|bit| (mask_number >> bit) as u8 % 2).collect()
     }
 
     fn add_format_information(&mut self, mask_bits: Vec<u8>, locations: Vec<FinderLocations>) {
+        for (x, y, dark) in self.format_cells(mask_bits, locations) {
+            let pixel = if dark { Self::black() } else { Self::white() };
+            self.loud_region.as_mut().unwrap().put_pixel(x, y, pixel);
+            self.set_role(x, y, ModuleRoles::Format);
+        }
+    }
+
+    // Each format information module, with whether it is dark, for the given mask
+    pub(crate) fn format_cells(&self, mask_bits: Vec<u8>, locations: Vec<FinderLocations>) -> Vec<(u32, u32, bool)> {
         let mut format_bits = self.qr_code.ec_level_bits(self.correction_level);
         format_bits.extend(mask_bits.iter());
         assert!(format_bits.len() == 5);
@@ -214,61 +364,56 @@ impl<'a> ImageBuilder<'a> {
             }
         }
 
-        // Apply the final format info into the reserved areas
+        // Lay the final format info over the reserved areas
         let timing_coord = self.qr_code.timing_coord();
-        let buffer = self.loud_region.as_mut().unwrap();
+        let width = self.qr_code.module_width();
+        let mut format_cells = vec![];
         for location in locations {
             let mut format_iter = format_bits.iter().rev();
             match location {
                 FinderLocations::TopLeft => {
                     for n in 0..9 {
                         if n != timing_coord {
-                            let pixel = match format_iter.next() {
-                                Some(0) => Self::white(),
-                                Some(1) => Self::black(),
-                                _ => unreachable!()
-                            };
-                            buffer.put_pixel(8, n, pixel);
+                            format_cells.push((8, n, format_iter.next() == Some(&1)));
                         }
                     }
                     for n in 1..9 {
                         if 8 - n != timing_coord {
-                            let pixel = match format_iter.next() {
-                                Some(0) => Self::white(),
-                                Some(1) => Self::black(),
-                                _ => unreachable!()
-                            };
-                            buffer.put_pixel(8 - n, 8, pixel);
+                            format_cells.push((8 - n, 8, format_iter.next() == Some(&1)));
                         }
                     }
                 },
                 FinderLocations::BottomLeft => {
                     let mut second_half = format_iter.skip(8);
                     for n in 0..7 {
-                        let pixel = match second_half.next() {
-                            Some(0) => Self::white(),
-                            Some(1) => Self::black(),
-                            _ => unreachable!()
-                        };
-                        buffer.put_pixel(8, buffer.height() - 7 + n, pixel);
+                        format_cells.push((8, width - 7 + n, second_half.next() == Some(&1)));
                     }
                 }
                 FinderLocations::TopRight => {
                     for n in 0..8 {
-                        let pixel = match format_iter.next() {
-                            Some(0) => Self::white(),
-                            Some(1) => Self::black(),
-                            _ => unreachable!()
-                        };
-                        buffer.put_pixel(buffer.width() - 1 - n, 8, pixel);
+                        format_cells.push((width - 1 - n, 8, format_iter.next() == Some(&1)));
                     }
                 }
             }
         }
 
+        format_cells
     }
 
     fn add_version_information(&mut self) {
+        for (x, y, dark) in self.version_cells() {
+            let pixel = if dark { Self::black() } else { Self::white() };
+            self.loud_region.as_mut().unwrap().put_pixel(x, y, pixel);
+        }
+
+        let width = self.qr_code.module_width() as i64;
+        self.set_region_role(width - 11, 0, (3, 6), ModuleRoles::Version);
+        self.set_region_role(0, width - 11, (6, 3), ModuleRoles::Version);
+    }
+
+    // Each version information module, with whether it is dark. The 6x3 block above the bottom left
+    // finder pattern is the transpose of the 3x6 block beside the top right one.
+    fn version_cells(&self) -> Vec<(u32, u32, bool)> {
         let version = self.qr_code.version();
         let version_bits = (0..6).map(|b| IntMod::<2>::from((version >> b) % 2)).collect::<Vec<IntMod<2>>>();
         let mut version_poly = Polynomial::<IntMod<2>>::from(version_bits.clone());
@@ -283,18 +428,14 @@ impl<'a> ImageBuilder<'a> {
         let ec_poly = &version_poly % &ec_generator;
         version_poly = version_poly + ec_poly;
 
-        let mut version_area: GrayImage = ImageBuffer::from_pixel(3, 6, Luma([255]));
-        for (index, bit) in version_poly.coefficients.iter().enumerate() {
-            if bit.value == 1 {
-                version_area.put_pixel(index as u32 % 3, index as u32 / 3, Luma([0]));
-            }
+        let width = self.qr_code.module_width();
+        let mut version_cells = vec![];
+        for index in 0..18 {
+            let dark = version_poly.coefficients.get(index as usize).is_some_and(|bit| bit.value == 1);
+            version_cells.push((width - 11 + index % 3, index / 3, dark));
+            version_cells.push((index / 3, width - 11 + index % 3, dark));
         }
-
-        let buffer = self.loud_region.as_mut().unwrap();
-        imageops::overlay(buffer, &version_area, buffer.width() as i64 - 11, 0);
-        version_area = imageops::rotate270(&version_area);
-        imageops::flip_vertical_in_place(&mut version_area);
-        imageops::overlay(buffer, &version_area, 0, buffer.height() as i64 - 11);
+        version_cells
     }
 
     fn recolour_function_pixels(&mut self) {
@@ -347,7 +488,7 @@ impl<'a> MessageCells<'a> {
         match (self.up, self.y_index, self.loud_region.height() - 1 - self.y_index) {
             (true, 0, _) => {
                 // Currently at left-top going up. Move one column-pair left, right cell, downwards
-                if self.left_col_x_index == 0 {
+                if self.left_col_x_index < 2 {
                     None // Nowhere to go left!
                 } else {
                     self.left_col_x_index -= 2;
@@ -365,7 +506,7 @@ impl<'a> MessageCells<'a> {
             },
             (false, _, 0) => {
                 // Currently at left-bottom going down. Move one column-pair left, right cell, upwards
-                if self.left_col_x_index == 0 {
+                if self.left_col_x_index < 2 {
                     None // Nowhere to go left!
                 } else {
                     self.left_col_x_index -= 2;
@@ -1,26 +1,102 @@
+mod binarizer;
+mod bit_matrix;
+mod colour;
+mod decoder;
+mod detector;
+mod eci;
 mod encoder;
 mod error_correction;
+pub mod gs1;
+mod output_format;
+mod perspective_transform;
+mod print_renderer;
+mod qr_code;
 mod qr_errors;
+mod qr_matrix;
 mod qr_types;
+mod raster_renderer;
+mod segment;
+mod segmenter;
 mod sizer;
+mod svg_renderer;
+mod terminal_renderer;
 mod image_builder;
+mod logo;
+mod mask_scores;
+mod module_style;
+#[cfg(test)]
+mod test_helpers;
+pub use colour::Colour;
+pub use decoder::{decode, decode_image, decode_image_file, DecodedQRCode};
+pub use eci::CharacterSets;
+pub use logo::Logo;
+pub use mask_scores::MaskScores;
+pub use module_style::{FinderShapes, ModuleShape, ModuleShapes, ModuleStyle};
 use encoder::Encoder;
-pub use encoder::EncodingModes;
+pub use encoder::{EncodingModes, Fnc1Modes};
 pub use error_correction::CorrectionLevels;
-use image::{imageops, GrayImage, ImageBuffer, Luma};
+pub use output_format::OutputFormats;
+pub use print_renderer::Lengths;
+use print_renderer::PrintRenderer;
+use raster_renderer::RasterRenderer;
 use qr_errors::QRError;
+pub use qr_code::QRCode;
+pub use qr_matrix::{Module, ModuleRoles, QRMatrix};
 pub use qr_types::QRSymbolTypes;
+pub use segment::Segment;
+pub use segmenter::SegmentationStrategies;
 use sizer::Sizer;
+use std::{fs, path::Path};
+use svg_renderer::SvgRenderer;
+use terminal_renderer::TerminalRenderer;
+
+use crate::{error_correction::ErrorCorrector, image_builder::ImageBuilder, qr_errors::EncodingError};
 
-use crate::{error_correction::ErrorCorrector, image_builder::ImageBuilder};
+const DEFAULT_FILEPATH: &str = "./qr_code.png";
+const STRUCTURED_APPEND_HEADER_BITS: usize = 20;
+const STRUCTURED_APPEND_MAX_SYMBOLS: usize = 16;
+const DEFAULT_QUIET_ZONE: u32 = 4;
+const DEFAULT_PIXELS_PER_MODULE: u32 = 10;
+const DEFAULT_MODULE_SIZE: Lengths = Lengths::Millimetres(0.5);
+const DEFAULT_JPEG_QUALITY: u8 = 90;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Options {
     pub filepath: Option<String>,
     pub qr_type: Option<QRSymbolTypes>,
     pub version: Option<u32>,
     pub mode: Option<EncodingModes>,
     pub correction_level: Option<CorrectionLevels>,
+    pub eci: Option<CharacterSets>,
+    pub fnc1: Option<Fnc1Modes>,
+    pub segmentation: Option<SegmentationStrategies>,
+    // Use this mask pattern, 0-7 for QR Codes or 0-3 for Micro QR Codes, rather than the best scoring one
+    pub mask: Option<u8>,
+    // If not set, the format is guessed from the filepath's extension, falling back to PNG
+    pub format: Option<OutputFormats>,
+    // Width of the light border around the symbol, in modules. Defaults to 4.
+    pub quiet_zone: Option<u32>,
+    // The size of raster images: either pixels per module, by default 10, or the exact width in pixels
+    pub pixels_per_module: Option<u32>,
+    pub size: Option<u32>,
+    // Resolution recorded in PNG and JPEG images, for printing at a known size
+    pub dpi: Option<u32>,
+    // From 1 to 100. Defaults to 90, as lower qualities blur the edges of modules.
+    pub jpeg_quality: Option<u8>,
+    // Shapes for the modules of raster and SVG output, such as dots. Plain squares if not set.
+    pub style: Option<ModuleStyle>,
+    // Colours of the dark and light modules. Default to black and white.
+    pub foreground: Option<Colour>,
+    pub background: Option<Colour>,
+    // Physical size of each module in PDF and EPS output. Defaults to 0.5mm.
+    pub module_size: Option<Lengths>,
+    // For terminal output: draw light modules as blocks, for dark terminals, or set the foreground and
+    // background colours with ANSI escapes
+    pub inverted: Option<bool>,
+    pub ansi_colours: Option<bool>,
+    // An image placed over the centre of raster and SVG output. Making a symbol fails if the logo would cover
+    // more codewords than error correction can recover.
+    pub logo: Option<Logo>,
 }
 
 pub struct QRGenerator {
@@ -36,77 +112,460 @@ impl QRGenerator {
         }
     }
 
-    pub fn make_qr_code(&mut self, data: String) -> Result<String, QRError> {
+    // Encodes the data into a symbol in memory. Use save_qr_code to write it out as an image.
+    pub fn make_qr_code(&mut self, data: String) -> Result<QRCode, QRError> {
+        let mut generator = self.with_default_options();
+        let version = generator.options.version;
+        generator.fit_logo(|generator, min_version| {
+            // Work out how large the QR code needs to be
+            if version.is_none() {
+                generator.options.version = Some(Sizer::calculate_version(&generator.options, &data)?.max(min_version));
+            }
+            Self::build_qr_code(generator, Encoder::new(generator, data.clone()), None)
+        })
+    }
+
+    // Encodes binary data, such as compressed or signed payloads, as a single Byte mode segment. It is
+    // only marked with an ECI if one is set in the options.
+    pub fn make_qr_code_from_bytes(&mut self, data: &[u8]) -> Result<QRCode, QRError> {
+        self.make_qr_code_from_segments(vec![Segment::Byte(data.to_vec())])
+    }
+
+    // Encodes hand-built segments exactly as given. Unless the version is set, the smallest version whose
+    // capacity holds their exact bit length is used.
+    pub fn make_qr_code_from_segments(&mut self, segments: Vec<Segment>) -> Result<QRCode, QRError> {
+        let mut generator = self.with_default_options();
+        let version = generator.options.version;
+        generator.fit_logo(|generator, min_version| {
+            if version.is_none() {
+                generator.options.version =
+                    Some(Sizer::calculate_segments_version(&generator.options, &segments)?.max(min_version));
+            }
+            Self::build_qr_code(generator, Encoder::from_segments(generator, segments.clone()), None)
+        })
+    }
+
+    // Splits data which is too large for a single QR code across up to 16 linked Structured Append
+    // symbols, all of the same version. Data which fits in one code is made into a single normal code.
+    pub fn make_structured_append_qr_codes(&mut self, data: String) -> Result<Vec<QRCode>, QRError> {
+        let mut generator = self.with_default_options();
+        if generator.options.qr_type == Some(QRSymbolTypes::MicroQRCode) {
+            return Err(EncodingError::new("MicroQR codes don't support Structured Append").into());
+        }
+
+        let max_version = generator.options.version.unwrap_or(40);
+        if let Ok(version) = Sizer::calculate_version(&generator.options, &data) {
+            if version <= max_version {
+                return Ok(vec![self.make_qr_code(data)?]);
+            }
+        }
+
+        // Every symbol uses the same character set, so the parity is consistent
+        if generator.options.eci.is_none() {
+            generator.options.eci = Encoder::select_character_set(&generator.options, &data)?;
+        }
+        let parity = Encoder::structured_append_parity(&generator.options, &data);
+
+        let chars = data.chars().collect::<Vec<char>>();
+        for total in 2..=STRUCTURED_APPEND_MAX_SYMBOLS {
+            let chunks = chars
+                .chunks(chars.len().div_ceil(total))
+                .map(|chunk| chunk.iter().collect::<String>())
+                .collect::<Vec<String>>();
+            let versions = chunks
+                .iter()
+                .map(|chunk| {
+                    Sizer::calculate_version_with_overhead(&generator.options, chunk, STRUCTURED_APPEND_HEADER_BITS)
+                })
+                .collect::<Result<Vec<u32>, QRError>>();
+            let version = match versions {
+                Ok(versions) if *versions.iter().max().unwrap() <= max_version => {
+                    generator.options.version.unwrap_or(*versions.iter().max().unwrap())
+                }
+                _ => continue,
+            };
+
+            // Short data may fill fewer symbols than were aimed for
+            let total = chunks.len() as u8;
+            generator.options.version = Some(version);
+            return chunks
+                .into_iter()
+                .enumerate()
+                .map(|(index, chunk)| {
+                    let qr_code =
+                        Self::build_qr_code(&generator, Encoder::new(&generator, chunk), Some((index as u8, total, parity)))?;
+                    if let Some(logo) = &generator.options.logo {
+                        logo.check_correction_capacity(&qr_code)?;
+                    }
+                    Ok(qr_code)
+                })
+                .collect();
+        }
+
+        Err(EncodingError::new("Too much data to split across 16 QR codes").into())
+    }
+
+    // Writes the symbol as an image to Options.filepath, or ./qr_code.png, and returns the path
+    pub fn save_qr_code(&self, qr_code: &QRCode) -> Result<String, QRError> {
+        let filepath = self.options.filepath.clone().unwrap_or(DEFAULT_FILEPATH.to_string());
+        self.save_qr_image(&filepath, qr_code)?;
+        Ok(filepath)
+    }
+
+    // Draws the symbol in the given format, as the bytes of the file save_qr_code would write
+    pub fn render(&self, qr_code: &QRCode, format: OutputFormats) -> Result<Vec<u8>, QRError> {
+        match format {
+            OutputFormats::Svg => Ok(self.render_svg(qr_code)?.into_bytes()),
+            OutputFormats::Pdf => Ok(self.render_pdf(qr_code)),
+            OutputFormats::Eps => Ok(self.render_eps(qr_code).into_bytes()),
+            raster => self.raster_renderer().encode(qr_code, raster),
+        }
+    }
+
+    // Draws the symbol as a PNG image. When Options.size isn't a whole number of pixels per module, the
+    // spare pixels are added to the quiet zone.
+    pub fn render_png(&self, qr_code: &QRCode) -> Result<Vec<u8>, QRError> {
+        self.raster_renderer().render_png(qr_code)
+    }
+
+    // Draws the symbol as an SVG document, sized in modules through its viewBox
+    pub fn render_svg(&self, qr_code: &QRCode) -> Result<String, QRError> {
+        SvgRenderer {
+            quiet_zone: self.quiet_zone() as usize,
+            foreground: self.options.foreground.unwrap_or(Colour::BLACK),
+            background: self.options.background.unwrap_or(Colour::WHITE),
+            style: self.options.style.clone(),
+            logo: self.options.logo.clone(),
+        }
+        .render(qr_code)
+    }
+
+    // Draws the symbol as a single page PDF, the size of the symbol and its quiet zone
+    pub fn render_pdf(&self, qr_code: &QRCode) -> Vec<u8> {
+        self.print_renderer().render_pdf(qr_code)
+    }
+
+    // Draws the symbol as Encapsulated PostScript, bounded by the symbol and its quiet zone
+    pub fn render_eps(&self, qr_code: &QRCode) -> String {
+        self.print_renderer().render_eps(qr_code)
+    }
+
+    // Draws the symbol as lines of Unicode half blocks to print to a terminal
+    pub fn render_terminal(&self, qr_code: &QRCode) -> String {
+        let colours = (
+            self.options.foreground.unwrap_or(Colour::BLACK),
+            self.options.background.unwrap_or(Colour::WHITE),
+        );
+        TerminalRenderer {
+            quiet_zone: self.quiet_zone() as usize,
+            inverted: self.options.inverted.unwrap_or(false),
+            colours: self.options.ansi_colours.unwrap_or(false).then_some(colours),
+        }
+        .render(qr_code)
+    }
+
+    // Writes several symbols, such as a Structured Append series, to numbered files: qr_code_1.png,
+    // qr_code_2.png, ... A single symbol is saved as by save_qr_code.
+    pub fn save_qr_codes(&self, qr_codes: &[QRCode]) -> Result<Vec<String>, QRError> {
+        if let [qr_code] = qr_codes {
+            return Ok(vec![self.save_qr_code(qr_code)?]);
+        }
+        let filepath = self.options.filepath.clone().unwrap_or(DEFAULT_FILEPATH.to_string());
+        qr_codes
+            .iter()
+            .enumerate()
+            .map(|(index, qr_code)| {
+                let numbered_filepath = Self::numbered_filepath(&filepath, index + 1);
+                self.save_qr_image(&numbered_filepath, qr_code)?;
+                Ok(numbered_filepath)
+            })
+            .collect()
+    }
+
+    fn numbered_filepath(filepath: &str, number: usize) -> String {
+        let path = Path::new(filepath);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let file_name = match path.extension() {
+            Some(extension) => format!("{}_{}.{}", stem, number, extension.to_string_lossy()),
+            None => format!("{}_{}", stem, number),
+        };
+        path.with_file_name(file_name).to_string_lossy().into_owned()
+    }
+
+    // A copy with the defaults filled in, to make symbols with. Sizing them and raising the correction
+    // level for a logo change only the copy, so the options are as set for the next symbol.
+    fn with_default_options(&self) -> Self {
+        let mut generator = Self { options: self.options.clone() };
+        generator.apply_default_options();
+        generator
+    }
+
+    fn apply_default_options(&mut self) {
         // Unless specified, assume a QRCode (not a MicroQR)
         if self.options.qr_type.is_none() {
             self.options.qr_type = Some(QRSymbolTypes::QRCode);
         }
 
-        // Unless specified, assume Q-level correction
+        // Unless specified, assume Q-level correction (M-level for MicroQRs, where Q is only available in M4)
         if self.options.correction_level.is_none() {
-            self.options.correction_level = Some(CorrectionLevels::Q);
+            self.options.correction_level = match self.options.qr_type {
+                Some(QRSymbolTypes::MicroQRCode) => Some(CorrectionLevels::M),
+                _ => Some(CorrectionLevels::Q),
+            };
         }
+    }
 
-        // Work out how large the QR code needs to be
-        if self.options.version.is_none() {
-            self.options.version = Some(Sizer::calculate_version(&self.options, &data)?);
+    // Makes the symbol, then checks a logo won't cover more than it can correct. If the logo is allowed to
+    // raise the correction level, the symbol is remade at each higher level until it fits, and then, unless
+    // the version is set, at each larger version. make is given the smallest version to use.
+    fn fit_logo(&mut self, mut make: impl FnMut(&mut Self, u32) -> Result<QRCode, QRError>) -> Result<QRCode, QRError> {
+        let fixed_version = self.options.version.is_some();
+        // Start from a version large enough for the logo to miss the finder patterns
+        let mut min_version = match &self.options.logo {
+            Some(logo) if logo.raise_correction && !fixed_version && self.options.qr_type == Some(QRSymbolTypes::QRCode) => {
+                logo.smallest_version_clear_of_corners()?.unwrap_or(1)
+            }
+            _ => 1,
+        };
+        let mut capacity_error = None;
+        loop {
+            let qr_code = make(self, min_version)?;
+            let Some(logo) = &self.options.logo else {
+                return Ok(qr_code);
+            };
+            if let Err(err) = logo.check_placement(&qr_code) {
+                // Larger versions bring more function patterns under the logo, so once one does, report
+                // why the smaller version wasn't enough
+                return Err(capacity_error.unwrap_or(err));
+            }
+            let Err(err) = logo.check_correction_capacity(&qr_code) else {
+                return Ok(qr_code);
+            };
+            if !logo.raise_correction {
+                return Err(err);
+            }
+            if qr_code.correction_level != CorrectionLevels::H {
+                self.options.correction_level = match qr_code.correction_level {
+                    CorrectionLevels::L => Some(CorrectionLevels::M),
+                    CorrectionLevels::M => Some(CorrectionLevels::Q),
+                    _ => Some(CorrectionLevels::H),
+                };
+            } else if !fixed_version && qr_code.version < 40 {
+                min_version = qr_code.version + 1;
+                capacity_error = Some(err);
+            } else {
+                return Err(err);
+            }
         }
+    }
 
-        let mut encoder = Encoder::new(&self, data);
+    fn build_qr_code(generator: &QRGenerator, mut encoder: Encoder, structured_append: Option<(u8, u8, u8)>) -> Result<QRCode, QRError> {
+        if let Some((index, total, parity)) = structured_append {
+            encoder.set_structured_append(index, total, parity);
+        }
         encoder.encode_data_into_byte_stream()?;
         let data_bitstream = &encoder.output_data;
         let data_codewords = data_bitstream.clone().into_vec();
 
         let mut error_corrector = ErrorCorrector::from(&Sizer::error_correction_shape(
-            self.options.qr_type.as_ref().unwrap(),
-            self.options.version.unwrap(),
-            self.options.correction_level.as_ref().unwrap(),
+            generator.options.qr_type.as_ref().unwrap(),
+            generator.options.version.unwrap(),
+            generator.options.correction_level.as_ref().unwrap(),
         ));
         error_corrector.fill_data_into_blocks(data_codewords)?;
         error_corrector.generate_error_correction();
 
         let message_sequence: Vec<u8> = error_corrector.interleave().collect();
 
+        let mask_count = match generator.options.qr_type.unwrap() {
+            QRSymbolTypes::QRCode => 8,
+            QRSymbolTypes::MicroQRCode => 4,
+        };
+        if generator.options.mask.is_some_and(|mask| mask >= mask_count) {
+            return Err(EncodingError::new("Masks are numbered 0-7 for QR Codes and 0-3 for Micro QR Codes").into());
+        }
+
         let mut image_builder = ImageBuilder::new(
-            self.options.qr_type.unwrap(),
-            self.options.version.unwrap(),
+            generator.options.qr_type.unwrap(),
+            generator.options.version.unwrap(),
             &message_sequence,
-            self.options.correction_level.unwrap()
+            generator.options.correction_level.unwrap(),
+            generator.options.mask
         );
         image_builder.build_qr_image();
 
-        self.save_qr_image(&"./qr_code.png".to_string(), image_builder.get_image())?;
-        Ok("./qr_code.png".to_string())
+        Ok(QRCode {
+            qr_type: generator.options.qr_type.unwrap(),
+            version: generator.options.version.unwrap(),
+            correction_level: generator.options.correction_level.unwrap(),
+            mask: image_builder.mask(),
+            mask_scores: image_builder.mask_scores(),
+            segments: encoder.segments,
+            matrix: image_builder.get_matrix(),
+        })
     }
 
-    fn save_qr_image(&self, filepath: &String, loud_region: &GrayImage) -> Result<(), QRError> {
-        let dimension = loud_region.width();
-        let quiet_width = 4;
-        let full_dimension = dimension + quiet_width * 2;
-        let mut full_image: GrayImage =
-            ImageBuffer::from_pixel(full_dimension, full_dimension, Luma([255]));
+    fn quiet_zone(&self) -> u32 {
+        self.options.quiet_zone.unwrap_or(DEFAULT_QUIET_ZONE)
+    }
 
-        imageops::overlay(
-            &mut full_image,
-            loud_region,
-            quiet_width as i64,
-            quiet_width as i64,
-        );
-        let scale_factor = 10;
-        let scaled_size = full_dimension * scale_factor;
-        let scaled_image = imageops::resize(
-            &full_image,
-            scaled_size,
-            scaled_size,
-            imageops::FilterType::Nearest,
-        );
-        scaled_image.save(filepath)?;
+    fn raster_renderer(&self) -> RasterRenderer {
+        RasterRenderer {
+            quiet_zone: self.quiet_zone(),
+            pixels_per_module: self.options.pixels_per_module.unwrap_or(DEFAULT_PIXELS_PER_MODULE),
+            size: self.options.size,
+            foreground: self.options.foreground.unwrap_or(Colour::BLACK),
+            background: self.options.background.unwrap_or(Colour::WHITE),
+            dpi: self.options.dpi,
+            jpeg_quality: self.options.jpeg_quality.unwrap_or(DEFAULT_JPEG_QUALITY),
+            style: self.options.style.clone(),
+            logo: self.options.logo.clone(),
+        }
+    }
+
+    fn print_renderer(&self) -> PrintRenderer {
+        PrintRenderer {
+            quiet_zone: self.quiet_zone() as usize,
+            module_size: self.options.module_size.unwrap_or(DEFAULT_MODULE_SIZE),
+            foreground: self.options.foreground.unwrap_or(Colour::BLACK),
+            background: self.options.background.unwrap_or(Colour::WHITE),
+        }
+    }
 
+    fn save_qr_image(&self, filepath: &String, qr_code: &QRCode) -> Result<(), QRError> {
+        let format = self
+            .options
+            .format
+            .or(OutputFormats::from_filepath(filepath))
+            .unwrap_or(OutputFormats::Png);
+        fs::write(filepath, self.render(qr_code, format)?)?;
         Ok(())
     }
 }
 
+// Lays out a symbol from a made-up message and masks it, returning the mask chosen. This skips encoding
+// and error correction, which cost far more than masking at large versions, so that benchmarks can time
+// mask evaluation on its own. Only built with the bench feature.
+#[cfg(feature = "bench")]
+pub fn lay_out_and_mask(qr_type: QRSymbolTypes, version: u32, correction_level: CorrectionLevels) -> u8 {
+    let codeword_count = Sizer::error_correction_shape(&qr_type, version, &correction_level)
+        .iter()
+        .map(|(codewords, _, repeat)| codewords * repeat)
+        .sum::<usize>();
+    let message = (0..codeword_count).map(|index| (index * 167 % 256) as u8).collect();
+    let mut image_builder = ImageBuilder::new(qr_type, version, &message, correction_level, None);
+    image_builder.build_qr_image();
+    image_builder.mask()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn makes_qr_code_in_memory() {
+        let mut generator = QRGenerator::default();
+        let qr_code = generator.make_qr_code("Hello".to_string()).unwrap();
+
+        assert_eq!(qr_code.qr_type, QRSymbolTypes::QRCode);
+        assert_eq!(qr_code.version, 1);
+        assert_eq!(qr_code.correction_level, CorrectionLevels::Q);
+        assert_eq!(qr_code.mask, 7);
+        assert_eq!(qr_code.segments, vec![Segment::Byte(b"Hello".to_vec())]);
+        assert_eq!(qr_code.width(), 21);
+        assert!(qr_code.is_dark(0, 0) && !qr_code.is_dark(1, 1) && qr_code.is_dark(6, 8));
+    }
+
+    #[test]
+    fn chooses_mask_of_iso_worked_example() {
+        // ISO/IEC 18004 Annex I encodes 01234567 as a 1-M symbol with mask 010
+        let mut generator = QRGenerator::default();
+        generator.options.correction_level = Some(CorrectionLevels::M);
+        let qr_code = generator.make_qr_code("01234567".to_string()).unwrap();
+        assert_eq!((qr_code.version, qr_code.mask), (1, 2));
+        assert_eq!(
+            qr_code.mask_scores[2],
+            MaskScores::Penalties { n1: 206, n2: 111, n3: 720, n4: 0 }
+        );
+    }
+
+    #[test]
+    fn forces_mask_and_reports_scores() {
+        let mut generator = QRGenerator::default();
+        let best = generator.make_qr_code("Hello".to_string()).unwrap();
+        assert_eq!(best.mask_scores.len(), 8);
+        let lowest = best.mask_scores.iter().map(MaskScores::total).min().unwrap();
+        assert_eq!(best.mask_scores[best.mask as usize].total(), lowest);
+
+        generator.options.mask = Some(2);
+        let forced = generator.make_qr_code("Hello".to_string()).unwrap();
+        assert_eq!(forced.mask, 2);
+        assert_eq!(forced.mask_scores, best.mask_scores);
+        assert_ne!(forced.matrix, best.matrix);
+
+        generator.options.mask = Some(8);
+        assert!(generator.make_qr_code("Hello".to_string()).is_err());
+
+        let mut micro = QRGenerator::default();
+        micro.options.qr_type = Some(QRSymbolTypes::MicroQRCode);
+        micro.options.correction_level = None;
+        micro.options.mask = Some(3);
+        let forced = micro.make_qr_code("12345".to_string()).unwrap();
+        assert_eq!((forced.mask, forced.mask_scores.len()), (3, 4));
+        micro.options.mask = Some(4);
+        assert!(micro.make_qr_code("12345".to_string()).is_err());
+    }
+
+    #[test]
+    fn records_segments_as_encoded() {
+        let mut generator = QRGenerator::default();
+        generator.options.fnc1 = Some(Fnc1Modes::First);
+        let qr_code = generator.make_qr_code("0109501101530003\u{1D}10AB%".to_string()).unwrap();
+        assert_eq!(
+            qr_code.segments,
+            vec![
+                Segment::Numeric("0109501101530003".to_string()),
+                Segment::AlphaNumeric("%10AB%%".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_options_as_set_after_structured_append() {
+        let mut generator = QRGenerator::default();
+        let qr_codes = generator.make_structured_append_qr_codes("1".repeat(8000)).unwrap();
+        assert!(qr_codes[0].version > 1);
+        assert_eq!((generator.options.version, generator.options.eci), (None, None));
+        assert_eq!(generator.make_qr_code("HELLO".to_string()).unwrap().version, 1);
+    }
+
+    #[test]
+    fn splits_data_too_large_for_one_symbol() {
+        // Version 40-Q holds 1663 bytes
+        let data = (0..1800).map(|i| char::from(b'a' + (i * 7 % 26) as u8)).collect::<String>();
+        let mut generator = QRGenerator::default();
+        assert!(generator.make_qr_code(data.clone()).is_err());
+        let qr_codes = generator.make_structured_append_qr_codes(data.clone()).unwrap();
+        assert!((2..=16).contains(&qr_codes.len()));
+        assert!(qr_codes.iter().all(|qr_code| qr_code.version == qr_codes[0].version));
+
+        let parity = data.bytes().fold(0, |parity, byte| parity ^ byte);
+        let modules = |qr_code: &QRCode| {
+            (0..qr_code.width()).map(|y| (0..qr_code.width()).map(|x| qr_code.is_dark(x, y)).collect()).collect::<Vec<Vec<bool>>>()
+        };
+        let mut text = String::new();
+        for (index, qr_code) in qr_codes.iter().enumerate() {
+            let decoded = decode(&modules(qr_code)).unwrap();
+            assert_eq!(decoded.structured_append, Some((index as u8, qr_codes.len() as u8, parity)));
+            text += &decoded.text().unwrap();
+        }
+        assert_eq!(text, data);
+    }
+
+    #[test]
+    fn numbers_linked_filepaths() {
+        assert_eq!(QRGenerator::numbered_filepath("./qr_code.png", 1), "./qr_code_1.png");
+        assert_eq!(QRGenerator::numbered_filepath("codes/ticket.svg", 12), "codes/ticket_12.svg");
+        assert_eq!(QRGenerator::numbered_filepath("code", 2), "code_2");
+    }
 }
@@ -0,0 +1,307 @@
+use std::{collections::HashSet, io::Cursor};
+
+use image::{imageops, imageops::FilterType, io::Reader, RgbaImage};
+
+use crate::{
+    error_correction::ErrorCorrector,
+    qr_errors::EncodingError,
+    qr_errors::QRError,
+    qr_types::QRFactory,
+    sizer::Sizer,
+    ModuleRoles, QRCode, QRSymbolTypes,
+};
+
+const DEFAULT_LOGO_WIDTH: f64 = 0.2;
+const DEFAULT_LOGO_MARGIN: u32 = 1;
+// Finder patterns, their separators and the format information fill the first 9 rows and columns at
+// each corner of a QR Code
+const CORNER_PATTERNS_WIDTH: usize = 9;
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// An image drawn over the centre of a symbol. The modules beneath it, and a clear margin around it, are
+// left light, so reading the symbol relies on error correction to recover the codewords they held.
+#[derive(Clone, Debug)]
+pub struct Logo {
+    // The encoded image, in any format the image crate reads, such as PNG or JPEG
+    pub image: Vec<u8>,
+    // Width of the logo as a fraction of the symbol's width. Its height follows the image's aspect ratio.
+    pub width: f64,
+    // Light modules left clear around the logo
+    pub margin: u32,
+    // Rather than refusing a logo which covers too many codewords, raise the correction level and then,
+    // unless it is set, the version until it fits
+    pub raise_correction: bool,
+}
+
+// Where the logo sits, in modules from the top left of the symbol
+pub(crate) struct LogoPlacement {
+    // The range of modules cleared, including the margin. The ends are exclusive.
+    pub cleared_columns: (usize, usize),
+    pub cleared_rows: (usize, usize),
+    pub left: f64,
+    pub top: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Logo {
+    pub fn new(image: Vec<u8>) -> Self {
+        Self {
+            image,
+            width: DEFAULT_LOGO_WIDTH,
+            margin: DEFAULT_LOGO_MARGIN,
+            raise_correction: false,
+        }
+    }
+
+    pub(crate) fn placement(&self, symbol_width: usize) -> Result<LogoPlacement, QRError> {
+        if !(self.width > 0.0 && self.width <= 1.0) {
+            return Err(EncodingError::new("Logo widths are a fraction of the symbol's width, above 0 and at most 1").into());
+        }
+        let (pixel_width, pixel_height) = Reader::new(Cursor::new(&self.image)).with_guessed_format()?.into_dimensions()?;
+
+        let centre = symbol_width as f64 / 2.0;
+        let width = self.width * symbol_width as f64;
+        let height = width * pixel_height as f64 / pixel_width as f64;
+        // Any module the logo touches is cleared, which keeps the margin the same on both sides
+        let cleared = |size: f64| {
+            let start = (centre - size / 2.0).floor() as usize;
+            let end = (centre + size / 2.0).ceil() as usize;
+            (start.saturating_sub(self.margin as usize), (end + self.margin as usize).min(symbol_width))
+        };
+        Ok(LogoPlacement {
+            cleared_columns: cleared(width),
+            cleared_rows: cleared(height),
+            left: centre - width / 2.0,
+            top: centre - height / 2.0,
+            width,
+            height,
+        })
+    }
+
+    // The smallest QR Code version whose finder patterns and format information lie clear of the logo,
+    // which as a fraction of the symbol's width leaves them more room in larger versions. None if even
+    // version 40 is too small.
+    pub(crate) fn smallest_version_clear_of_corners(&self) -> Result<Option<u32>, QRError> {
+        for version in 1..=40 {
+            let placement = self.placement(QRFactory::build_code(QRSymbolTypes::QRCode, version).module_width() as usize)?;
+            if placement.cleared_columns.0 >= CORNER_PATTERNS_WIDTH && placement.cleared_rows.0 >= CORNER_PATTERNS_WIDTH {
+                return Ok(Some(version));
+            }
+        }
+        Ok(None)
+    }
+
+    // Checks the logo only covers data and error correction codewords. Function patterns, format and
+    // version information have no error correction, and from version 7 the alignment patterns at the
+    // centre are needed to sample the symbol.
+    pub(crate) fn check_placement(&self, qr_code: &QRCode) -> Result<(), QRError> {
+        self.covered_codewords(qr_code).map(|_| ())
+    }
+
+    // Checks that every error correction block could still recover the codewords the logo covers. Any
+    // covered module may read wrongly, so a codeword with a module under the logo counts as an error.
+    pub(crate) fn check_correction_capacity(&self, qr_code: &QRCode) -> Result<(), QRError> {
+        let covered_codewords = self.covered_codewords(qr_code)?;
+
+        let error_corrector = ErrorCorrector::from(&Sizer::error_correction_shape(
+            &qr_code.qr_type,
+            qr_code.version,
+            &qr_code.correction_level,
+        ));
+        let data_blocks = error_corrector.data_codeword_blocks();
+        let ec_blocks = error_corrector.ec_codeword_blocks();
+        let ec_counts = error_corrector.ec_codeword_counts();
+        let mut errors = vec![0; ec_counts.len()];
+        for (is_error_correction, index) in covered_codewords {
+            let block = if is_error_correction { ec_blocks[index] } else { data_blocks[index] };
+            errors[block] += 1;
+        }
+
+        // Each error takes two error correction codewords to correct, after those kept for misdecode protection
+        let misdecode_protection = Sizer::misdecode_protection_codewords(qr_code.version, &qr_code.correction_level);
+        for (block, (errors, ec_count)) in errors.iter().zip(ec_counts).enumerate() {
+            let capacity = (ec_count - misdecode_protection) / 2;
+            if *errors > capacity {
+                return Err(EncodingError::new(&format!(
+                    "The logo covers {} codewords of error correction block {}, which can only correct {}",
+                    errors,
+                    block + 1,
+                    capacity
+                ))
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    // The codewords under the logo and its margin, as (is error correction, index in the interleaved
+    // sequence), or an error if it covers anything else
+    fn covered_codewords(&self, qr_code: &QRCode) -> Result<HashSet<(bool, usize)>, QRError> {
+        if qr_code.qr_type == QRSymbolTypes::MicroQRCode {
+            return Err(EncodingError::new("Logos can't be placed on MicroQR codes, which have too little error correction").into());
+        }
+        let placement = self.placement(qr_code.width())?;
+
+        let mut covered_codewords = HashSet::new();
+        for y in placement.cleared_rows.0..placement.cleared_rows.1 {
+            for x in placement.cleared_columns.0..placement.cleared_columns.1 {
+                match qr_code.matrix.role(x, y) {
+                    ModuleRoles::Data(index) => covered_codewords.insert((false, index)),
+                    ModuleRoles::ErrorCorrection(index) => covered_codewords.insert((true, index)),
+                    // Remainder bits carry nothing
+                    ModuleRoles::Remainder => false,
+                    role => {
+                        return Err(EncodingError::new(&format!("The logo is too large, covering {:?} modules", role)).into())
+                    }
+                };
+            }
+        }
+        Ok(covered_codewords)
+    }
+
+    // A copy of the symbol with the modules under the logo and its margin made light
+    pub(crate) fn clear(&self, qr_code: &QRCode) -> Result<QRCode, QRError> {
+        let placement = self.placement(qr_code.width())?;
+        let mut cleared = qr_code.clone();
+        for y in placement.cleared_rows.0..placement.cleared_rows.1 {
+            for x in placement.cleared_columns.0..placement.cleared_columns.1 {
+                cleared.matrix.set_light(x, y);
+            }
+        }
+        Ok(cleared)
+    }
+
+    // Draws the logo over an image of the symbol, whose top left module starts at offset pixels
+    pub(crate) fn draw(&self, image: &mut RgbaImage, symbol_width: usize, offset: u32, scale: u32) -> Result<(), QRError> {
+        let placement = self.placement(symbol_width)?;
+        let to_pixels = |modules: f64| (modules * scale as f64).round() as u32;
+        let logo = image::load_from_memory(&self.image)?
+            .resize_exact(to_pixels(placement.width).max(1), to_pixels(placement.height).max(1), FilterType::Lanczos3)
+            .to_rgba8();
+        imageops::overlay(
+            image,
+            &logo,
+            (offset + to_pixels(placement.left)) as i64,
+            (offset + to_pixels(placement.top)) as i64,
+        );
+        Ok(())
+    }
+
+    // The image as a data URI, to embed in SVG
+    pub(crate) fn data_uri(&self) -> Result<String, QRError> {
+        let format = image::guess_format(&self.image)?;
+        Ok(format!("data:{};base64,{}", format.to_mime_type(), Self::base64(&self.image)))
+    }
+
+    fn base64(bytes: &[u8]) -> String {
+        let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+        for chunk in bytes.chunks(3) {
+            let group = chunk.iter().enumerate().fold(0u32, |group, (index, &byte)| group | (byte as u32) << (16 - index * 8));
+            for index in 0..4 {
+                if index <= chunk.len() {
+                    encoded.push(BASE64_ALPHABET[(group >> (18 - index * 6) & 0x3f) as usize] as char);
+                } else {
+                    encoded.push('=');
+                }
+            }
+        }
+        encoded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CorrectionLevels, QRGenerator};
+
+    // A square PNG, all one colour
+    fn square_png(side: u32) -> Vec<u8> {
+        let mut png = vec![];
+        RgbaImage::from_pixel(side, side, image::Rgba([200, 0, 0, 255]))
+            .write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+        png
+    }
+
+    fn make(correction_level: CorrectionLevels, logo: Logo) -> Result<QRCode, QRError> {
+        let mut generator = QRGenerator::default();
+        generator.options.correction_level = Some(correction_level);
+        generator.options.logo = Some(logo);
+        generator.make_qr_code("https://example.com/a/long/enough/path".to_string())
+    }
+
+    #[test]
+    fn centres_logo_and_margin() {
+        let logo = Logo { width: 0.25, ..Logo::new(square_png(10)) };
+        // 25 modules wide: the logo spans 9.375 to 15.625, so modules 9 to 15, and 8 to 16 with the margin
+        let placement = logo.placement(25).unwrap();
+        assert_eq!((placement.cleared_columns, placement.cleared_rows), ((8, 17), (8, 17)));
+        assert_eq!((placement.left, placement.width), (9.375, 6.25));
+
+        // 37 modules wide at version 5: the logo spans 13.875 to 23.125
+        let qr_code = make(CorrectionLevels::H, logo.clone()).unwrap();
+        assert_eq!(qr_code.width(), 37);
+        let cleared = logo.clear(&qr_code).unwrap();
+        assert!((12..25).all(|y| (12..25).all(|x| !cleared.is_dark(x, y))));
+        assert!((12..25).any(|x| qr_code.is_dark(x, 12)));
+        assert_eq!(cleared.is_dark(11, 12), qr_code.is_dark(11, 12));
+    }
+
+    #[test]
+    fn checks_each_block_can_correct_covered_codewords() {
+        let logo = Logo { width: 0.3, ..Logo::new(square_png(10)) };
+        assert!(make(CorrectionLevels::H, logo.clone()).is_ok());
+        let error = make(CorrectionLevels::L, logo.clone()).unwrap_err();
+        assert!(error.to_string().starts_with("The logo covers"));
+
+        let mut generator = QRGenerator::default();
+        generator.options.correction_level = Some(CorrectionLevels::L);
+        generator.options.logo = Some(Logo { raise_correction: true, ..logo });
+        let raised = generator.make_qr_code("https://example.com/a/long/enough/path".to_string()).unwrap();
+        assert!(raised.correction_level > CorrectionLevels::L);
+        // The level is only raised for that symbol
+        generator.options.logo = None;
+        assert_eq!(generator.options.correction_level, Some(CorrectionLevels::L));
+        assert_eq!(generator.make_qr_code("Hello".to_string()).unwrap().correction_level, CorrectionLevels::L);
+    }
+
+    #[test]
+    fn refuses_logos_over_function_patterns() {
+        let error = make(CorrectionLevels::H, Logo { width: 0.9, ..Logo::new(square_png(10)) }).unwrap_err();
+        assert!(error.to_string().starts_with("The logo is too large"));
+    }
+
+    #[test]
+    fn refuses_logos_over_alignment_patterns() {
+        // At version 7 and above, an alignment pattern sits at the centre of the symbol
+        let mut generator = QRGenerator::default();
+        generator.options.version = Some(7);
+        generator.options.correction_level = Some(CorrectionLevels::H);
+        generator.options.logo = Some(Logo { width: 0.1, ..Logo::new(square_png(10)) });
+        let error = generator.make_qr_code("Hello".to_string()).unwrap_err();
+        assert_eq!(error.to_string(), "The logo is too large, covering Alignment modules");
+    }
+
+    #[test]
+    fn raises_version_to_clear_finder_patterns() {
+        let logo = Logo { width: 0.3, raise_correction: true, ..Logo::new(square_png(10)) };
+        // 25 modules wide at version 2, the margin starts at column 7, on the separator
+        assert_eq!(logo.smallest_version_clear_of_corners().unwrap(), Some(3));
+        let mut generator = QRGenerator::default();
+        generator.options.correction_level = Some(CorrectionLevels::L);
+        generator.options.logo = Some(logo.clone());
+        let qr_code = generator.make_qr_code("Hello".to_string()).unwrap();
+        assert!(qr_code.version >= 3);
+        assert!(logo.check_correction_capacity(&qr_code).is_ok());
+
+        assert_eq!(Logo { width: 0.9, ..logo }.smallest_version_clear_of_corners().unwrap(), None);
+    }
+
+    #[test]
+    fn encodes_base64() {
+        assert_eq!(Logo::base64(b"Man"), "TWFu");
+        assert_eq!(Logo::base64(b"Ma"), "TWE=");
+        assert_eq!(Logo::base64(b"M"), "TQ==");
+    }
+}
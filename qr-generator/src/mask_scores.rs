@@ -0,0 +1,172 @@
+use crate::bit_matrix::{BitMatrix, Line, LINE_WORDS};
+
+const N1: u32 = 3;
+const N2: u32 = 3;
+const N3: u32 = 40;
+const N4: u32 = 10;
+const FINDER_LIKE_PATTERN: [bool; 7] = [true, false, true, true, true, false, true];
+
+// How a candidate mask pattern rated. QR Codes add up penalty points for features which make symbols
+// harder to read, and the lowest total wins. Micro QR Codes instead count the dark modules along the
+// right and bottom edges, and the highest score wins.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum MaskScores {
+    Penalties {
+        n1: u32, // Runs of five or more modules of one colour in a row or column
+        n2: u32, // 2x2 blocks of one colour
+        n3: u32, // 1:1:3:1:1 patterns, which look like finder patterns, beside four light modules
+        n4: u32, // How far the proportion of dark modules strays from half
+    },
+    EdgeDarkness {
+        sum1: u32, // The fewer dark modules of the two edges
+        sum2: u32, // The more dark modules of the two edges
+    },
+}
+
+impl MaskScores {
+    // The sum of the penalties, or for Micro QR Codes SUM1 * 16 + SUM2
+    pub fn total(&self) -> u32 {
+        match self {
+            MaskScores::Penalties { n1, n2, n3, n4 } => n1 + n2 + n3 + n4,
+            MaskScores::EdgeDarkness { sum1, sum2 } => sum1 * 16 + sum2,
+        }
+    }
+
+    // Scores a masked QR Code by the penalty rules of ISO/IEC 18004 section 7.8.3.1. Format and version
+    // information should already be in place.
+    pub(crate) fn penalties(matrix: &BitMatrix) -> Self {
+        let (mut n1, mut n2, mut n3) = (0, 0, 0);
+        // Each row is compared with the one above as it is scored, to find 2x2 blocks of one colour
+        let mut above: Option<(&Line, Line)> = None;
+        for row in matrix.rows() {
+            let same_as_next = Self::same_as_next(row, matrix.width());
+            n1 += Self::run_penalty(&same_as_next);
+            n3 += Self::finder_like_patterns(row) * N3;
+            if let Some((above_row, above_same_as_next)) = above {
+                // Every block, overlapping ones included, counted at its top left module
+                let same_as_above = BitMatrix::not(&BitMatrix::xor(row, above_row));
+                n2 += BitMatrix::count(&BitMatrix::and(
+                    &BitMatrix::and(&same_as_above, &BitMatrix::ahead(&same_as_above, 1)),
+                    &above_same_as_next,
+                )) * N2;
+            }
+            above = Some((row, same_as_next));
+        }
+        for column in matrix.columns() {
+            n1 += Self::run_penalty(&Self::same_as_next(column, matrix.height()));
+            n3 += Self::finder_like_patterns(column) * N3;
+        }
+
+        // 10 for each whole 5% the proportion of dark modules is away from 50%
+        let total = (matrix.width() * matrix.height()) as u32;
+        let n4 = (matrix.count_dark() * 2).abs_diff(total) * 10 / total * N4;
+
+        MaskScores::Penalties { n1, n2, n3, n4 }
+    }
+
+    // Each run of five or more modules of one colour scores 3, and 1 more for each module beyond five. Runs
+    // are found from which modules of a line are the same colour as the next.
+    fn run_penalty(same_as_next: &Line) -> u32 {
+        // Modules which start five in a row of one colour. A run of n has n - 4 of them, and scores N1 + n - 5.
+        let five = (1..4).fold(*same_as_next, |five, distance| {
+            BitMatrix::and(&five, &BitMatrix::ahead(same_as_next, distance))
+        });
+        let run_starts = BitMatrix::and(&five, &BitMatrix::not(&BitMatrix::behind(same_as_next, 1)));
+        BitMatrix::count(&five) + (N1 - 1) * BitMatrix::count(&run_starts)
+    }
+
+    // Dark-light-dark-dark-dark-light-dark with four light modules before or after it. Modules beyond
+    // the edge of the symbol are the light quiet zone, as the clear bits past the end of the line are.
+    fn finder_like_patterns(line: &Line) -> u32 {
+        let pattern = FINDER_LIKE_PATTERN.iter().enumerate().fold(*line, |matches, (distance, &dark)| {
+            let module = BitMatrix::ahead(line, distance as u32);
+            BitMatrix::and(&matches, &if dark { module } else { BitMatrix::not(&module) })
+        });
+        let dark_before = (1..=4).fold([0; LINE_WORDS], |dark, distance| {
+            BitMatrix::or(&dark, &BitMatrix::behind(line, distance))
+        });
+        let dark_after = (7..=10).fold([0; LINE_WORDS], |dark, distance| {
+            BitMatrix::or(&dark, &BitMatrix::ahead(line, distance))
+        });
+        let light_beside = BitMatrix::not(&BitMatrix::and(&dark_before, &dark_after));
+        BitMatrix::count(&BitMatrix::and(&pattern, &light_beside))
+    }
+
+    // Modules the same colour as the next one along
+    fn same_as_next(line: &Line, length: usize) -> Line {
+        let same = BitMatrix::not(&BitMatrix::xor(line, &BitMatrix::ahead(line, 1)));
+        BitMatrix::and(&same, &BitMatrix::first(length.saturating_sub(1)))
+    }
+
+    // Higher is better, whichever way the mask was scored
+    pub(crate) fn rank(&self) -> i64 {
+        match self {
+            MaskScores::Penalties { .. } => -(self.total() as i64),
+            MaskScores::EdgeDarkness { .. } => self.total() as i64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Rows of # for dark and . for light
+    fn penalties(rows: &[&str]) -> (u32, u32, u32, u32) {
+        let mut matrix = BitMatrix::new(rows[0].len(), rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            for (x, module) in row.chars().enumerate() {
+                matrix.set(x, y, module == '#');
+            }
+        }
+        match MaskScores::penalties(&matrix) {
+            MaskScores::Penalties { n1, n2, n3, n4 } => (n1, n2, n3, n4),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn scores_runs_of_five_or_more() {
+        // Runs of 6 dark and 5 light, with runs of light and dark changing colour straight away
+        assert_eq!(penalties(&["######.....#"]).0, 4 + 3);
+        assert_eq!(penalties(&["####.####.#."]).0, 0);
+        // Columns count too
+        assert_eq!(penalties(&["#", "#", "#", "#", "#", "."]).0, 3);
+    }
+
+    #[test]
+    fn scores_2x2_blocks() {
+        assert_eq!(penalties(&["###", "###", "###"]).1, 12);
+        assert_eq!(penalties(&["##.", "#..", ".##"]).1, 0);
+    }
+
+    #[test]
+    fn scores_finder_like_patterns_beside_light_modules() {
+        assert_eq!(penalties(&["....#.###.#.#"]).2, 40);
+        // Beyond the edges is light quiet zone, and light on both sides still counts once
+        assert_eq!(penalties(&["#.###.#"]).2, 40);
+        assert_eq!(penalties(&["#.###.#....#.###.#"]).2, 80);
+        // Without four light modules on either side
+        assert_eq!(penalties(&["#...#.###.#...#"]).2, 0);
+    }
+
+    #[test]
+    fn scores_dark_proportion() {
+        assert_eq!(penalties(&["############............."]).3, 0);
+        assert_eq!(penalties(&["######..............", "...................."]).3, 70);
+        // 55% is just into the next 5% band
+        assert_eq!(penalties(&["###########........."]).3, 10);
+    }
+
+    #[test]
+    fn ranks_lower_penalties_and_higher_edge_scores_first() {
+        let low = MaskScores::Penalties { n1: 3, n2: 0, n3: 40, n4: 0 };
+        let high = MaskScores::Penalties { n1: 10, n2: 6, n3: 40, n4: 10 };
+        assert_eq!((low.total(), high.total()), (43, 66));
+        assert!(low.rank() > high.rank());
+
+        let dark_edges = MaskScores::EdgeDarkness { sum1: 5, sum2: 6 };
+        assert_eq!(dark_edges.total(), 86);
+        assert!(dark_edges.rank() > MaskScores::EdgeDarkness { sum1: 4, sum2: 10 }.rank());
+    }
+}
@@ -0,0 +1,330 @@
+use std::sync::Arc;
+
+use crate::qr_matrix::{ModuleRoles, QRMatrix};
+
+const ROUNDED_RADIUS: f64 = 0.3;
+const DOT_DIAMETER: f64 = 0.9;
+
+// The shape drawn for each dark data module. Coordinates are in modules, from the top left corner of
+// the module's cell.
+pub trait ModuleShape {
+    // Whether a point in the cell, each coordinate between 0 and 1, is inside the shape
+    fn contains(&self, x: f64, y: f64) -> bool;
+    // SVG path data for the shape in the cell at (left, top)
+    fn svg_path(&self, left: f64, top: f64) -> String;
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum ModuleShapes {
+    Square,
+    Rounded, // Squares with rounded corners
+    Dot,
+}
+
+impl ModuleShape for ModuleShapes {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        match self {
+            ModuleShapes::Square => Outline::Square.contains(0.0, 0.0, 1.0, x, y),
+            ModuleShapes::Rounded => Outline::Rounded(ROUNDED_RADIUS).contains(0.0, 0.0, 1.0, x, y),
+            ModuleShapes::Dot => Outline::Circle.contains(Self::DOT_INSET, Self::DOT_INSET, DOT_DIAMETER, x, y),
+        }
+    }
+
+    fn svg_path(&self, left: f64, top: f64) -> String {
+        match self {
+            ModuleShapes::Square => Outline::Square.svg_path(left, top, 1.0),
+            ModuleShapes::Rounded => Outline::Rounded(ROUNDED_RADIUS).svg_path(left, top, 1.0),
+            ModuleShapes::Dot => {
+                Outline::Circle.svg_path(left + Self::DOT_INSET, top + Self::DOT_INSET, DOT_DIAMETER)
+            }
+        }
+    }
+}
+
+impl ModuleShapes {
+    const DOT_INSET: f64 = (1.0 - DOT_DIAMETER) / 2.0;
+}
+
+// How the finder patterns, the "eyes", are drawn: module by module like the data (only with the
+// safeguard off), or as a whole 7x7 ring around a 3x3 centre
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum FinderShapes {
+    Modules,
+    Square,
+    Rounded,
+    Circle,
+}
+
+impl FinderShapes {
+    // The outlines of the ring, outside and in, and of the centre
+    fn outlines(&self, safeguard: bool) -> [Outline; 3] {
+        let [outer, inner, centre] = match self {
+            FinderShapes::Modules | FinderShapes::Square => [Outline::Square; 3],
+            FinderShapes::Rounded => [Outline::Rounded(2.0), Outline::Rounded(1.5), Outline::Rounded(1.0)],
+            FinderShapes::Circle => [Outline::Circle; 3],
+        };
+        // Scanners find the pattern by the square corners of its ring, so the safeguard only shapes the centre
+        if safeguard {
+            [Outline::Square, Outline::Square, centre]
+        } else {
+            [outer, inner, centre]
+        }
+    }
+
+    // The point is measured from the finder's top left corner
+    fn contains(&self, safeguard: bool, x: f64, y: f64) -> bool {
+        let [outer, inner, centre] = self.outlines(safeguard);
+        (outer.contains(0.0, 0.0, 7.0, x, y) && !inner.contains(1.0, 1.0, 5.0, x, y))
+            || centre.contains(2.0, 2.0, 3.0, x, y)
+    }
+
+    // The ring and the centre, to be filled with the even-odd rule
+    fn svg_path(&self, safeguard: bool, left: f64, top: f64) -> String {
+        let [outer, inner, centre] = self.outlines(safeguard);
+        [
+            outer.svg_path(left, top, 7.0),
+            inner.svg_path(left + 1.0, top + 1.0, 5.0),
+            centre.svg_path(left + 2.0, top + 2.0, 3.0),
+        ]
+        .concat()
+    }
+}
+
+// How modules are drawn in PNG and SVG output
+#[derive(Clone)]
+pub struct ModuleStyle {
+    pub data_modules: Arc<dyn ModuleShape + Send + Sync>,
+    pub finders: FinderShapes,
+    // Keeps timing, alignment, format and version modules square, and finders whole with square rings,
+    // however the data modules are drawn. Scanners rely on these patterns to locate and sample the symbol.
+    pub safeguard: bool,
+}
+
+impl Default for ModuleStyle {
+    fn default() -> Self {
+        Self::new(ModuleShapes::Square, FinderShapes::Modules)
+    }
+}
+
+impl ModuleStyle {
+    pub fn new(data_modules: ModuleShapes, finders: FinderShapes) -> Self {
+        Self {
+            data_modules: Arc::new(data_modules),
+            finders,
+            safeguard: true,
+        }
+    }
+
+    // Round dots, with circular eyes
+    pub fn dots() -> Self {
+        Self::new(ModuleShapes::Dot, FinderShapes::Circle)
+    }
+
+    // Rounded squares, with rounded eyes
+    pub fn rounded() -> Self {
+        Self::new(ModuleShapes::Rounded, FinderShapes::Rounded)
+    }
+
+    // Whether the point (x, y), in modules from the symbol's top left corner, is drawn dark
+    pub(crate) fn covers(&self, matrix: &QRMatrix, x: f64, y: f64) -> bool {
+        let width = matrix.width() as f64;
+        if x < 0.0 || y < 0.0 || x >= width || y >= width {
+            return false;
+        }
+        let (column, row) = (x as usize, y as usize);
+        match self.cell_drawing(matrix, column, row) {
+            Drawing::Light => false,
+            Drawing::Square => true,
+            Drawing::Shape => self.data_modules.contains(x - column as f64, y - row as f64),
+            Drawing::Finder(left, top) => self.finders.contains(self.safeguard, x - left as f64, y - top as f64),
+        }
+    }
+
+    // SVG path data for the whole symbol, offset by the quiet zone. Cells drawn square are merged into
+    // horizontal runs. The path is to be filled with the even-odd rule, for the finder rings.
+    pub(crate) fn svg_path(&self, matrix: &QRMatrix, quiet_zone: usize) -> String {
+        let mut path = String::new();
+        let offset = quiet_zone as f64;
+        if self.shaped_finders() {
+            for (left, top) in Self::finder_origins(matrix) {
+                path.push_str(&self.finders.svg_path(
+                    self.safeguard,
+                    (left + quiet_zone) as f64,
+                    (top + quiet_zone) as f64,
+                ));
+            }
+        }
+        for y in 0..matrix.width() {
+            let mut run_start = None;
+            for x in 0..=matrix.width() {
+                let drawing = if x < matrix.width() { self.cell_drawing(matrix, x, y) } else { Drawing::Light };
+                if drawing == Drawing::Square {
+                    run_start.get_or_insert(x);
+                    continue;
+                }
+                if let Some(start) = run_start.take() {
+                    path.push_str(&format!("M{},{}h{}v1h-{}z", start + quiet_zone, y + quiet_zone, x - start, x - start));
+                }
+                if drawing == Drawing::Shape {
+                    path.push_str(&self.data_modules.svg_path(x as f64 + offset, y as f64 + offset));
+                }
+            }
+        }
+        path
+    }
+
+    fn cell_drawing(&self, matrix: &QRMatrix, x: usize, y: usize) -> Drawing {
+        let module = matrix.module(x, y);
+        if module.role == ModuleRoles::Finder && self.shaped_finders() {
+            let (left, top) = Self::finder_origins(matrix)
+                .into_iter()
+                .find(|&(left, top)| (left..left + 7).contains(&x) && (top..top + 7).contains(&y))
+                .unwrap();
+            return Drawing::Finder(left, top);
+        }
+        if !module.dark {
+            Drawing::Light
+        } else if self.safeguard && module.role.is_function_pattern() {
+            Drawing::Square
+        } else {
+            Drawing::Shape
+        }
+    }
+
+    fn shaped_finders(&self) -> bool {
+        self.finders != FinderShapes::Modules || self.safeguard
+    }
+
+    // Top left corners of the finder patterns. Micro QR codes only have the top left one.
+    fn finder_origins(matrix: &QRMatrix) -> Vec<(usize, usize)> {
+        let far = matrix.width() - 7;
+        [(0, 0), (far, 0), (0, far)]
+            .into_iter()
+            .filter(|&(left, top)| {
+                matrix.role(left, top) == ModuleRoles::Finder && matrix.role(left + 6, top + 6) == ModuleRoles::Finder
+            })
+            .collect()
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+enum Drawing {
+    Light,
+    Square,
+    Shape,
+    Finder(usize, usize),
+}
+
+// The outlines shapes are built from, in a square of the given size at (left, top)
+#[derive(Clone, Copy)]
+enum Outline {
+    Square,
+    Rounded(f64), // Corner radius
+    Circle,
+}
+
+impl Outline {
+    fn contains(&self, left: f64, top: f64, size: f64, x: f64, y: f64) -> bool {
+        let (x, y) = (x - left, y - top);
+        if x < 0.0 || y < 0.0 || x > size || y > size {
+            return false;
+        }
+        let radius = match self {
+            Outline::Square => return true,
+            Outline::Rounded(radius) => *radius,
+            Outline::Circle => size / 2.0,
+        };
+        // The distance from the square inset by the radius
+        let dx = (radius - x).max(x - (size - radius)).max(0.0);
+        let dy = (radius - y).max(y - (size - radius)).max(0.0);
+        dx * dx + dy * dy <= radius * radius
+    }
+
+    fn svg_path(&self, left: f64, top: f64, size: f64) -> String {
+        // Rounded to keep floating point noise out of the path
+        let round = |value: f64| (value * 10000.0).round() / 10000.0;
+        let (left, top, size) = (round(left), round(top), round(size));
+        let radius = match self {
+            Outline::Square => return format!("M{},{}h{}v{}h-{}z", left, top, size, size, size),
+            Outline::Rounded(radius) => *radius,
+            Outline::Circle => size / 2.0,
+        };
+        let radius = round(radius);
+        let side = round(size - 2.0 * radius);
+        format!(
+            "M{},{}h{side}a{r},{r} 0 0 1 {r},{r}v{side}a{r},{r} 0 0 1 -{r},{r}h-{side}a{r},{r} 0 0 1 -{r},-{r}v-{side}a{r},{r} 0 0 1 {r},-{r}z",
+            round(left + radius),
+            top,
+            side = side,
+            r = radius
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::hello;
+    use crate::QRGenerator;
+
+    #[test]
+    fn shapes_contain_points() {
+        assert!(ModuleShapes::Square.contains(0.02, 0.02));
+        assert!(!ModuleShapes::Rounded.contains(0.02, 0.02));
+        assert!(ModuleShapes::Rounded.contains(0.5, 0.02));
+        assert!(!ModuleShapes::Dot.contains(0.1, 0.1));
+        assert!(ModuleShapes::Dot.contains(0.5, 0.5));
+        assert!(FinderShapes::Circle.contains(false, 3.5, 0.5));
+        assert!(!FinderShapes::Circle.contains(false, 0.5, 0.5));
+        assert!(!FinderShapes::Circle.contains(false, 3.5, 1.5));
+        assert!(FinderShapes::Circle.contains(false, 3.5, 3.5));
+        // The safeguard keeps the ring square, but still shapes the centre
+        assert!(FinderShapes::Circle.contains(true, 0.5, 0.5));
+        assert!(!FinderShapes::Circle.contains(true, 2.1, 2.1));
+    }
+
+    #[test]
+    fn safeguard_keeps_function_patterns_square() {
+        let matrix = hello().matrix;
+        let style = ModuleStyle::dots();
+        // A dark timing module
+        assert!(matrix.is_dark(8, 6));
+        assert!(style.covers(&matrix, 8.02, 6.02));
+        let unguarded = ModuleStyle { safeguard: false, ..ModuleStyle::dots() };
+        assert!(!unguarded.covers(&matrix, 8.02, 6.02));
+        assert!(unguarded.covers(&matrix, 8.5, 6.5));
+    }
+
+    #[test]
+    fn draws_finders_whole() {
+        let matrix = hello().matrix;
+        let style = ModuleStyle::new(ModuleShapes::Dot, FinderShapes::Square);
+        // The corner of the top left finder is square, though data modules are dots
+        assert!(style.covers(&matrix, 0.02, 0.02));
+        assert!(style.covers(&matrix, 20.98, 0.02));
+        assert!(!style.covers(&matrix, 1.5, 1.5));
+        // With the safeguard off, finders can be drawn module by module
+        let unguarded = ModuleStyle { safeguard: false, ..ModuleStyle::new(ModuleShapes::Dot, FinderShapes::Modules) };
+        assert!(!unguarded.covers(&matrix, 0.02, 0.02));
+    }
+
+    #[test]
+    fn builds_svg_paths() {
+        let matrix = hello().matrix;
+        let path = ModuleStyle::default().svg_path(&matrix, 4);
+        assert!(path.starts_with("M4,4h7v7h-7zM5,5h5v5h-5zM6,6h3v3h-3z"));
+        let micro = QRGenerator {
+            options: crate::Options { qr_type: Some(crate::QRSymbolTypes::MicroQRCode), ..Default::default() },
+        }
+        .make_qr_code("1".to_string())
+        .unwrap()
+        .matrix;
+        assert_eq!(ModuleStyle::finder_origins(&micro), vec![(0, 0)]);
+
+        let dots = ModuleStyle::dots().svg_path(&matrix, 0);
+        assert!(dots.starts_with("M0,0h7v7h-7zM1,1h5v5h-5zM3.5,2h0a1.5,1.5 0 0 1 1.5,1.5"));
+        let unguarded = ModuleStyle { safeguard: false, ..ModuleStyle::dots() }.svg_path(&matrix, 0);
+        assert!(unguarded.starts_with("M3.5,0h0a3.5,3.5 0 0 1 3.5,3.5"));
+        assert!(dots.contains("a0.45,0.45"));
+    }
+}
@@ -0,0 +1,51 @@
+use std::path::Path;
+
+// The file formats symbols can be saved in
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum OutputFormats {
+    Png, // Written with a 1-bit palette when the image has only two colours
+    Jpeg,
+    Gif,
+    Bmp,
+    Tiff,
+    Webp, // Lossless
+    Svg,
+    Pdf,
+    Eps,
+}
+
+impl OutputFormats {
+    // Guesses the format from a file's extension, or None if it isn't one we write
+    pub fn from_filepath(filepath: &str) -> Option<Self> {
+        let extension = Path::new(filepath).extension()?.to_string_lossy().to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(OutputFormats::Png),
+            "jpg" | "jpeg" => Some(OutputFormats::Jpeg),
+            "gif" => Some(OutputFormats::Gif),
+            "bmp" => Some(OutputFormats::Bmp),
+            "tif" | "tiff" => Some(OutputFormats::Tiff),
+            "webp" => Some(OutputFormats::Webp),
+            "svg" => Some(OutputFormats::Svg),
+            "pdf" => Some(OutputFormats::Pdf),
+            "eps" => Some(OutputFormats::Eps),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guesses_format_from_extension() {
+        assert_eq!(OutputFormats::from_filepath("./qr_code.png"), Some(OutputFormats::Png));
+        assert_eq!(OutputFormats::from_filepath("codes/ticket.SVG"), Some(OutputFormats::Svg));
+        assert_eq!(OutputFormats::from_filepath("print/label.eps"), Some(OutputFormats::Eps));
+        assert_eq!(OutputFormats::from_filepath("photo.JPG"), Some(OutputFormats::Jpeg));
+        assert_eq!(OutputFormats::from_filepath("scan.tif"), Some(OutputFormats::Tiff));
+        assert_eq!(OutputFormats::from_filepath("web/code.webp"), Some(OutputFormats::Webp));
+        assert_eq!(OutputFormats::from_filepath("qr_code.txt"), None);
+        assert_eq!(OutputFormats::from_filepath("qr_code"), None);
+    }
+}
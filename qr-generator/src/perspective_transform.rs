@@ -0,0 +1,97 @@
+// A projective mapping of the plane, for sampling a symbol seen at an angle. Points are row vectors
+// [x y 1] multiplied by the matrix.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct PerspectiveTransform {
+    matrix: [[f64; 3]; 3],
+}
+
+impl PerspectiveTransform {
+    // Maps each corner of one quadrilateral onto the same corner of the other. Corners go round in order,
+    // top left, top right, bottom right then bottom left.
+    pub fn quadrilateral_to_quadrilateral(from: [(f64, f64); 4], to: [(f64, f64); 4]) -> Self {
+        Self::square_to_quadrilateral(from).adjugate().then(&Self::square_to_quadrilateral(to))
+    }
+
+    pub fn transform(&self, x: f64, y: f64) -> (f64, f64) {
+        let m = &self.matrix;
+        let divisor = m[0][2] * x + m[1][2] * y + m[2][2];
+        (
+            (m[0][0] * x + m[1][0] * y + m[2][0]) / divisor,
+            (m[0][1] * x + m[1][1] * y + m[2][1]) / divisor,
+        )
+    }
+
+    // Maps the unit square's corners (0, 0), (1, 0), (1, 1) and (0, 1) onto the quadrilateral's
+    fn square_to_quadrilateral(corners: [(f64, f64); 4]) -> Self {
+        let [(x0, y0), (x1, y1), (x2, y2), (x3, y3)] = corners;
+        let (dx3, dy3) = (x0 - x1 + x2 - x3, y0 - y1 + y2 - y3);
+        if dx3 == 0.0 && dy3 == 0.0 {
+            // A parallelogram, which needs no perspective
+            return Self {
+                matrix: [[x1 - x0, y1 - y0, 0.0], [x2 - x1, y2 - y1, 0.0], [x0, y0, 1.0]],
+            };
+        }
+
+        let (dx1, dx2, dy1, dy2) = (x1 - x2, x3 - x2, y1 - y2, y3 - y2);
+        let denominator = dx1 * dy2 - dx2 * dy1;
+        let a13 = (dx3 * dy2 - dx2 * dy3) / denominator;
+        let a23 = (dx1 * dy3 - dx3 * dy1) / denominator;
+        Self {
+            matrix: [
+                [x1 - x0 + a13 * x1, y1 - y0 + a13 * y1, a13],
+                [x3 - x0 + a23 * x3, y3 - y0 + a23 * y3, a23],
+                [x0, y0, 1.0],
+            ],
+        }
+    }
+
+    // The inverse, up to a scale factor which the division in transform cancels out
+    fn adjugate(&self) -> Self {
+        let m = &self.matrix;
+        let cofactor = |row: usize, column: usize| {
+            let (r1, r2) = ((row + 1) % 3, (row + 2) % 3);
+            let (c1, c2) = ((column + 1) % 3, (column + 2) % 3);
+            m[r1][c1] * m[r2][c2] - m[r1][c2] * m[r2][c1]
+        };
+        Self {
+            matrix: [0, 1, 2].map(|row| [0, 1, 2].map(|column| cofactor(column, row))),
+        }
+    }
+
+    // This transform followed by the other
+    fn then(&self, other: &Self) -> Self {
+        let (a, b) = (&self.matrix, &other.matrix);
+        Self {
+            matrix: [0, 1, 2].map(|row| [0, 1, 2].map(|column| (0..3).map(|k| a[row][k] * b[k][column]).sum())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near((x, y): (f64, f64), (expected_x, expected_y): (f64, f64)) {
+        assert!((x - expected_x).abs() < 1e-6 && (y - expected_y).abs() < 1e-6, "({x}, {y}) isn't ({expected_x}, {expected_y})");
+    }
+
+    #[test]
+    fn maps_corners_onto_corners() {
+        let from = [(3.5, 3.5), (22.5, 3.5), (19.5, 19.5), (3.5, 22.5)];
+        let to = [(140.0, 60.0), (400.0, 90.0), (380.0, 330.0), (110.0, 390.0)];
+        let transform = PerspectiveTransform::quadrilateral_to_quadrilateral(from, to);
+        for (point, expected) in from.into_iter().zip(to) {
+            assert_near(transform.transform(point.0, point.1), expected);
+        }
+        let back = PerspectiveTransform::quadrilateral_to_quadrilateral(to, from);
+        let (x, y) = transform.transform(10.0, 15.0);
+        assert_near(back.transform(x, y), (10.0, 15.0));
+    }
+
+    #[test]
+    fn keeps_parallelograms_affine() {
+        let to = [(10.0, 10.0), (30.0, 20.0), (30.0, 40.0), (10.0, 30.0)];
+        let transform = PerspectiveTransform::quadrilateral_to_quadrilateral([(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)], to);
+        assert_near(transform.transform(1.0, 1.0), (20.0, 25.0));
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt::Write;
+
+use crate::{colour::Colour, QRCode};
+
+const POINTS_PER_MILLIMETRE: f64 = 72.0 / 25.4;
+
+// A physical length, for sizing modules in print output
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Lengths {
+    Millimetres(f64),
+    Points(f64), // 1/72 inch, as PDF and PostScript measure
+}
+
+impl Lengths {
+    pub fn to_points(&self) -> f64 {
+        match self {
+            Lengths::Millimetres(mm) => mm * POINTS_PER_MILLIMETRE,
+            Lengths::Points(points) => *points,
+        }
+    }
+}
+
+// Draws symbols as self-contained PDF and EPS files at an exact physical size. Dark modules are filled as
+// one rectangle per horizontal run. Neither format's page model has simple alpha, so colours are drawn
+// opaque, except that a fully transparent background isn't drawn at all.
+pub(crate) struct PrintRenderer {
+    pub quiet_zone: usize,
+    pub module_size: Lengths,
+    pub foreground: Colour,
+    pub background: Colour,
+}
+
+impl PrintRenderer {
+    pub fn render_pdf(&self, qr_code: &QRCode) -> Vec<u8> {
+        let dimension = qr_code.width() + self.quiet_zone * 2;
+        let page_size = Self::number(dimension as f64 * self.module_size.to_points());
+
+        // PDF's origin is at the bottom left, so rows are flipped
+        let mut content = String::new();
+        writeln!(content, "{0} 0 0 {0} 0 0 cm", Self::number(self.module_size.to_points())).unwrap();
+        if self.background.a > 0 {
+            writeln!(content, "{} rg 0 0 {1} {1} re f", Self::rgb(&self.background), dimension).unwrap();
+        }
+        writeln!(content, "{} rg", Self::rgb(&self.foreground)).unwrap();
+        for (x, y, length) in qr_code.matrix.dark_runs() {
+            writeln!(
+                content,
+                "{} {} {} 1 re",
+                x + self.quiet_zone,
+                dimension - 1 - y - self.quiet_zone,
+                length
+            )
+            .unwrap();
+        }
+        content.push_str("f\n");
+
+        let objects = [
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {0} {0}] /Resources << >> /Contents 4 0 R >>",
+                page_size
+            ),
+            format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content),
+        ];
+
+        // The cross-reference table gives the byte offset of every object
+        let mut pdf = String::from("%PDF-1.4\n");
+        let mut offsets = vec![];
+        for (index, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            write!(pdf, "{} 0 obj\n{}\nendobj\n", index + 1, object).unwrap();
+        }
+        let xref_offset = pdf.len();
+        write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).unwrap();
+        for offset in offsets {
+            // Each entry is exactly 20 bytes, ending in a space and a newline
+            #[allow(clippy::write_with_newline)]
+            write!(pdf, "{:010} 00000 n \n", offset).unwrap();
+        }
+        write!(
+            pdf,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref_offset
+        )
+        .unwrap();
+        pdf.into_bytes()
+    }
+
+    pub fn render_eps(&self, qr_code: &QRCode) -> String {
+        let dimension = qr_code.width() + self.quiet_zone * 2;
+        let size = dimension as f64 * self.module_size.to_points();
+
+        let mut eps = String::from("%!PS-Adobe-3.0 EPSF-3.0\n");
+        writeln!(eps, "%%BoundingBox: 0 0 {0} {0}", size.ceil() as u64).unwrap();
+        writeln!(eps, "%%HiResBoundingBox: 0 0 {0} {0}", Self::number(size)).unwrap();
+        eps.push_str("%%Creator: qr-generator\n%%LanguageLevel: 2\n%%EndComments\n");
+        eps.push_str("gsave\n/r { 1 rectfill } bind def\n");
+        writeln!(eps, "{0} {0} scale", Self::number(self.module_size.to_points())).unwrap();
+        if self.background.a > 0 {
+            writeln!(eps, "{} setrgbcolor 0 0 {1} {1} rectfill", Self::rgb(&self.background), dimension).unwrap();
+        }
+        writeln!(eps, "{} setrgbcolor", Self::rgb(&self.foreground)).unwrap();
+        for (x, y, length) in qr_code.matrix.dark_runs() {
+            writeln!(eps, "{} {} {} r", x + self.quiet_zone, dimension - 1 - y - self.quiet_zone, length).unwrap();
+        }
+        eps.push_str("grestore\nshowpage\n%%EOF\n");
+        eps
+    }
+
+    // Colour components between 0 and 1, as both formats take them
+    fn rgb(colour: &Colour) -> String {
+        [colour.r, colour.g, colour.b]
+            .iter()
+            .map(|&component| Self::number(component as f64 / 255.0))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    // Up to 6 decimal places, without trailing zeros
+    fn number(value: f64) -> String {
+        let formatted = format!("{:.6}", value);
+        formatted.trim_end_matches('0').trim_end_matches('.').to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{hello, print_renderer as renderer};
+
+    #[test]
+    fn converts_lengths_to_points() {
+        assert_eq!(Lengths::Points(2.5).to_points(), 2.5);
+        assert!((Lengths::Millimetres(25.4).to_points() - 72.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sizes_pdf_page_to_symbol() {
+        let qr_code = hello();
+        // 21 modules and a quiet zone of 4 either side make 29, so a 20mm square
+        let pdf = String::from_utf8(renderer(Lengths::Millimetres(20.0 / 29.0)).render_pdf(&qr_code)).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.contains("/MediaBox [0 0 56.692913 56.692913]"));
+        // The top row of the top left finder pattern, flipped to PDF's coordinates
+        assert!(pdf.contains("\n4 24 7 1 re\n"));
+        assert!(pdf.ends_with("%%EOF\n"));
+    }
+
+    #[test]
+    fn pdf_cross_references_point_at_objects() {
+        let qr_code = hello();
+        let pdf = String::from_utf8(renderer(Lengths::Points(2.0)).render_pdf(&qr_code)).unwrap();
+        let xref_offset: usize = pdf.rsplit("startxref\n").next().unwrap().lines().next().unwrap().parse().unwrap();
+        assert!(pdf[xref_offset..].starts_with("xref\n0 5\n"));
+        for (index, entry) in pdf[xref_offset..].lines().skip(3).take(4).enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(&format!("{} 0 obj", index + 1)));
+        }
+    }
+
+    #[test]
+    fn sizes_eps_bounding_box_to_symbol() {
+        let qr_code = hello();
+        let eps = PrintRenderer {
+            background: Colour::TRANSPARENT,
+            ..renderer(Lengths::Points(2.5))
+        }
+        .render_eps(&qr_code);
+        assert!(eps.starts_with("%!PS-Adobe-3.0 EPSF-3.0\n%%BoundingBox: 0 0 73 73\n"));
+        assert!(eps.contains("%%HiResBoundingBox: 0 0 72.5 72.5\n"));
+        assert!(eps.contains("2.5 2.5 scale\n0 0 0 setrgbcolor\n4 24 7 r\n"));
+        assert!(eps.ends_with("%%EOF\n"));
+    }
+}
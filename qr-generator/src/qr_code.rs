@@ -0,0 +1,25 @@
+use crate::{error_correction::CorrectionLevels, mask_scores::MaskScores, qr_matrix::QRMatrix, segment::Segment, QRSymbolTypes};
+
+// A finished symbol: its modules, and how the data was encoded into them
+#[derive(Clone, Debug)]
+pub struct QRCode {
+    pub qr_type: QRSymbolTypes,
+    pub version: u32,
+    pub correction_level: CorrectionLevels,
+    pub mask: u8,
+    // How every candidate mask scored, indexed by mask number, whether or not the mask was forced
+    pub mask_scores: Vec<MaskScores>,
+    pub segments: Vec<Segment>,
+    pub matrix: QRMatrix,
+}
+
+impl QRCode {
+    // The number of modules along each side
+    pub fn width(&self) -> usize {
+        self.matrix.width()
+    }
+
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.matrix.is_dark(x, y)
+    }
+}
@@ -3,15 +3,19 @@ use std::fmt;
 
 #[derive(Debug)]
 pub enum QRError {
-    EncodingError(EncodingError),
-    ImageError(image::ImageError),
+    Encoding(EncodingError),
+    Decoding(DecodingError),
+    Image(image::ImageError),
+    Io(std::io::Error),
 }
 
 impl fmt::Display for QRError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::EncodingError(msg) => write!(f, "{}", msg),
-            Self::ImageError(img_err) => write!(f, "{}", img_err),
+            Self::Encoding(msg) => write!(f, "{}", msg),
+            Self::Decoding(msg) => write!(f, "{}", msg),
+            Self::Image(img_err) => write!(f, "{}", img_err),
+            Self::Io(io_err) => write!(f, "{}", io_err),
         }
     }
 }
@@ -20,7 +24,22 @@ impl Error for QRError {}
 
 impl From<image::ImageError> for QRError {
     fn from(err: image::ImageError) -> Self {
-        QRError::ImageError(err)
+        QRError::Image(err)
+    }
+}
+
+impl From<std::io::Error> for QRError {
+    fn from(err: std::io::Error) -> Self {
+        QRError::Io(err)
+    }
+}
+
+impl From<png::EncodingError> for QRError {
+    fn from(err: png::EncodingError) -> Self {
+        QRError::Image(image::ImageError::Encoding(image::error::EncodingError::new(
+            image::ImageFormat::Png.into(),
+            err,
+        )))
     }
 }
 
@@ -42,6 +61,30 @@ impl EncodingError {
 }
 impl From<EncodingError> for QRError {
     fn from(err: EncodingError) -> Self {
-        QRError::EncodingError(err)
+        QRError::Encoding(err)
+    }
+}
+
+// A symbol which can't be read, because it is damaged beyond what error correction can recover or
+// doesn't follow the standard
+#[derive(Debug)]
+pub struct DecodingError {
+    msg: String,
+}
+impl fmt::Display for DecodingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+impl DecodingError {
+    pub fn new(msg: &str) -> Self {
+        Self {
+            msg: msg.to_string(),
+        }
+    }
+}
+impl From<DecodingError> for QRError {
+    fn from(err: DecodingError) -> Self {
+        QRError::Decoding(err)
     }
 }
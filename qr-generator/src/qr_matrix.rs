@@ -0,0 +1,174 @@
+// What a module is part of. Codeword indices are positions in the final, interleaved codeword sequence
+// which is placed into the symbol, counted separately for data and error correction codewords.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum ModuleRoles {
+    Finder,
+    Separator,
+    Timing,
+    Alignment,
+    Format,
+    Version,
+    DarkModule, // The single always-dark module beside the bottom-left format information
+    Data(usize),
+    ErrorCorrection(usize),
+    Remainder, // Left over cells after the last codeword, which are always 0 before masking
+}
+
+impl ModuleRoles {
+    // Function patterns are fixed by the version and format, rather than carrying the message
+    pub fn is_function_pattern(&self) -> bool {
+        !matches!(self, ModuleRoles::Data(_) | ModuleRoles::ErrorCorrection(_) | ModuleRoles::Remainder)
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct Module {
+    pub dark: bool,
+    pub role: ModuleRoles,
+}
+
+// The modules of a symbol, without a quiet zone, with the role each one plays
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct QRMatrix {
+    width: usize,
+    modules: Vec<Module>,
+}
+
+impl QRMatrix {
+    pub(crate) fn new(width: usize, modules: Vec<Module>) -> Self {
+        assert!(modules.len() == width * width);
+        Self { width, modules }
+    }
+
+    // The number of modules along each side
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    // x counts from the left and y from the top
+    pub fn module(&self, x: usize, y: usize) -> Module {
+        self.modules[y * self.width + x]
+    }
+
+    // Used to clear the modules under a logo
+    pub(crate) fn set_light(&mut self, x: usize, y: usize) {
+        self.modules[y * self.width + x].dark = false;
+    }
+
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.module(x, y).dark
+    }
+
+    pub fn role(&self, x: usize, y: usize) -> ModuleRoles {
+        self.module(x, y).role
+    }
+
+    // Every module as (x, y, module), row by row from the top left
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, Module)> + '_ {
+        self.modules
+            .iter()
+            .enumerate()
+            .map(|(index, &module)| (index % self.width, index / self.width, module))
+    }
+
+    // Horizontal runs of dark modules as (x, y, length), row by row, which vector renderers draw as one
+    // rectangle each
+    pub(crate) fn dark_runs(&self) -> Vec<(usize, usize, usize)> {
+        let mut runs = vec![];
+        for y in 0..self.width {
+            let mut x = 0;
+            while x < self.width {
+                let run_start = x;
+                while x < self.width && self.is_dark(x, y) {
+                    x += 1;
+                }
+                if x > run_start {
+                    runs.push((run_start, y, x - run_start));
+                } else {
+                    x += 1;
+                }
+            }
+        }
+        runs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CorrectionLevels, QRGenerator, QRSymbolTypes};
+
+    fn make_matrix(qr_type: QRSymbolTypes, version: u32, data: &str) -> QRMatrix {
+        let mut generator = QRGenerator::default();
+        generator.options.qr_type = Some(qr_type);
+        generator.options.version = Some(version);
+        if qr_type == QRSymbolTypes::MicroQRCode {
+            generator.options.correction_level = Some(CorrectionLevels::DetectionOnly);
+        }
+        generator.make_qr_code(data.to_string()).unwrap().matrix
+    }
+
+    fn count_roles(matrix: &QRMatrix, role: impl Fn(ModuleRoles) -> bool) -> usize {
+        matrix.iter().filter(|(_, _, module)| role(module.role)).count()
+    }
+
+    #[test]
+    fn records_function_pattern_roles() {
+        let matrix = make_matrix(QRSymbolTypes::QRCode, 1, "Hello");
+        assert_eq!(matrix.width(), 21);
+        assert_eq!(matrix.role(0, 0), ModuleRoles::Finder);
+        assert_eq!(matrix.role(7, 3), ModuleRoles::Separator);
+        assert_eq!(matrix.role(13, 7), ModuleRoles::Separator);
+        assert_eq!(matrix.role(8, 6), ModuleRoles::Timing);
+        assert_eq!(matrix.role(6, 10), ModuleRoles::Timing);
+        assert_eq!(matrix.role(8, 2), ModuleRoles::Format);
+        assert_eq!(matrix.module(8, 13), Module { dark: true, role: ModuleRoles::DarkModule });
+
+        assert_eq!(count_roles(&matrix, |role| role == ModuleRoles::Finder), 3 * 49);
+        assert_eq!(count_roles(&matrix, |role| role == ModuleRoles::Separator), 3 * 15);
+        assert_eq!(count_roles(&matrix, |role| role == ModuleRoles::Timing), 2 * 5);
+        assert_eq!(count_roles(&matrix, |role| role == ModuleRoles::Format), 2 * 15);
+        assert!(matrix.iter().all(|(x, y, module)| module.role != ModuleRoles::Finder
+            || module.dark == !matches!((x % 14, y % 14), (1 | 5, 1..=5) | (1..=5, 1 | 5))));
+    }
+
+    #[test]
+    fn records_codeword_indices() {
+        // Version 1-Q has 13 data and 13 error correction codewords, and no remainder bits
+        let matrix = make_matrix(QRSymbolTypes::QRCode, 1, "Hello");
+        assert_eq!(matrix.role(20, 20), ModuleRoles::Data(0));
+        assert_eq!(matrix.role(19, 17), ModuleRoles::Data(0));
+        assert_eq!(matrix.role(20, 16), ModuleRoles::Data(1));
+        for index in 0..13 {
+            assert_eq!(count_roles(&matrix, |role| role == ModuleRoles::Data(index)), 8);
+            assert_eq!(count_roles(&matrix, |role| role == ModuleRoles::ErrorCorrection(index)), 8);
+        }
+        assert_eq!(count_roles(&matrix, |role| !role.is_function_pattern()), 26 * 8);
+    }
+
+    #[test]
+    fn records_alignment_version_and_remainder_roles() {
+        let matrix = make_matrix(QRSymbolTypes::QRCode, 7, "Hello");
+        assert_eq!(count_roles(&matrix, |role| role == ModuleRoles::Alignment), 6 * 25);
+        assert_eq!(count_roles(&matrix, |role| role == ModuleRoles::Version), 2 * 18);
+        assert_eq!(matrix.role(34, 0), ModuleRoles::Version);
+        assert_eq!(matrix.role(0, 34), ModuleRoles::Version);
+
+        let matrix = make_matrix(QRSymbolTypes::QRCode, 2, "Hello");
+        assert_eq!(count_roles(&matrix, |role| role == ModuleRoles::Remainder), 7);
+    }
+
+    #[test]
+    fn records_micro_qr_code_roles() {
+        // M1 has 3 data codewords, the last only 4 bits long, and 2 error detection codewords
+        let matrix = make_matrix(QRSymbolTypes::MicroQRCode, 1, "12345");
+        assert_eq!(matrix.width(), 11);
+        assert_eq!(matrix.role(7, 0), ModuleRoles::Separator);
+        assert_eq!(matrix.role(8, 0), ModuleRoles::Timing);
+        assert_eq!(count_roles(&matrix, |role| role == ModuleRoles::Format), 15);
+        assert_eq!(count_roles(&matrix, |role| role == ModuleRoles::Data(2)), 4);
+        assert_eq!(count_roles(&matrix, |role| role == ModuleRoles::ErrorCorrection(1)), 8);
+        assert_eq!(count_roles(&matrix, |role| role == ModuleRoles::Remainder), 0);
+        assert_eq!(count_roles(&matrix, |role| role == ModuleRoles::DarkModule), 0);
+    }
+}
@@ -1,14 +1,13 @@
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum FinderLocations {
     TopLeft,
     TopRight,
     BottomLeft,
 }
-use image::{GrayImage, Luma, imageops};
 use itertools::Itertools;
 use FinderLocations::*;
 
-use crate::error_correction::CorrectionLevels;
+use crate::{bit_matrix::BitMatrix, error_correction::CorrectionLevels, mask_scores::MaskScores};
 
 pub trait QRSymbol {
     fn version(&self) -> u32;
@@ -19,7 +18,7 @@ pub trait QRSymbol {
     fn format_locations(&self) -> Vec<FinderLocations>;
     fn include_version_locations(&self) -> bool;
     fn mask_functions(&self) -> Vec<Box<dyn Fn(u32, u32) -> bool>>;
-    fn score_masked_image(&self, image: &GrayImage) -> i32;
+    fn score_masked_image(&self, matrix: &BitMatrix) -> MaskScores;
     fn ec_level_bits(&self, ec_level: CorrectionLevels) -> Vec<u8>;
     fn format_mask(&self) -> Vec<u8>;
 }
@@ -54,9 +53,9 @@ impl QRCode {
             22 => vec![6, 26, 50, 74, 98],
             23 => vec![6, 30, 54, 76, 102],
             24 => vec![6, 28, 54, 78, 106],
-            25 => vec![6, 32, 58, 80, 110],
-            26 => vec![6, 30, 58, 82, 114],
-            27 => vec![6, 34, 62, 84, 118],
+            25 => vec![6, 32, 58, 84, 110],
+            26 => vec![6, 30, 58, 86, 114],
+            27 => vec![6, 34, 62, 90, 118],
             28 => vec![6, 26, 50, 74, 98, 122],
             29 => vec![6, 30, 54, 78, 102, 126],
             30 => vec![6, 26, 52, 78, 104, 130],
@@ -127,87 +126,8 @@ impl QRSymbol for QRCode {
             Box::new(|j, i| ((i + j) % 2 + (i * j) % 3) % 2 == 0),
         ]
     }
-    fn score_masked_image(&self, image: &GrayImage) -> i32 {
-        let mut score = 0i32;
-
-        // Runs of same-colour cells per row
-        for row in image.rows() {
-            let mut current = 0i32;
-            let mut run_colour = Luma([128]);
-            for pixel in row {
-                if *pixel == run_colour {
-                    current += 1;
-                } else {
-                    if current >= 5 {
-                        score -= 3 + current - 5;
-                    }
-                    run_colour = *pixel;
-                }
-            }
-        }
-
-        // Runs of same-colour cells per column
-        for col in imageops::rotate90(image).rows() {
-            let mut current = 0i32;
-            let mut run_colour = Luma([128]);
-            for pixel in col {
-                if *pixel == run_colour {
-                    current += 1;
-                } else {
-                    if current >= 5 {
-                        score -= 3 + current - 5;
-                    }
-                    run_colour = *pixel;
-                }
-            }
-        }
-
-        // 2x2 blocks of the same colour
-        let (width, height) = image.dimensions();
-        for (x, y, pixel) in image.enumerate_pixels() {
-            if x < width - 1 && y < height - 1
-                && (pixel.0[0] < 128) == (image.get_pixel(x + 1, y).0[0] < 128)
-                && (pixel.0[0] < 128) == (image.get_pixel(x + 1, y + 1).0[0] < 128)
-                && (pixel.0[0] < 128) == (image.get_pixel(x, y + 1).0[0] < 128)
-            {
-                score -= 3;
-            }
-        }
-
-        // 1011101 pattern with white run on one side - rows
-        for row in image.rows() {
-            let row_vec = row.collect::<Vec<&Luma<u8>>>();
-            let matches = row_vec.windows(11).filter(|run| {
-                let run_as_1_0 = run.iter().map(|pixel| {
-                    if pixel.0[0] < 128 { 1 } else { 0 }
-                }).collect::<Vec<u8>>();
-                (run_as_1_0 == vec![0, 0, 0, 0, 1, 0, 1, 1, 1, 0, 1]) ||
-                    (run_as_1_0 == vec![1, 0, 1, 1, 1, 0, 1, 0, 0, 0, 0])
-            }).count();
-            score -= 40 * matches as i32;
-        }
-
-        // 1011101 pattern with white run on one side - cols
-        for col in imageops::rotate90(image).rows() {
-            let col_vec = col.collect::<Vec<&Luma<u8>>>();
-            let matches = col_vec.windows(11).filter(|run| {
-                let run_as_1_0 = run.iter().map(|pixel| {
-                    if pixel.0[0] < 128 { 1 } else { 0 }
-                }).collect::<Vec<u8>>();
-                (run_as_1_0 == vec![0, 0, 0, 0, 1, 0, 1, 1, 1, 0, 1]) ||
-                    (run_as_1_0 == vec![1, 0, 1, 1, 1, 0, 1, 0, 0, 0, 0])
-            }).count();
-            score -= 40 * matches as i32;
-        }
-
-        // Proportion of dark cells
-        let total_cells = image.pixels().count();
-        let dark_cells = image.pixels().filter(|p| p.0[0] < 128).count();
-        let dark_percentage = dark_cells * 100 / total_cells;
-        let unbalance = (dark_percentage as i32 - 50).abs();
-        score -= 10 * (unbalance / 5);
-
-        score
+    fn score_masked_image(&self, matrix: &BitMatrix) -> MaskScores {
+        MaskScores::penalties(matrix)
     }
 
     fn ec_level_bits(&self, ec_level: CorrectionLevels) -> Vec<u8> {
@@ -252,18 +172,19 @@ impl QRSymbol for MicroQRCode {
     }
     fn mask_functions(&self) -> Vec<Box<dyn Fn(u32, u32) -> bool>> {
         vec![
-            Box::new(|i, _| i % 2 == 0),
-            Box::new(|i, j| (i / 2 + j / 3) % 2 == 0),
-            Box::new(|i, j| ((i * j) % 2 + (i * j) % 3) % 2 == 0),
-            Box::new(|i, j| ((i + j) % 2 + (i * j) % 3) % 2 == 0),
+            Box::new(|_, i| i % 2 == 0),
+            Box::new(|j, i| (i / 2 + j / 3) % 2 == 0),
+            Box::new(|j, i| ((i * j) % 2 + (i * j) % 3) % 2 == 0),
+            Box::new(|j, i| ((i + j) % 2 + (i * j) % 3) % 2 == 0),
         ]
     }
-    fn score_masked_image(&self, image: &GrayImage) -> i32 {
-        let bottom_score = image.rows().last().unwrap().filter(|pixel| pixel.0[0] < 128).count() as i32;
-        let last_col_ix = image.width() - 1;
-        let right_score = image.enumerate_pixels().filter(|&(x, _, pixel)| x == last_col_ix && pixel.0[0] < 128).count() as i32;
+    fn score_masked_image(&self, matrix: &BitMatrix) -> MaskScores {
+        // Count dark modules along the right and bottom edges, excluding the timing pattern module
+        let past_timing = BitMatrix::not(&BitMatrix::first(1));
+        let bottom_score = BitMatrix::count(&BitMatrix::and(matrix.rows().last().unwrap(), &past_timing));
+        let right_score = BitMatrix::count(&BitMatrix::and(matrix.columns().last().unwrap(), &past_timing));
 
-        16 * bottom_score.min(right_score) + bottom_score.max(right_score)
+        MaskScores::EdgeDarkness { sum1: bottom_score.min(right_score), sum2: bottom_score.max(right_score) }
     }
     fn ec_level_bits(&self, ec_level: CorrectionLevels) -> Vec<u8> {
         match (self.version, ec_level) {
@@ -283,7 +204,7 @@ impl QRSymbol for MicroQRCode {
     }
 }
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum QRSymbolTypes {
     QRCode,
     MicroQRCode,
@@ -385,4 +306,21 @@ mod tests {
     fn micro_qr_code_v4_has_no_alignments() {
         assert!(MicroQRCode { version: 4 }.alignment_locations().is_empty());
     }
+
+    #[test]
+    fn micro_qr_code_masks_are_row_based() {
+        // Mask 00 inverts every even row, called with (x, y)
+        let masks = MicroQRCode { version: 2 }.mask_functions();
+        assert!(masks[0](1, 0));
+        assert!(!masks[0](0, 1));
+    }
+    #[test]
+    fn micro_qr_code_score_ignores_timing_modules() {
+        let mut matrix = BitMatrix::new(11, 11);
+        matrix.set(10, 0, true);
+        matrix.set(0, 10, true);
+        assert_eq!(MicroQRCode { version: 1 }.score_masked_image(&matrix).total(), 0);
+        matrix.set(10, 10, true);
+        assert_eq!(MicroQRCode { version: 1 }.score_masked_image(&matrix).total(), 17);
+    }
 }
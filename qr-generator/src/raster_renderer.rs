@@ -0,0 +1,336 @@
+use std::io::Cursor;
+
+use image::{
+    codecs::{
+        jpeg::{JpegEncoder, PixelDensity},
+        webp::WebPEncoder,
+    },
+    error::{ParameterError, ParameterErrorKind},
+    ColorType, DynamicImage, ImageBuffer, ImageError, ImageOutputFormat, Rgb, RgbImage, Rgba, RgbaImage,
+};
+
+use crate::{colour::Colour, logo::Logo, module_style::ModuleStyle, qr_errors::QRError, OutputFormats, QRCode};
+
+const METRES_PER_INCH: f64 = 0.0254;
+// Styled modules are anti-aliased by sampling a grid of points in each pixel
+const SAMPLES_PER_PIXEL_SIDE: u32 = 4;
+
+// Draws symbols as images, each module a square block of pixels
+pub(crate) struct RasterRenderer {
+    pub quiet_zone: u32,
+    pub pixels_per_module: u32,
+    // The exact width of the image, overriding pixels_per_module. When it isn't a multiple of the symbol
+    // and quiet zone width, the modules are as large as fit and the spare pixels widen the quiet zone.
+    pub size: Option<u32>,
+    pub foreground: Colour,
+    pub background: Colour,
+    // Recorded in the PNG pHYs chunk and the JPEG JFIF header
+    pub dpi: Option<u32>,
+    // From 1 to 100
+    pub jpeg_quality: u8,
+    // Shapes to draw modules with, rather than plain squares
+    pub style: Option<ModuleStyle>,
+    pub logo: Option<Logo>,
+}
+
+impl RasterRenderer {
+    pub fn render(&self, qr_code: &QRCode) -> Result<RgbaImage, QRError> {
+        let cleared;
+        let qr_code = match &self.logo {
+            Some(logo) => {
+                cleared = logo.clear(qr_code)?;
+                &cleared
+            }
+            None => qr_code,
+        };
+        let width = qr_code.width() as u32;
+        let modules = width + self.quiet_zone * 2;
+        let (scale, size) = match self.size {
+            Some(size) if size < modules => {
+                return Err(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(format!(
+                    "{} pixels is too small for a symbol {} modules wide, with its quiet zone",
+                    size, modules
+                ))))
+                .into())
+            }
+            Some(size) => (size / modules, size),
+            None if self.pixels_per_module == 0 => {
+                return Err(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+                    "Modules must be at least 1 pixel wide".to_string(),
+                )))
+                .into())
+            }
+            None => (self.pixels_per_module, modules * self.pixels_per_module),
+        };
+        // Any spare pixels are split around the symbol, the odd one to the right and bottom
+        let offset = (size - width * scale) / 2;
+
+        let dark = Self::pixel(&self.foreground);
+        let light = Self::pixel(&self.background);
+        let mut image = if let Some(style) = &self.style {
+            let samples = SAMPLES_PER_PIXEL_SIDE;
+            ImageBuffer::from_fn(size, size, |x, y| {
+                let to_modules = |p: u32, sample: u32| {
+                    (p as f64 - offset as f64 + (sample as f64 + 0.5) / samples as f64) / scale as f64
+                };
+                let covered = (0..samples * samples)
+                    .filter(|sample| {
+                        style.covers(&qr_code.matrix, to_modules(x, sample % samples), to_modules(y, sample / samples))
+                    })
+                    .count() as f64;
+                Self::blend(&dark, &light, covered / (samples * samples) as f64)
+            })
+        } else {
+            ImageBuffer::from_fn(size, size, |x, y| {
+                let in_symbol = |p: u32| p >= offset && (p - offset) / scale < width;
+                if in_symbol(x) && in_symbol(y)
+                    && qr_code.is_dark(((x - offset) / scale) as usize, ((y - offset) / scale) as usize)
+                {
+                    dark
+                } else {
+                    light
+                }
+            })
+        };
+
+        if let Some(logo) = &self.logo {
+            logo.draw(&mut image, qr_code.width(), offset, scale)?;
+        }
+        Ok(image)
+    }
+
+    // Encodes the image in one of the raster formats
+    pub fn encode(&self, qr_code: &QRCode, format: OutputFormats) -> Result<Vec<u8>, QRError> {
+        if format == OutputFormats::Png {
+            return self.render_png(qr_code);
+        }
+        let image = self.render(qr_code)?;
+        let mut bytes = vec![];
+        match format {
+            OutputFormats::Jpeg => {
+                let image = Self::flatten(&image);
+                let mut encoder = JpegEncoder::new_with_quality(&mut bytes, self.jpeg_quality);
+                if let Some(dpi) = self.dpi {
+                    encoder.set_pixel_density(PixelDensity::dpi(dpi.min(u16::MAX as u32) as u16));
+                }
+                encoder.encode(image.as_raw(), image.width(), image.height(), ColorType::Rgb8)?;
+            }
+            OutputFormats::Webp => {
+                WebPEncoder::new_lossless(&mut bytes).encode(image.as_raw(), image.width(), image.height(), ColorType::Rgba8)?
+            }
+            OutputFormats::Gif => DynamicImage::ImageRgba8(image).write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Gif)?,
+            OutputFormats::Bmp => DynamicImage::ImageRgba8(image).write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Bmp)?,
+            OutputFormats::Tiff => DynamicImage::ImageRgba8(image).write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Tiff)?,
+            OutputFormats::Png | OutputFormats::Svg | OutputFormats::Pdf | OutputFormats::Eps => {
+                unreachable!("{:?} isn't a raster format", format)
+            }
+        }
+        Ok(bytes)
+    }
+
+    // Images with only two colours, as plain square modules are, are written with a 1-bit palette, which
+    // is much smaller than full colour
+    pub fn render_png(&self, qr_code: &QRCode) -> Result<Vec<u8>, QRError> {
+        let image = self.render(qr_code)?;
+        let mut colours: Vec<Rgba<u8>> = vec![];
+        for pixel in image.pixels() {
+            if !colours.contains(pixel) {
+                colours.push(*pixel);
+                if colours.len() > 2 {
+                    break;
+                }
+            }
+        }
+
+        let mut png = vec![];
+        let mut encoder = png::Encoder::new(&mut png, image.width(), image.height());
+        let data = if colours.len() <= 2 {
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_depth(png::BitDepth::One);
+            encoder.set_palette(colours.iter().flat_map(|colour| colour.0[..3].to_vec()).collect::<Vec<u8>>());
+            if colours.iter().any(|colour| colour.0[3] < 255) {
+                encoder.set_trns(colours.iter().map(|colour| colour.0[3]).collect::<Vec<u8>>());
+            }
+            // Each row is padded to a whole byte
+            image
+                .rows()
+                .flat_map(|row| {
+                    row.collect::<Vec<&Rgba<u8>>>()
+                        .chunks(8)
+                        .map(|pixels| {
+                            pixels.iter().enumerate().fold(0u8, |byte, (index, &pixel)| {
+                                byte | ((colours[1..].contains(pixel) as u8) << (7 - index))
+                            })
+                        })
+                        .collect::<Vec<u8>>()
+                })
+                .collect()
+        } else {
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            image.into_raw()
+        };
+        if let Some(dpi) = self.dpi {
+            let pixels_per_metre = (dpi as f64 / METRES_PER_INCH).round() as u32;
+            encoder.set_pixel_dims(Some(png::PixelDimensions {
+                xppu: pixels_per_metre,
+                yppu: pixels_per_metre,
+                unit: png::Unit::Meter,
+            }));
+        }
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        writer.finish()?;
+        Ok(png)
+    }
+
+    // JPEG has no alpha channel, so transparency is blended onto white
+    fn flatten(image: &RgbaImage) -> RgbImage {
+        let white = Rgba([255, 255, 255, 255]);
+        ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+            let pixel = image.get_pixel(x, y);
+            let blended = Self::blend(pixel, &white, pixel.0[3] as f64 / 255.0);
+            Rgb([blended.0[0], blended.0[1], blended.0[2]])
+        })
+    }
+
+    fn pixel(colour: &Colour) -> Rgba<u8> {
+        Rgba([colour.r, colour.g, colour.b, colour.a])
+    }
+
+    fn blend(dark: &Rgba<u8>, light: &Rgba<u8>, coverage: f64) -> Rgba<u8> {
+        let mut pixel = *light;
+        for (channel, &dark_channel) in pixel.0.iter_mut().zip(dark.0.iter()) {
+            *channel = (*channel as f64 + (dark_channel as f64 - *channel as f64) * coverage).round() as u8;
+        }
+        pixel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{hello, raster_renderer as renderer};
+    use crate::{Logo, QRGenerator};
+
+    #[test]
+    fn scales_modules_and_quiet_zone() {
+        let image = RasterRenderer { quiet_zone: 2, pixels_per_module: 3, ..renderer() }.render(&hello()).unwrap();
+        assert_eq!(image.dimensions(), (75, 75));
+        assert_eq!(image.get_pixel(5, 5), &Rgba([255, 255, 255, 255]));
+        assert_eq!(image.get_pixel(6, 6), &Rgba([0, 0, 0, 255]));
+        assert_eq!(image.get_pixel(8, 26), &Rgba([0, 0, 0, 255]));
+        assert_eq!(image.get_pixel(9, 9), &Rgba([255, 255, 255, 255]));
+
+        assert!(RasterRenderer { pixels_per_module: 0, ..renderer() }.render(&hello()).is_err());
+    }
+
+    #[test]
+    fn renders_exact_sizes() {
+        // 29 modules wide with the quiet zone
+        let image = RasterRenderer { size: Some(290), ..renderer() }.render(&hello()).unwrap();
+        assert_eq!(image.dimensions(), (290, 290));
+        assert_eq!(image.get_pixel(40, 40), &Rgba([0, 0, 0, 255]));
+
+        // 9 spare pixels, 4 before the symbol and 5 after
+        let image = RasterRenderer { size: Some(299), ..renderer() }.render(&hello()).unwrap();
+        assert_eq!(image.dimensions(), (299, 299));
+        assert_eq!(image.get_pixel(43, 43), &Rgba([255, 255, 255, 255]));
+        assert_eq!(image.get_pixel(44, 44), &Rgba([0, 0, 0, 255]));
+        assert_eq!(image.get_pixel(253, 44), &Rgba([0, 0, 0, 255]));
+        assert_eq!(image.get_pixel(254, 44), &Rgba([255, 255, 255, 255]));
+
+        assert!(RasterRenderer { size: Some(28), ..renderer() }.render(&hello()).is_err());
+    }
+
+    #[test]
+    fn uses_configured_colours() {
+        let image = RasterRenderer {
+            foreground: Colour::rgb(0, 0, 128),
+            background: Colour::TRANSPARENT,
+            ..renderer()
+        }
+        .render(&hello())
+        .unwrap();
+        assert_eq!(image.get_pixel(0, 0).0[3], 0);
+        assert_eq!(image.get_pixel(40, 40), &Rgba([0, 0, 128, 255]));
+    }
+
+    #[test]
+    fn records_dpi_in_png() {
+        let png = RasterRenderer { dpi: Some(300), ..renderer() }.render_png(&hello()).unwrap();
+        let decoder = png::Decoder::new(png.as_slice());
+        let reader = decoder.read_info().unwrap();
+        let pixel_dims = reader.info().pixel_dims.unwrap();
+        assert_eq!((pixel_dims.xppu, pixel_dims.yppu, pixel_dims.unit), (11811, 11811, png::Unit::Meter));
+        assert_eq!(reader.info().width, 290);
+    }
+
+    #[test]
+    fn writes_two_colour_png_with_palette() {
+        let png = renderer().render_png(&hello()).unwrap();
+        let reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+        assert_eq!((reader.info().color_type, reader.info().bit_depth), (png::ColorType::Indexed, png::BitDepth::One));
+        assert_eq!(image::load_from_memory(&png).unwrap().to_rgba8(), renderer().render(&hello()).unwrap());
+
+        let transparent = RasterRenderer { background: Colour::TRANSPARENT, ..renderer() }.render_png(&hello()).unwrap();
+        let reader = png::Decoder::new(transparent.as_slice()).read_info().unwrap();
+        // The transparent quiet zone comes first
+        assert_eq!(reader.info().trns.as_deref(), Some(&[0, 255][..]));
+
+        let styled = RasterRenderer { style: Some(ModuleStyle::dots()), ..renderer() }.render_png(&hello()).unwrap();
+        let reader = png::Decoder::new(styled.as_slice()).read_info().unwrap();
+        assert_eq!(reader.info().color_type, png::ColorType::Rgba);
+    }
+
+    #[test]
+    fn encodes_other_raster_formats() {
+        let expected = renderer().render(&hello()).unwrap();
+        for (format, image_format) in [
+            (OutputFormats::Gif, image::ImageFormat::Gif),
+            (OutputFormats::Bmp, image::ImageFormat::Bmp),
+            (OutputFormats::Tiff, image::ImageFormat::Tiff),
+            (OutputFormats::Webp, image::ImageFormat::WebP),
+        ] {
+            let bytes = renderer().encode(&hello(), format).unwrap();
+            assert_eq!(image::guess_format(&bytes).unwrap(), image_format);
+            assert_eq!(image::load_from_memory(&bytes).unwrap().to_rgba8(), expected, "{:?}", format);
+        }
+
+        // JPEG is lossy, so only check it's close
+        let jpeg = RasterRenderer { dpi: Some(300), ..renderer() }.encode(&hello(), OutputFormats::Jpeg).unwrap();
+        let decoded = image::load_from_memory(&jpeg).unwrap().to_rgb8();
+        assert_eq!(decoded.dimensions(), (290, 290));
+        assert!(decoded.get_pixel(45, 45).0[0] < 64 && decoded.get_pixel(55, 55).0[0] > 192);
+    }
+
+    #[test]
+    fn draws_styled_modules() {
+        let image = RasterRenderer { style: Some(ModuleStyle::dots()), ..renderer() }.render(&hello()).unwrap();
+        // The finder's circular centre leaves its corner light, and partly covered pixels are blended
+        assert_eq!(image.get_pixel(40, 40), &Rgba([0, 0, 0, 255]));
+        assert_eq!(image.get_pixel(60, 60), &Rgba([255, 255, 255, 255]));
+        assert_eq!(image.get_pixel(75, 60), &Rgba([0, 0, 0, 255]));
+        assert!(image.pixels().any(|pixel| (1..255).contains(&pixel.0[0])));
+    }
+
+    #[test]
+    fn draws_logo_over_cleared_modules() {
+        let mut png = vec![];
+        RgbaImage::from_pixel(8, 4, Rgba([255, 0, 0, 255]))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+        let logo = Logo { width: 0.2, margin: 0, ..Logo::new(png) };
+        let mut generator = QRGenerator::default();
+        generator.options.correction_level = Some(crate::CorrectionLevels::H);
+        generator.options.logo = Some(logo.clone());
+        let qr_code = generator.make_qr_code("Hello".to_string()).unwrap();
+
+        let image = RasterRenderer { logo: Some(logo), ..renderer() }.render(&qr_code).unwrap();
+        // The logo spans modules 8.4 to 12.6 across and 9.45 to 11.55 down, so modules 8 to 12 and 9 to 11
+        // are cleared
+        assert_eq!(image.get_pixel(125, 136), &Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(165, 155), &Rgba([255, 0, 0, 255]));
+        assert!((120..124).all(|x| (130..160).all(|y| image.get_pixel(x, y) == &Rgba([255, 255, 255, 255]))));
+    }
+}
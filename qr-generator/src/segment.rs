@@ -0,0 +1,141 @@
+use crate::{
+    eci,
+    encoder::{Encoder, EncodingModes},
+    qr_errors::EncodingError,
+    segmenter, QRSymbolTypes,
+};
+
+// A hand-built piece of the data bitstream. Segments are encoded exactly as given, in order, so a
+// sequence of them can reproduce another generator's symbol bit for bit.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub enum Segment {
+    Numeric(String),      // 0-9
+    AlphaNumeric(String), // 0-9, A-Z (ucase), sp, $%*+-./:
+    Byte(Vec<u8>),
+    Kanji(String), // Shift-JIS
+    ECI(u32),      // Declares the character set of the following byte segments
+}
+
+impl Segment {
+    // The exact length of this segment in bits, including its mode and character count indicators, in a
+    // symbol of the given type and version
+    pub fn bit_length(&self, qr_type: &QRSymbolTypes, version: u32) -> Result<usize, EncodingError> {
+        let valid_version = match qr_type {
+            QRSymbolTypes::MicroQRCode => (1..=4).contains(&version),
+            QRSymbolTypes::QRCode => (1..=40).contains(&version),
+        };
+        if !valid_version {
+            return Err(EncodingError::new("No such version for this symbol type"));
+        }
+
+        let (mode, char_count, data_bits) = match self {
+            Segment::ECI(designator) => {
+                if *qr_type == QRSymbolTypes::MicroQRCode {
+                    return Err(EncodingError::new("MicroQR codes don't support ECI segments"));
+                }
+                return Ok(eci::eci_segment(*designator)?.len());
+            }
+            Segment::Numeric(digits) => {
+                let count = digits.chars().count();
+                (EncodingModes::Numeric, count, count / 3 * 10 + [0, 4, 7][count % 3])
+            }
+            Segment::AlphaNumeric(chars) => {
+                let count = chars.chars().count();
+                (EncodingModes::AlphaNumeric, count, count / 2 * 11 + count % 2 * 6)
+            }
+            Segment::Byte(bytes) => (EncodingModes::Byte, bytes.len(), bytes.len() * 8),
+            Segment::Kanji(kanji) => {
+                let count = kanji.chars().count();
+                (EncodingModes::Kanji, count, count * 13)
+            }
+        };
+
+        if !segmenter::available_modes(qr_type, version).contains(&mode) {
+            return Err(EncodingError::new("This MicroQR version doesn't support the segment's mode"));
+        }
+        let count_bits = Encoder::character_count_bits(qr_type, version, mode);
+        if char_count >= 1 << count_bits {
+            return Err(EncodingError::new("Too many characters for one segment in this version"));
+        }
+
+        Ok(Encoder::mode_indicator_bits(qr_type, version) + count_bits + data_bits)
+    }
+
+    // Checks the characters can be represented in the segment's mode
+    pub(crate) fn validate(&self) -> Result<(), EncodingError> {
+        match self {
+            Segment::Numeric(digits) if digits.chars().any(|c| !c.is_ascii_digit()) => Err(
+                EncodingError::new("Can't encode non-numeric characters in a Numeric segment"),
+            ),
+            Segment::AlphaNumeric(chars) if chars.chars().any(|c| !Encoder::is_fnc1_alphanumeric(c, false)) => {
+                Err(EncodingError::new(
+                    "Can't encode non-alphanumeric characters in an AlphaNumeric segment",
+                ))
+            }
+            Segment::Kanji(kanji) if kanji.chars().any(|c| !Encoder::is_qr_kanji(c)) => Err(EncodingError::new(
+                "Can't encode characters outside the Shift-JIS Kanji set in a Kanji segment",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn mode(&self) -> Option<EncodingModes> {
+        match self {
+            Segment::Numeric(_) => Some(EncodingModes::Numeric),
+            Segment::AlphaNumeric(_) => Some(EncodingModes::AlphaNumeric),
+            Segment::Byte(_) => Some(EncodingModes::Byte),
+            Segment::Kanji(_) => Some(EncodingModes::Kanji),
+            Segment::ECI(_) => None,
+        }
+    }
+
+    pub(crate) fn char_count(&self) -> usize {
+        match self {
+            Segment::Numeric(chars) | Segment::AlphaNumeric(chars) | Segment::Kanji(chars) => chars.chars().count(),
+            Segment::Byte(bytes) => bytes.len(),
+            Segment::ECI(_) => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_length_includes_indicators() {
+        let qr = QRSymbolTypes::QRCode;
+        assert_eq!(Segment::Numeric("01234567".to_string()).bit_length(&qr, 1).unwrap(), 4 + 10 + 27);
+        assert_eq!(Segment::AlphaNumeric("AC-42".to_string()).bit_length(&qr, 1).unwrap(), 4 + 9 + 28);
+        assert_eq!(Segment::Byte(vec![0; 3]).bit_length(&qr, 10).unwrap(), 4 + 16 + 24);
+        assert_eq!(Segment::Kanji("点茗".to_string()).bit_length(&qr, 27).unwrap(), 4 + 12 + 26);
+        assert_eq!(Segment::ECI(26).bit_length(&qr, 40).unwrap(), 12);
+    }
+
+    #[test]
+    fn bit_length_in_micro_qr_codes() {
+        let micro = QRSymbolTypes::MicroQRCode;
+        assert_eq!(Segment::Numeric("12345".to_string()).bit_length(&micro, 1).unwrap(), 3 + 17);
+        assert_eq!(Segment::AlphaNumeric("AB".to_string()).bit_length(&micro, 2).unwrap(), 1 + 3 + 11);
+        assert!(Segment::AlphaNumeric("AB".to_string()).bit_length(&micro, 1).is_err());
+        assert!(Segment::Byte(vec![0]).bit_length(&micro, 2).is_err());
+        assert!(Segment::ECI(3).bit_length(&micro, 4).is_err());
+        assert!(Segment::Numeric("1".to_string()).bit_length(&micro, 5).is_err());
+    }
+
+    #[test]
+    fn bit_length_fails_when_character_count_overflows() {
+        // M1 has a 3-bit character count indicator
+        let micro = QRSymbolTypes::MicroQRCode;
+        assert!(Segment::Numeric("12345678".to_string()).bit_length(&micro, 1).is_err());
+        assert!(Segment::Byte(vec![0; 256]).bit_length(&QRSymbolTypes::QRCode, 9).is_err());
+    }
+
+    #[test]
+    fn validates_segment_characters() {
+        assert!(Segment::Numeric("12a".to_string()).validate().is_err());
+        assert!(Segment::AlphaNumeric("abc".to_string()).validate().is_err());
+        assert!(Segment::Kanji("A".to_string()).validate().is_err());
+        assert!(Segment::Byte(vec![0xFF]).validate().is_ok());
+    }
+}
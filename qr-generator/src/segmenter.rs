@@ -0,0 +1,211 @@
+use crate::{
+    eci::CharacterSets,
+    encoder::{Encoder, EncodingModes},
+    qr_errors::EncodingError,
+    QRSymbolTypes,
+};
+
+// How data is split into segments of different modes when encoding in Dynamic mode
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Default)]
+pub enum SegmentationStrategies {
+    // The shortest possible bitstream for the symbol version
+    #[default]
+    Optimal,
+    // The heuristic from Annex J of ISO/IEC 18004, which only looks a few characters ahead
+    AnnexJ,
+}
+
+const MODES: [EncodingModes; 4] = [
+    EncodingModes::Numeric,
+    EncodingModes::AlphaNumeric,
+    EncodingModes::Byte,
+    EncodingModes::Kanji,
+];
+
+// Splits the data into the runs of (mode, characters) which give the shortest bitstream in a symbol of
+// this version, and returns them with that length in bits, including mode and character count indicators.
+// Costs are tracked in sixths of a bit, so that numeric digits (10 bits per 3) and alphanumeric
+// characters (11 bits per 2) have whole costs.
+pub(crate) fn optimal_segments(
+    data: &str,
+    qr_type: &QRSymbolTypes,
+    version: u32,
+    character_set: Option<CharacterSets>,
+    fnc1: bool,
+) -> Result<(Vec<(EncodingModes, String)>, usize), EncodingError> {
+    if data.is_empty() {
+        return Err(EncodingError::new("No data to encode"));
+    }
+    let character_set = character_set.unwrap_or(CharacterSets::Iso8859_1);
+    let modes = available_modes(qr_type, version);
+    let header_costs: Vec<usize> = modes
+        .iter()
+        .map(|&mode| {
+            (Encoder::mode_indicator_bits(qr_type, version)
+                + Encoder::character_count_bits(qr_type, version, mode))
+                * 6
+        })
+        .collect();
+
+    // costs[m] is the cheapest encoding of the data so far which ends in modes[m]; previous_modes[i][m]
+    // is the mode of character i - 1 in that encoding
+    let chars: Vec<char> = data.chars().collect();
+    let mut costs: Vec<Option<usize>> = vec![None; modes.len()];
+    let mut previous_modes: Vec<Vec<usize>> = Vec::with_capacity(chars.len());
+    for (index, &c) in chars.iter().enumerate() {
+        let mut next_costs = vec![None; modes.len()];
+        let mut previous = vec![0; modes.len()];
+        for (m, &mode) in modes.iter().enumerate() {
+            let Some(char_cost) = char_cost(c, mode, character_set, fnc1) else {
+                continue;
+            };
+
+            // Either carry on with the current segment, or end the cheapest one (its partial bit rounded
+            // up) and start a new segment
+            let mut best = match (index, costs[m]) {
+                (0, _) => Some((header_costs[m], m)),
+                (_, cost) => cost.map(|cost| (cost, m)),
+            };
+            for (k, cost) in costs.iter().enumerate() {
+                if let Some(cost) = cost {
+                    let switch_cost = cost.div_ceil(6) * 6 + header_costs[m];
+                    if best.is_none_or(|(best_cost, _)| switch_cost < best_cost) {
+                        best = Some((switch_cost, k));
+                    }
+                }
+            }
+
+            if let Some((cost, k)) = best {
+                next_costs[m] = Some(cost + char_cost);
+                previous[m] = k;
+            }
+        }
+
+        if next_costs.iter().all(Option::is_none) {
+            return Err(EncodingError::new(&format!(
+                "Can't encode '{}' in any mode available to this symbol",
+                c
+            )));
+        }
+        costs = next_costs;
+        previous_modes.push(previous);
+    }
+
+    let (mut m, cost) = costs
+        .iter()
+        .enumerate()
+        .filter_map(|(m, cost)| cost.map(|cost| (m, cost.div_ceil(6))))
+        .min_by_key(|&(_, cost)| cost)
+        .unwrap();
+
+    let mut char_modes = vec![EncodingModes::Numeric; chars.len()];
+    for index in (0..chars.len()).rev() {
+        char_modes[index] = modes[m];
+        m = previous_modes[index][m];
+    }
+
+    let mut runs: Vec<(EncodingModes, String)> = vec![];
+    for (c, mode) in chars.into_iter().zip(char_modes) {
+        match runs.last_mut() {
+            Some((run_mode, run)) if *run_mode == mode => run.push(c),
+            _ => runs.push((mode, c.to_string())),
+        }
+    }
+
+    Ok((runs, cost))
+}
+
+// M1 Micro QR codes can only hold numeric data, and M2 codes numeric and alphanumeric data
+pub(crate) fn available_modes(qr_type: &QRSymbolTypes, version: u32) -> &'static [EncodingModes] {
+    match (qr_type, version) {
+        (QRSymbolTypes::MicroQRCode, 1) => &MODES[..1],
+        (QRSymbolTypes::MicroQRCode, 2) => &MODES[..2],
+        _ => &MODES,
+    }
+}
+
+// The cost in sixths of a bit of a character in the given mode, or None if the mode can't encode it
+fn char_cost(c: char, mode: EncodingModes, character_set: CharacterSets, fnc1: bool) -> Option<usize> {
+    match mode {
+        EncodingModes::Numeric => c.is_ascii_digit().then_some(20),
+        // In FNC1 modes a literal % is escaped as %%
+        EncodingModes::AlphaNumeric if fnc1 && c == '%' => Some(66),
+        EncodingModes::AlphaNumeric => Encoder::is_fnc1_alphanumeric(c, fnc1).then_some(33),
+        EncodingModes::Byte => character_set.encode_char(c).map(|bytes| bytes.len() * 48),
+        EncodingModes::Kanji => Encoder::is_qr_kanji(c).then_some(78),
+        EncodingModes::Dynamic => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn modes_of(runs: &[(EncodingModes, String)]) -> Vec<(EncodingModes, &str)> {
+        runs.iter().map(|(mode, run)| (*mode, run.as_str())).collect()
+    }
+
+    #[test]
+    fn encodes_single_mode_data_in_one_segment() {
+        let (runs, bits) = optimal_segments("0109501101530003", &QRSymbolTypes::QRCode, 1, None, false).unwrap();
+        assert_eq!(modes_of(&runs), vec![(EncodingModes::Numeric, "0109501101530003")]);
+        // 4 + 10 + 5 * 10 + 4
+        assert_eq!(bits, 68);
+    }
+
+    #[test]
+    fn switches_modes_only_when_shorter() {
+        let (runs, _) = optimal_segments("A1B2C3", &QRSymbolTypes::QRCode, 1, None, false).unwrap();
+        assert_eq!(modes_of(&runs), vec![(EncodingModes::AlphaNumeric, "A1B2C3")]);
+
+        let (runs, bits) =
+            optimal_segments("a0123456789012", &QRSymbolTypes::QRCode, 1, None, false).unwrap();
+        assert_eq!(
+            modes_of(&runs),
+            vec![(EncodingModes::Byte, "a"), (EncodingModes::Numeric, "0123456789012")]
+        );
+        // (4 + 8 + 8) + (4 + 10 + 4 * 10 + 4)
+        assert_eq!(bits, 78);
+    }
+
+    #[test]
+    fn segmentation_depends_on_character_count_widths() {
+        // Six digits between bytes are worth a numeric segment in small symbols, but not once byte mode's
+        // character count indicator is 16 bits wide
+        let data = "aaaaaaaaaaaaaaaa123456aaaaaaaaaaaaaaaa";
+        let (runs, _) = optimal_segments(data, &QRSymbolTypes::QRCode, 9, None, false).unwrap();
+        assert_eq!(runs.len(), 3);
+        let (runs, _) = optimal_segments(data, &QRSymbolTypes::QRCode, 10, None, false).unwrap();
+        assert_eq!(modes_of(&runs), vec![(EncodingModes::Byte, data)]);
+    }
+
+    #[test]
+    fn uses_kanji_mode_for_kanji() {
+        let (runs, bits) = optimal_segments("点茗A", &QRSymbolTypes::QRCode, 1, None, false).unwrap();
+        assert_eq!(
+            modes_of(&runs),
+            vec![(EncodingModes::Kanji, "点茗"), (EncodingModes::AlphaNumeric, "A")]
+        );
+        assert_eq!(bits, (4 + 8 + 26) + (4 + 9 + 6));
+    }
+
+    #[test]
+    fn counts_fnc1_escapes() {
+        let (runs, bits) = optimal_segments("AB%", &QRSymbolTypes::QRCode, 1, None, true).unwrap();
+        assert_eq!(modes_of(&runs), vec![(EncodingModes::AlphaNumeric, "AB%")]);
+        assert_eq!(bits, 4 + 9 + 2 * 11);
+        // Escaping makes a lone % cheaper in byte mode
+        let (runs, _) = optimal_segments("A%", &QRSymbolTypes::QRCode, 1, None, true).unwrap();
+        assert_eq!(modes_of(&runs), vec![(EncodingModes::Byte, "A%")]);
+    }
+
+    #[test]
+    fn micro_qr_code_modes_depend_on_version() {
+        assert!(optimal_segments("12A", &QRSymbolTypes::MicroQRCode, 1, None, false).is_err());
+        let (runs, bits) = optimal_segments("12A", &QRSymbolTypes::MicroQRCode, 2, None, false).unwrap();
+        assert_eq!(modes_of(&runs), vec![(EncodingModes::AlphaNumeric, "12A")]);
+        assert_eq!(bits, 1 + 3 + 11 + 6);
+        assert!(optimal_segments("a", &QRSymbolTypes::MicroQRCode, 2, None, false).is_err());
+        assert!(optimal_segments("a", &QRSymbolTypes::MicroQRCode, 3, None, false).is_ok());
+    }
+}
@@ -1,24 +1,99 @@
 use crate::{
-    encoder::EncodingModes, error_correction::CorrectionLevels, qr_errors::EncodingError, Options,
-    QRError, QRSymbolTypes,
+    eci::{self, CharacterSets},
+    encoder::{Encoder, EncodingModes, Fnc1Modes}, error_correction::CorrectionLevels, qr_errors::EncodingError,
+    segment::Segment, segmenter::{self, SegmentationStrategies}, Options, QRError, QRSymbolTypes,
 };
 pub struct Sizer;
 
 impl Sizer {
     pub(crate) fn calculate_version(options: &Options, data: &String) -> Result<u32, QRError> {
+        Self::calculate_version_with_overhead(options, data, 0)
+    }
+
+    // Sizes the code with room for extra header bits, e.g. for a Structured Append header
+    pub(crate) fn calculate_version_with_overhead(
+        options: &Options,
+        data: &String,
+        overhead_bits: usize,
+    ) -> Result<u32, QRError> {
         let correction = options.correction_level.as_ref().unwrap();
         let mode = options.mode.unwrap_or(EncodingModes::Dynamic);
+        let optimal = mode == EncodingModes::Dynamic
+            && options.segmentation.unwrap_or_default() == SegmentationStrategies::Optimal;
         match options.qr_type {
+            Some(QRSymbolTypes::MicroQRCode) if optimal => {
+                Ok(Self::calculate_optimal_micro_version(correction, data)?)
+            }
             Some(QRSymbolTypes::MicroQRCode) => {
                 Ok(Self::calculate_micro_version(correction, mode, data)?)
             }
             Some(QRSymbolTypes::QRCode) => {
-                Ok(Self::calculate_standard_version(correction, mode, data)?)
+                let character_set = Encoder::select_character_set(options, data)?;
+                let fnc1_bits = Self::fnc1_bits(options);
+                // In FNC1 modes a literal % is escaped as %%
+                let escapes = match options.fnc1 {
+                    Some(_) => data.chars().filter(|&c| c == '%').count(),
+                    None => 0,
+                };
+                if optimal {
+                    return Ok(Self::calculate_optimal_standard_version(
+                        correction,
+                        character_set,
+                        options.fnc1.is_some(),
+                        overhead_bits + fnc1_bits,
+                        data,
+                    )?);
+                }
+                Ok(Self::calculate_standard_version(
+                    correction,
+                    mode,
+                    character_set,
+                    overhead_bits + fnc1_bits,
+                    escapes,
+                    data,
+                )?)
             }
             None => unreachable!(),
         }
     }
 
+    // Sizes hand-built segments by their exact bit length in each version
+    pub(crate) fn calculate_segments_version(options: &Options, segments: &[Segment]) -> Result<u32, QRError> {
+        let qr_type = options.qr_type.as_ref().unwrap();
+        let correction = options.correction_level.as_ref().unwrap();
+        let header_bits = Self::fnc1_bits(options)
+            + match options.eci {
+                Some(character_set) => eci::eci_segment(character_set.eci_designator())?.len(),
+                None => 0,
+            };
+
+        let versions = match qr_type {
+            QRSymbolTypes::MicroQRCode => 1..=4,
+            QRSymbolTypes::QRCode => 1..=40,
+        };
+        for version in versions {
+            if Self::validate_version(qr_type, version, correction).is_err() {
+                continue;
+            }
+            let bits = segments
+                .iter()
+                .map(|segment| segment.bit_length(qr_type, version))
+                .sum::<Result<usize, EncodingError>>();
+            if bits.is_ok_and(|bits| header_bits + bits <= Self::data_bit_capacity(qr_type, version, correction)) {
+                return Ok(version);
+            }
+        }
+        Err(EncodingError::new("The segments don't fit in any version at this error correction level").into())
+    }
+
+    fn fnc1_bits(options: &Options) -> usize {
+        match options.fnc1 {
+            Some(Fnc1Modes::First) => 4,
+            Some(Fnc1Modes::Second(_)) => 12,
+            None => 0,
+        }
+    }
+
     pub(crate) fn data_codeword_capacity(
         qr_type: &QRSymbolTypes,
         version: u32,
@@ -202,6 +277,61 @@ impl Sizer {
         }
     }
 
+    // The number of data bits available; in M1 and M3 codes the final data codeword is only 4 bits long
+    pub(crate) fn data_bit_capacity(
+        qr_type: &QRSymbolTypes,
+        version: u32,
+        correction: &CorrectionLevels,
+    ) -> usize {
+        let capacity = Self::data_codeword_capacity(qr_type, version, correction) * 8;
+        if Self::has_short_final_codeword(qr_type, version) {
+            capacity - 4
+        } else {
+            capacity
+        }
+    }
+
+    pub(crate) fn has_short_final_codeword(qr_type: &QRSymbolTypes, version: u32) -> bool {
+        *qr_type == QRSymbolTypes::MicroQRCode && (version == 1 || version == 3)
+    }
+
+    // Error correction codewords which only guard against misdecoding, and so can't be used to correct
+    // errors. Only the smallest QR Code versions reserve any.
+    pub(crate) fn misdecode_protection_codewords(version: u32, correction: &CorrectionLevels) -> usize {
+        match (version, correction) {
+            (1, CorrectionLevels::L) => 3,
+            (1, CorrectionLevels::M) | (2, CorrectionLevels::L) => 2,
+            (1, _) | (3, CorrectionLevels::L) => 1,
+            _ => 0,
+        }
+    }
+
+    pub(crate) fn validate_version(
+        qr_type: &QRSymbolTypes,
+        version: u32,
+        correction: &CorrectionLevels,
+    ) -> Result<(), EncodingError> {
+        match qr_type {
+            QRSymbolTypes::MicroQRCode => match (version, correction) {
+                (1, CorrectionLevels::DetectionOnly) => Ok(()),
+                (1, _) => Err(EncodingError::new("M1 MicroQR only supports error detection")),
+                (2..=3, CorrectionLevels::L | CorrectionLevels::M) => Ok(()),
+                (4, CorrectionLevels::L | CorrectionLevels::M | CorrectionLevels::Q) => Ok(()),
+                (2..=4, _) => Err(EncodingError::new(
+                    "Unsupported error correction level for this MicroQR version",
+                )),
+                _ => Err(EncodingError::new("MicroQR versions range from 1 to 4")),
+            },
+            QRSymbolTypes::QRCode => match (version, correction) {
+                (1..=40, CorrectionLevels::DetectionOnly) => Err(EncodingError::new(
+                    "Error detection only is only available in M1 MicroQR codes",
+                )),
+                (1..=40, _) => Ok(()),
+                _ => Err(EncodingError::new("QR versions range from 1 to 40")),
+            },
+        }
+    }
+
     // Returns a vector of (total codewords, ec codewords, repeat)
     pub(crate) fn error_correction_shape(
         qr_type: &QRSymbolTypes,
@@ -386,11 +516,98 @@ impl Sizer {
         }
     }
 
+    // Finds the smallest version holding the optimal segmentation of the data. Character count indicators
+    // widen at versions 10 and 27, which changes the optimal segmentation, so it is redone for each range.
+    fn calculate_optimal_standard_version(
+        correction: &CorrectionLevels,
+        character_set: Option<CharacterSets>,
+        fnc1: bool,
+        overhead_bits: usize,
+        data: &str,
+    ) -> Result<u32, EncodingError> {
+        if *correction == CorrectionLevels::DetectionOnly {
+            return Err(EncodingError::new("Can't simply detect errors in a Standard QR code"));
+        }
+        let header_bits = overhead_bits
+            + match character_set {
+                Some(character_set) => eci::eci_segment(character_set.eci_designator())?.len(),
+                None => 0,
+            };
+
+        for versions in [1..=9, 10..=26, 27..=40] {
+            let (_, data_bits) = segmenter::optimal_segments(
+                data,
+                &QRSymbolTypes::QRCode,
+                *versions.end(),
+                character_set,
+                fnc1,
+            )?;
+            let fits = versions.into_iter().find(|&version| {
+                Self::data_bit_capacity(&QRSymbolTypes::QRCode, version, correction) >= header_bits + data_bits
+            });
+            if let Some(version) = fits {
+                return Ok(version);
+            }
+        }
+        Err(EncodingError::new("Too much data for error correction level"))
+    }
+
+    // Micro QR versions differ in the modes they support as well as their indicator widths, so each is tried
+    fn calculate_optimal_micro_version(correction: &CorrectionLevels, data: &str) -> Result<u32, EncodingError> {
+        for version in 1..=4 {
+            if Self::validate_version(&QRSymbolTypes::MicroQRCode, version, correction).is_err() {
+                continue;
+            }
+            let fits = segmenter::optimal_segments(data, &QRSymbolTypes::MicroQRCode, version, None, false)
+                .is_ok_and(|(_, data_bits)| {
+                    data_bits <= Self::data_bit_capacity(&QRSymbolTypes::MicroQRCode, version, correction)
+                });
+            if fits {
+                return Ok(version);
+            }
+        }
+        Err(EncodingError::new(
+            "Can't fit the data in a MicroQR code with this error correction level",
+        ))
+    }
+
+    fn calculate_micro_byte_version(correction: &CorrectionLevels, byte_count: usize) -> Result<u32, EncodingError> {
+        match (correction, byte_count) {
+            (CorrectionLevels::L, 0..=9) | (CorrectionLevels::M, 0..=7) => Ok(3),
+            (CorrectionLevels::L, 10..=15) | (CorrectionLevels::M, 8..=13) | (CorrectionLevels::Q, 0..=9) => Ok(4),
+            (CorrectionLevels::L, _) => Err(EncodingError::new("Can't encode more than 15 bytes in an L-correction MicroQR")),
+            (CorrectionLevels::M, _) => Err(EncodingError::new("Can't encode more than 13 bytes in an M-correction MicroQR")),
+            (CorrectionLevels::Q, _) => Err(EncodingError::new("Can't encode more than 9 bytes in an Q-correction MicroQR")),
+            (CorrectionLevels::DetectionOnly, _) => Err(EncodingError::new(
+                "Error Detection Only implies M1 MicroQR; can't encode bytes in M1 MicroQR",
+            )),
+            (CorrectionLevels::H, _) => Err(EncodingError::new("Can't use H-level error correction in a MicroQR")),
+        }
+    }
+
     fn calculate_micro_version(
         correction: &CorrectionLevels,
         mode: EncodingModes,
         data: &String,
     ) -> Result<u32, EncodingError> {
+        // Micro QR codes have very different capacities per mode, so size dynamic data by the
+        // narrowest mode that can hold all of it
+        let mode = match mode {
+            EncodingModes::Dynamic => {
+                if data.chars().all(|c| Encoder::char_type(c) == EncodingModes::Numeric) {
+                    EncodingModes::Numeric
+                } else if data.chars().all(|c| {
+                    matches!(Encoder::char_type(c), EncodingModes::Numeric | EncodingModes::AlphaNumeric)
+                }) {
+                    EncodingModes::AlphaNumeric
+                } else {
+                    EncodingModes::Byte
+                }
+            }
+            mode => mode,
+        };
+        // Micro QR codes can't declare an ECI, so byte data is ISO-8859-1: one byte per character
+        let byte_count = data.chars().count();
         match correction {
       CorrectionLevels::DetectionOnly => {
         if data.len() > 5 {
@@ -414,12 +631,8 @@ impl Sizer {
             15..=21 => Ok(4),
             _ => Err(EncodingError::new("Can't encode more than 21 alphanumeric characters in an L-correction MicroQR"))
           },
-          EncodingModes::Byte | EncodingModes::Dynamic => match data.len() {
-            0..=9 => Ok(3),
-            10..=15 => Ok(4),
-            _ => Err(EncodingError::new("Can't encode more than 15 bytes in an L-correction MicroQR"))
-          },
-          EncodingModes::Kanji => match data.len() {
+          EncodingModes::Byte | EncodingModes::Dynamic => Self::calculate_micro_byte_version(correction, byte_count),
+          EncodingModes::Kanji => match data.chars().count() {
             0..=6 => Ok(3),
             7..=9 => Ok(4),
             _ => Err(EncodingError::new("Can't encode more than 9 kanji in an L-correction MicroQR"))
@@ -440,12 +653,8 @@ impl Sizer {
             12..=18 => Ok(4),
             _ => Err(EncodingError::new("Can't encode more than 18 alphanumeric characters in an M-correction MicroQR"))
           },
-          EncodingModes::Byte | EncodingModes::Dynamic => match data.len() {
-            0..=7 => Ok(3),
-            8..=13 => Ok(4),
-            _ => Err(EncodingError::new("Can't encode more than 13 bytes in an M-correction MicroQR"))
-          },
-          EncodingModes::Kanji => match data.len() {
+          EncodingModes::Byte | EncodingModes::Dynamic => Self::calculate_micro_byte_version(correction, byte_count),
+          EncodingModes::Kanji => match data.chars().count() {
             0..=4 => Ok(3),
             5..=8 => Ok(4),
             _ => Err(EncodingError::new("Can't encode more than 8 kanji in an M-correction MicroQR"))
@@ -462,11 +671,8 @@ impl Sizer {
             0..=13 => Ok(4),
             _ => Err(EncodingError::new("Can't encode more than 13 alphanumeric characters in an Q-correction MicroQR"))
           },
-          EncodingModes::Byte | EncodingModes::Dynamic => match data.len() {
-            0..=9 => Ok(4),
-            _ => Err(EncodingError::new("Can't encode more than 9 bytes in an Q-correction MicroQR"))
-          },
-          EncodingModes::Kanji => match data.len() {
+          EncodingModes::Byte | EncodingModes::Dynamic => Self::calculate_micro_byte_version(correction, byte_count),
+          EncodingModes::Kanji => match data.chars().count() {
             0..=5 => Ok(4),
             _ => Err(EncodingError::new("Can't encode more than 5 kanji in an Q-correction MicroQR"))
           },
@@ -479,25 +685,48 @@ impl Sizer {
     fn calculate_standard_version(
         correction: &CorrectionLevels,
         mode: EncodingModes,
+        character_set: Option<CharacterSets>,
+        overhead_bits: usize,
+        escapes: usize,
         data: &String,
     ) -> Result<u32, EncodingError> {
+        // ECI and other headers take up space too; count them as however many extra characters they displace
+        let header_bits = overhead_bits
+            + match character_set {
+                Some(character_set) => eci::eci_segment(character_set.eci_designator())?.len(),
+                None => 0,
+            };
         match mode {
-            EncodingModes::Numeric => Self::calculate_standard_numeric_version(correction, data),
-            EncodingModes::AlphaNumeric => {
-                Self::calculate_standard_alphanumeric_version(correction, data)
-            }
+            EncodingModes::Numeric => Self::calculate_standard_numeric_version(
+                correction,
+                data.len() + (header_bits * 3).div_ceil(10),
+            ),
+            EncodingModes::AlphaNumeric => Self::calculate_standard_alphanumeric_version(
+                correction,
+                data.len() + escapes + (header_bits * 2).div_ceil(11),
+            ),
             EncodingModes::Byte | EncodingModes::Dynamic => {
-                Self::calculate_standard_byte_version(correction, data)
+                // Characters which can't be represented will be rejected by the Encoder, or are Kanji
+                // which take less than 2 bytes in Kanji mode
+                let character_set = character_set.unwrap_or(CharacterSets::Iso8859_1);
+                let byte_count: usize = data
+                    .chars()
+                    .map(|c| character_set.encode_char(c).map_or(2, |bytes| bytes.len()))
+                    .sum();
+                Self::calculate_standard_byte_version(correction, byte_count + header_bits.div_ceil(8))
             }
-            EncodingModes::Kanji => Self::calculate_standard_kanji_version(correction, data),
+            EncodingModes::Kanji => Self::calculate_standard_kanji_version(
+                correction,
+                data.chars().count() + header_bits.div_ceil(13),
+            ),
         }
     }
 
     fn calculate_standard_numeric_version(
         correction: &CorrectionLevels,
-        data: &String,
+        char_count: usize,
     ) -> Result<u32, EncodingError> {
-        match (correction, data.len()) {
+        match (correction, char_count) {
             (CorrectionLevels::L, 1..=41)
             | (CorrectionLevels::M, 1..=34)
             | (CorrectionLevels::Q, 1..=27)
@@ -669,9 +898,9 @@ impl Sizer {
 
     fn calculate_standard_alphanumeric_version(
         correction: &CorrectionLevels,
-        data: &String,
+        char_count: usize,
     ) -> Result<u32, EncodingError> {
-        match (correction, data.len()) {
+        match (correction, char_count) {
             (CorrectionLevels::L, 1..=25)
             | (CorrectionLevels::M, 1..=20)
             | (CorrectionLevels::Q, 1..=16)
@@ -843,9 +1072,9 @@ impl Sizer {
 
     fn calculate_standard_byte_version(
         correction: &CorrectionLevels,
-        data: &String,
+        byte_count: usize,
     ) -> Result<u32, EncodingError> {
-        match (correction, data.len()) {
+        match (correction, byte_count) {
             (CorrectionLevels::L, 1..=17)
             | (CorrectionLevels::M, 1..=14)
             | (CorrectionLevels::Q, 1..=11)
@@ -1016,53 +1245,176 @@ impl Sizer {
     }
 
     fn calculate_standard_kanji_version(
-        _correction: &CorrectionLevels,
-        _data: &String,
+        correction: &CorrectionLevels,
+        char_count: usize,
     ) -> Result<u32, EncodingError> {
-        Err(EncodingError::new("Kanji not yet supported"))
-        // match (correction, data.len()) {
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(1),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(2),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(3),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(4),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(5),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(6),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(7),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(8),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(9),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(10),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(11),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(12),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(13),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(14),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(15),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(16),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(17),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(18),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(19),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(20),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(21),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(22),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(23),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(24),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(25),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(26),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(27),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(28),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(29),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(30),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(31),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(32),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(33),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(34),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(35),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(36),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(37),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(38),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(39),
-        //   (CorrectionLevels::L, ..=) | (CorrectionLevels::M, ..=) | (CorrectionLevels::Q, ..=) | (CorrectionLevels::H, ..=) => Ok(40),
-        //   (CorrectionLevels::DetectionOnly, _) => Err(EncodingError::new("Can't simply detect errors in a Standard QR code")),
-        //   _ => Err(EncodingError::new("Too much data for Numeric mode and error correction level"))
-        // }
+        match (correction, char_count) {
+            (CorrectionLevels::L, 1..=10)
+            | (CorrectionLevels::M, 1..=8)
+            | (CorrectionLevels::Q, 1..=7)
+            | (CorrectionLevels::H, 1..=4) => Ok(1),
+            (CorrectionLevels::L, 11..=20)
+            | (CorrectionLevels::M, 9..=16)
+            | (CorrectionLevels::Q, 8..=12)
+            | (CorrectionLevels::H, 5..=8) => Ok(2),
+            (CorrectionLevels::L, 21..=32)
+            | (CorrectionLevels::M, 17..=26)
+            | (CorrectionLevels::Q, 13..=20)
+            | (CorrectionLevels::H, 9..=15) => Ok(3),
+            (CorrectionLevels::L, 33..=48)
+            | (CorrectionLevels::M, 27..=38)
+            | (CorrectionLevels::Q, 21..=28)
+            | (CorrectionLevels::H, 16..=21) => Ok(4),
+            (CorrectionLevels::L, 49..=65)
+            | (CorrectionLevels::M, 39..=52)
+            | (CorrectionLevels::Q, 29..=37)
+            | (CorrectionLevels::H, 22..=27) => Ok(5),
+            (CorrectionLevels::L, 66..=82)
+            | (CorrectionLevels::M, 53..=65)
+            | (CorrectionLevels::Q, 38..=45)
+            | (CorrectionLevels::H, 28..=36) => Ok(6),
+            (CorrectionLevels::L, 83..=95)
+            | (CorrectionLevels::M, 66..=75)
+            | (CorrectionLevels::Q, 46..=53)
+            | (CorrectionLevels::H, 37..=39) => Ok(7),
+            (CorrectionLevels::L, 96..=118)
+            | (CorrectionLevels::M, 76..=93)
+            | (CorrectionLevels::Q, 54..=66)
+            | (CorrectionLevels::H, 40..=52) => Ok(8),
+            (CorrectionLevels::L, 119..=141)
+            | (CorrectionLevels::M, 94..=111)
+            | (CorrectionLevels::Q, 67..=80)
+            | (CorrectionLevels::H, 53..=60) => Ok(9),
+            (CorrectionLevels::L, 142..=167)
+            | (CorrectionLevels::M, 112..=131)
+            | (CorrectionLevels::Q, 81..=93)
+            | (CorrectionLevels::H, 61..=74) => Ok(10),
+            (CorrectionLevels::L, 168..=198)
+            | (CorrectionLevels::M, 132..=155)
+            | (CorrectionLevels::Q, 94..=109)
+            | (CorrectionLevels::H, 75..=85) => Ok(11),
+            (CorrectionLevels::L, 199..=226)
+            | (CorrectionLevels::M, 156..=177)
+            | (CorrectionLevels::Q, 110..=125)
+            | (CorrectionLevels::H, 86..=96) => Ok(12),
+            (CorrectionLevels::L, 227..=262)
+            | (CorrectionLevels::M, 178..=204)
+            | (CorrectionLevels::Q, 126..=149)
+            | (CorrectionLevels::H, 97..=109) => Ok(13),
+            (CorrectionLevels::L, 263..=282)
+            | (CorrectionLevels::M, 205..=223)
+            | (CorrectionLevels::Q, 150..=159)
+            | (CorrectionLevels::H, 110..=120) => Ok(14),
+            (CorrectionLevels::L, 283..=320)
+            | (CorrectionLevels::M, 224..=254)
+            | (CorrectionLevels::Q, 160..=180)
+            | (CorrectionLevels::H, 121..=136) => Ok(15),
+            (CorrectionLevels::L, 321..=361)
+            | (CorrectionLevels::M, 255..=277)
+            | (CorrectionLevels::Q, 181..=198)
+            | (CorrectionLevels::H, 137..=154) => Ok(16),
+            (CorrectionLevels::L, 362..=397)
+            | (CorrectionLevels::M, 278..=310)
+            | (CorrectionLevels::Q, 199..=224)
+            | (CorrectionLevels::H, 155..=173) => Ok(17),
+            (CorrectionLevels::L, 398..=442)
+            | (CorrectionLevels::M, 311..=345)
+            | (CorrectionLevels::Q, 225..=243)
+            | (CorrectionLevels::H, 174..=191) => Ok(18),
+            (CorrectionLevels::L, 443..=488)
+            | (CorrectionLevels::M, 346..=384)
+            | (CorrectionLevels::Q, 244..=272)
+            | (CorrectionLevels::H, 192..=208) => Ok(19),
+            (CorrectionLevels::L, 489..=528)
+            | (CorrectionLevels::M, 385..=410)
+            | (CorrectionLevels::Q, 273..=297)
+            | (CorrectionLevels::H, 209..=235) => Ok(20),
+            (CorrectionLevels::L, 529..=572)
+            | (CorrectionLevels::M, 411..=438)
+            | (CorrectionLevels::Q, 298..=314)
+            | (CorrectionLevels::H, 236..=248) => Ok(21),
+            (CorrectionLevels::L, 573..=618)
+            | (CorrectionLevels::M, 439..=480)
+            | (CorrectionLevels::Q, 315..=348)
+            | (CorrectionLevels::H, 249..=270) => Ok(22),
+            (CorrectionLevels::L, 619..=672)
+            | (CorrectionLevels::M, 481..=528)
+            | (CorrectionLevels::Q, 349..=376)
+            | (CorrectionLevels::H, 271..=284) => Ok(23),
+            (CorrectionLevels::L, 673..=721)
+            | (CorrectionLevels::M, 529..=561)
+            | (CorrectionLevels::Q, 377..=407)
+            | (CorrectionLevels::H, 285..=315) => Ok(24),
+            (CorrectionLevels::L, 722..=784)
+            | (CorrectionLevels::M, 562..=614)
+            | (CorrectionLevels::Q, 408..=440)
+            | (CorrectionLevels::H, 316..=330) => Ok(25),
+            (CorrectionLevels::L, 785..=842)
+            | (CorrectionLevels::M, 615..=652)
+            | (CorrectionLevels::Q, 441..=462)
+            | (CorrectionLevels::H, 331..=365) => Ok(26),
+            (CorrectionLevels::L, 843..=902)
+            | (CorrectionLevels::M, 653..=692)
+            | (CorrectionLevels::Q, 463..=496)
+            | (CorrectionLevels::H, 366..=385) => Ok(27),
+            (CorrectionLevels::L, 903..=940)
+            | (CorrectionLevels::M, 693..=732)
+            | (CorrectionLevels::Q, 497..=534)
+            | (CorrectionLevels::H, 386..=405) => Ok(28),
+            (CorrectionLevels::L, 941..=1002)
+            | (CorrectionLevels::M, 733..=778)
+            | (CorrectionLevels::Q, 535..=559)
+            | (CorrectionLevels::H, 406..=430) => Ok(29),
+            (CorrectionLevels::L, 1003..=1066)
+            | (CorrectionLevels::M, 779..=843)
+            | (CorrectionLevels::Q, 560..=604)
+            | (CorrectionLevels::H, 431..=457) => Ok(30),
+            (CorrectionLevels::L, 1067..=1132)
+            | (CorrectionLevels::M, 844..=894)
+            | (CorrectionLevels::Q, 605..=634)
+            | (CorrectionLevels::H, 458..=486) => Ok(31),
+            (CorrectionLevels::L, 1133..=1201)
+            | (CorrectionLevels::M, 895..=947)
+            | (CorrectionLevels::Q, 635..=684)
+            | (CorrectionLevels::H, 487..=518) => Ok(32),
+            (CorrectionLevels::L, 1202..=1273)
+            | (CorrectionLevels::M, 948..=1002)
+            | (CorrectionLevels::Q, 685..=719)
+            | (CorrectionLevels::H, 519..=553) => Ok(33),
+            (CorrectionLevels::L, 1274..=1347)
+            | (CorrectionLevels::M, 1003..=1060)
+            | (CorrectionLevels::Q, 720..=756)
+            | (CorrectionLevels::H, 554..=590) => Ok(34),
+            (CorrectionLevels::L, 1348..=1417)
+            | (CorrectionLevels::M, 1061..=1113)
+            | (CorrectionLevels::Q, 757..=790)
+            | (CorrectionLevels::H, 591..=605) => Ok(35),
+            (CorrectionLevels::L, 1418..=1496)
+            | (CorrectionLevels::M, 1114..=1176)
+            | (CorrectionLevels::Q, 791..=832)
+            | (CorrectionLevels::H, 606..=647) => Ok(36),
+            (CorrectionLevels::L, 1497..=1577)
+            | (CorrectionLevels::M, 1177..=1224)
+            | (CorrectionLevels::Q, 833..=876)
+            | (CorrectionLevels::H, 648..=673) => Ok(37),
+            (CorrectionLevels::L, 1578..=1661)
+            | (CorrectionLevels::M, 1225..=1292)
+            | (CorrectionLevels::Q, 877..=923)
+            | (CorrectionLevels::H, 674..=701) => Ok(38),
+            (CorrectionLevels::L, 1662..=1729)
+            | (CorrectionLevels::M, 1293..=1362)
+            | (CorrectionLevels::Q, 924..=972)
+            | (CorrectionLevels::H, 702..=750) => Ok(39),
+            (CorrectionLevels::L, 1730..=1817)
+            | (CorrectionLevels::M, 1363..=1435)
+            | (CorrectionLevels::Q, 973..=1024)
+            | (CorrectionLevels::H, 751..=784) => Ok(40),
+            (CorrectionLevels::DetectionOnly, _) => Err(EncodingError::new(
+                "Can't simply detect errors in a Standard QR code",
+            )),
+            _ => Err(EncodingError::new(
+                "Too much data for Kanji mode and error correction level",
+            )),
+        }
     }
 }
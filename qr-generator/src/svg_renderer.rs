@@ -0,0 +1,176 @@
+use std::fmt::Write;
+
+use crate::{colour::Colour, logo::Logo, module_style::ModuleStyle, qr_errors::QRError, QRCode};
+
+// Draws symbols as SVG. Sizes are in modules, through the viewBox, so the image scales to whatever
+// width and height it's placed at.
+pub(crate) struct SvgRenderer {
+    pub quiet_zone: usize,
+    pub foreground: Colour,
+    pub background: Colour,
+    // Shapes to draw modules with, rather than plain squares
+    pub style: Option<ModuleStyle>,
+    // Embedded as a data URI over the centre of the symbol
+    pub logo: Option<Logo>,
+}
+
+impl SvgRenderer {
+    pub fn render(&self, qr_code: &QRCode) -> Result<String, QRError> {
+        let cleared;
+        let qr_code = match &self.logo {
+            Some(logo) => {
+                cleared = logo.clear(qr_code)?;
+                &cleared
+            }
+            None => qr_code,
+        };
+        let dimension = qr_code.width() + self.quiet_zone * 2;
+        let mut svg = String::new();
+        writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+        // Curved shapes need anti-aliasing, but square modules are sharpest without it
+        let rendering = if self.style.is_some() { "" } else { r#" shape-rendering="crispEdges""# };
+        // SVG 1.1 links images through the XLink namespace
+        let xlink = if self.logo.is_some() { r#" xmlns:xlink="http://www.w3.org/1999/xlink""# } else { "" };
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg"{2} version="1.1" viewBox="0 0 {0} {0}"{1}>"#,
+            dimension, rendering, xlink
+        )
+        .unwrap();
+        if self.background.a > 0 {
+            writeln!(
+                svg,
+                r#"<rect width="{0}" height="{0}" fill="{1}"{2}/>"#,
+                dimension,
+                self.background.to_hex(),
+                Self::opacity(&self.background)
+            )
+            .unwrap();
+        }
+        let (fill_rule, path_data) = match &self.style {
+            Some(style) => (r#" fill-rule="evenodd""#, style.svg_path(&qr_code.matrix, self.quiet_zone)),
+            None => ("", self.path_data(qr_code)),
+        };
+        writeln!(
+            svg,
+            r#"<path fill="{}"{}{} d="{}"/>"#,
+            self.foreground.to_hex(),
+            Self::opacity(&self.foreground),
+            fill_rule,
+            path_data
+        )
+        .unwrap();
+        if let Some(logo) = &self.logo {
+            let placement = logo.placement(qr_code.width())?;
+            writeln!(
+                svg,
+                r#"<image x="{}" y="{}" width="{}" height="{}" preserveAspectRatio="none" xlink:href="{}"/>"#,
+                Self::number(placement.left + self.quiet_zone as f64),
+                Self::number(placement.top + self.quiet_zone as f64),
+                Self::number(placement.width),
+                Self::number(placement.height),
+                logo.data_uri()?
+            )
+            .unwrap();
+        }
+        svg.push_str("</svg>\n");
+        Ok(svg)
+    }
+
+    // Up to 4 decimal places, without trailing zeros
+    fn number(value: f64) -> String {
+        let formatted = format!("{:.4}", value);
+        formatted.trim_end_matches('0').trim_end_matches('.').to_string()
+    }
+
+    // Each horizontal run of dark modules is one closed subpath
+    fn path_data(&self, qr_code: &QRCode) -> String {
+        let mut path = String::new();
+        for (x, y, length) in qr_code.matrix.dark_runs() {
+            write!(path, "M{},{}h{}v1h-{}z", x + self.quiet_zone, y + self.quiet_zone, length, length).unwrap();
+        }
+        path
+    }
+
+    fn opacity(colour: &Colour) -> String {
+        if colour.a == 255 {
+            String::new()
+        } else {
+            format!(r#" fill-opacity="{:.3}""#, colour.a as f32 / 255.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{hello, svg_renderer as renderer};
+    use crate::QRGenerator;
+
+    #[test]
+    fn merges_dark_modules_into_runs() {
+        let qr_code = hello();
+        let path = renderer().path_data(&qr_code);
+        // The top row starts with the 7 modules of the top left finder pattern
+        assert!(path.starts_with("M4,4h7v1h-7z"));
+
+        let runs = (0..qr_code.width())
+            .flat_map(|y| (0..qr_code.width()).map(move |x| (x, y)))
+            .filter(|&(x, y)| qr_code.is_dark(x, y) && (x == 0 || !qr_code.is_dark(x - 1, y)))
+            .count();
+        assert_eq!(path.matches('M').count(), runs);
+    }
+
+    #[test]
+    fn sizes_by_view_box_including_quiet_zone() {
+        let qr_code = hello();
+        let svg = renderer().render(&qr_code).unwrap();
+        assert!(svg.contains(r#"viewBox="0 0 29 29""#));
+        assert!(svg.contains(r##"<rect width="29" height="29" fill="#ffffff"/>"##));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn uses_configured_colours() {
+        let qr_code = hello();
+        let svg = SvgRenderer {
+            quiet_zone: 0,
+            foreground: Colour::rgba(0x12, 0x34, 0x56, 128),
+            background: Colour::TRANSPARENT,
+            style: None,
+            logo: None,
+        }
+        .render(&qr_code).unwrap();
+        assert!(svg.contains(r#"viewBox="0 0 21 21""#));
+        assert!(!svg.contains("<rect"));
+        assert!(svg.contains(r##"<path fill="#123456" fill-opacity="0.502" d="M0,0h7v1h-7z"##));
+    }
+
+    #[test]
+    fn draws_styled_modules() {
+        let qr_code = hello();
+        let svg = SvgRenderer { style: Some(ModuleStyle::dots()), ..renderer() }.render(&qr_code).unwrap();
+        assert!(!svg.contains("crispEdges"));
+        assert!(svg.contains(r##"<path fill="#000000" fill-rule="evenodd" d="M4,4h7v7h-7zM5,5h5v5h-5zM7.5,6h0a1.5,1.5"##));
+    }
+
+    #[test]
+    fn embeds_logo() {
+        let mut png = vec![];
+        image::RgbaImage::new(4, 2)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+        let logo = Logo { width: 0.2, margin: 0, ..Logo::new(png) };
+        let mut generator = QRGenerator::default();
+        generator.options.correction_level = Some(crate::CorrectionLevels::H);
+        generator.options.logo = Some(logo.clone());
+        let qr_code = generator.make_qr_code("Hello".to_string()).unwrap();
+
+        let svg = SvgRenderer { logo: Some(logo), ..renderer() }.render(&qr_code).unwrap();
+        assert!(svg.contains(r#"xmlns:xlink="http://www.w3.org/1999/xlink""#));
+        // 4.2 modules wide and half as tall, centred in the 21 module symbol
+        assert!(svg.contains(r#"<image x="12.4" y="13.45" width="4.2" height="2.1" preserveAspectRatio="none" xlink:href="data:image/png;base64,iVBOR"#));
+        // Modules 8 to 12 across and 9 to 11 down are cleared
+        assert!(!svg.contains("M12,13h"));
+    }
+}
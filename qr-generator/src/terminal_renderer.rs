@@ -0,0 +1,104 @@
+use std::fmt::Write;
+
+use crate::{colour::Colour, QRCode};
+
+// Draws symbols as text with Unicode half blocks, two rows of modules to each line of characters.
+// Terminal character cells are roughly twice as tall as they are wide, so the modules come out square.
+pub(crate) struct TerminalRenderer {
+    pub quiet_zone: usize,
+    // Draw the light modules as blocks rather than the dark ones, for light text on a dark terminal
+    pub inverted: bool,
+    // Set the dark and light colours with 24-bit ANSI escapes, rather than relying on the terminal's own.
+    // Blocks are drawn in the foreground colour, so when inverted it is the light colour.
+    pub colours: Option<(Colour, Colour)>,
+}
+
+impl TerminalRenderer {
+    pub fn render(&self, qr_code: &QRCode) -> String {
+        let dimension = qr_code.width() + self.quiet_zone * 2;
+        // Modules outside the symbol are the light quiet zone
+        let is_dark = |x: usize, y: usize| {
+            x >= self.quiet_zone
+                && y >= self.quiet_zone
+                && x - self.quiet_zone < qr_code.width()
+                && y - self.quiet_zone < qr_code.width()
+                && qr_code.is_dark(x - self.quiet_zone, y - self.quiet_zone)
+        };
+        let is_block = |x: usize, y: usize| y < dimension && is_dark(x, y) != self.inverted;
+
+        let mut text = String::new();
+        for y in (0..dimension).step_by(2) {
+            if let Some((dark, light)) = self.colours {
+                let (block, space) = if self.inverted { (light, dark) } else { (dark, light) };
+                write!(text, "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m", block.r, block.g, block.b, space.r, space.g, space.b)
+                    .unwrap();
+            }
+            for x in 0..dimension {
+                text.push(match (is_block(x, y), is_block(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            if self.colours.is_some() {
+                text.push_str("\x1b[0m");
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::hello;
+
+    fn render(quiet_zone: usize, inverted: bool, colours: Option<(Colour, Colour)>) -> Vec<String> {
+        TerminalRenderer { quiet_zone, inverted, colours }
+            .render(&hello())
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn draws_two_rows_of_modules_per_line() {
+        let lines = render(0, false, None);
+        assert_eq!(lines.len(), 11);
+        assert!(lines[0].starts_with("█▀▀▀▀▀█ "));
+        assert!(lines[1].starts_with("█ ███ █ "));
+        // The odd last row only fills the top half of its line
+        assert!(lines[10].starts_with("▀▀▀▀▀▀▀ "));
+        assert!(lines.iter().all(|line| line.chars().count() == 21));
+    }
+
+    #[test]
+    fn includes_quiet_zone() {
+        let lines = render(2, false, None);
+        assert_eq!(lines.len(), 13);
+        assert_eq!(lines[0], " ".repeat(25));
+        assert!(lines[1].starts_with("  █▀▀▀▀▀█ "));
+    }
+
+    #[test]
+    fn inverts_for_dark_terminals() {
+        let lines = render(1, true, None);
+        assert!(lines[0].starts_with("█▀▀▀▀▀▀▀█"));
+        assert_eq!(lines[0].chars().count(), 23);
+    }
+
+    #[test]
+    fn sets_ansi_colours() {
+        let lines = render(0, false, Some((Colour::BLACK, Colour::WHITE)));
+        assert!(lines[0].starts_with("\x1b[38;2;0;0;0m\x1b[48;2;255;255;255m█▀▀▀▀▀█ "));
+        assert!(lines[0].ends_with("\x1b[0m"));
+    }
+
+    #[test]
+    fn swaps_ansi_colours_when_inverted() {
+        let lines = render(0, true, Some((Colour::BLACK, Colour::WHITE)));
+        assert!(lines[0].starts_with("\x1b[38;2;255;255;255m\x1b[48;2;0;0;0m ▄▄▄▄▄ █"));
+    }
+}
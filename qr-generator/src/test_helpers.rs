@@ -0,0 +1,45 @@
+// Fixtures shared by the tests of the renderers and module styles
+use crate::colour::Colour;
+use crate::print_renderer::{Lengths, PrintRenderer};
+use crate::raster_renderer::RasterRenderer;
+use crate::svg_renderer::SvgRenderer;
+use crate::{QRCode, QRGenerator};
+
+// A version 1 symbol, 21 modules wide, at the default options
+pub(crate) fn hello() -> QRCode {
+    QRGenerator::default().make_qr_code("Hello".to_string()).unwrap()
+}
+
+// Each renderer with a quiet zone of 4 and black modules on white
+pub(crate) fn svg_renderer() -> SvgRenderer {
+    SvgRenderer {
+        quiet_zone: 4,
+        foreground: Colour::BLACK,
+        background: Colour::WHITE,
+        style: None,
+        logo: None,
+    }
+}
+
+pub(crate) fn raster_renderer() -> RasterRenderer {
+    RasterRenderer {
+        quiet_zone: 4,
+        pixels_per_module: 10,
+        size: None,
+        foreground: Colour::BLACK,
+        background: Colour::WHITE,
+        dpi: None,
+        jpeg_quality: 90,
+        style: None,
+        logo: None,
+    }
+}
+
+pub(crate) fn print_renderer(module_size: Lengths) -> PrintRenderer {
+    PrintRenderer {
+        quiet_zone: 4,
+        module_size,
+        foreground: Colour::BLACK,
+        background: Colour::WHITE,
+    }
+}
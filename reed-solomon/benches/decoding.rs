@@ -0,0 +1,64 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use reed_solomon::{
+    BerlekampMassey, ErrorLocator, Euclidean, GaloisField, IntMod, IsGaloisField, One, Polynomial, PolyWithinGF,
+    ReedSolomonEncoder,
+};
+
+type Element = Polynomial<IntMod<2>>;
+type GF256 = GaloisField<2, 8, 285, 2>;
+
+// Blocks as QR Codes use them, as (codewords, EC codewords): version 1-M, 2-H, 4-L and the largest
+// blocks of 40-L. Each has as many errors as it can correct.
+const BLOCKS: [(usize, usize); 4] = [(26, 10), (44, 28), (100, 20), (149, 30)];
+
+fn damaged_block(codeword_count: usize, ec_count: usize) -> Vec<Element> {
+    let message = (0..codeword_count - ec_count).map(|i| Element::from((i * 37 % 256) as u32)).collect();
+    let mut block = ReedSolomonEncoder::<GF256>::new().encode(message, ec_count);
+    for i in 0..ec_count / 2 {
+        let position = i * codeword_count / (ec_count / 2);
+        block[position] = Element::from(u32::from(block[position].clone()) ^ 0x5A);
+    }
+    block
+}
+
+fn syndromes(block: &[Element], ec_count: usize) -> Vec<PolyWithinGF<GF256>> {
+    let block_poly = Polynomial::from(block.iter().rev().map(|cw| GF256::make_polynomial(cw.clone())).collect::<Vec<_>>());
+    let mut alpha_power = GF256::make_polynomial(Element::one());
+    (0..ec_count)
+        .map(|_| {
+            let syndrome = block_poly.evaluate(&alpha_power);
+            alpha_power = &alpha_power * &GF256::alpha_poly();
+            syndrome
+        })
+        .collect()
+}
+
+// Whole decodes, and the error locator on its own, as the rest of decoding is the same for both
+fn bench_locator<L: ErrorLocator<GF256>>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group(format!("decoding/{}", name));
+    for (codeword_count, ec_count) in BLOCKS {
+        let rs = ReedSolomonEncoder::<GF256, L>::new();
+        let block = damaged_block(codeword_count, ec_count);
+        group.bench_with_input(BenchmarkId::from_parameter(format!("{}-{}", codeword_count, ec_count)), &block, |b, block| {
+            b.iter(|| rs.decode(block.clone(), ec_count).unwrap())
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group(format!("error_locator/{}", name));
+    for (codeword_count, ec_count) in BLOCKS {
+        let syndromes = syndromes(&damaged_block(codeword_count, ec_count), ec_count);
+        group.bench_with_input(BenchmarkId::from_parameter(format!("{}-{}", codeword_count, ec_count)), &syndromes, |b, syndromes| {
+            b.iter(|| <L as ErrorLocator<GF256>>::error_locator(syndromes, 0).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn decoding(c: &mut Criterion) {
+    bench_locator::<Euclidean>(c, "euclidean");
+    bench_locator::<BerlekampMassey>(c, "berlekamp_massey");
+}
+
+criterion_group!(benches, decoding);
+criterion_main!(benches);
@@ -1,28 +1,51 @@
-use galois_field::{PolyWithinGF, IsGaloisField};
-pub use galois_field::GaloisField;
+pub use galois_field::{GaloisField, IsGaloisField, PolyWithinGF};
 pub use polynomial_arithmetic::{Polynomial, Zero, One, int_mod::IntMod};
-use std::{ops::{Add, Sub, Mul, Div}, marker::PhantomData};
+use std::{ops::{Add, Sub, Mul, Div}, marker::PhantomData, error::Error, fmt};
 use num::traits::Inv;
 
-pub struct ReedSolomonEncoder<GF: IsGaloisField>
+// Decoding finds the errors with the ErrorLocator L, Berlekamp-Massey unless another is chosen
+pub struct ReedSolomonEncoder<GF: IsGaloisField, L = BerlekampMassey>
 where
 for<'a> &'a GF::CoeffType: Add<Output = GF::CoeffType>
 + Sub<Output = GF::CoeffType>
 + Mul<Output = GF::CoeffType>
 + Div<Output = GF::CoeffType>,
 {
-    _gf: PhantomData<GF>
+    // alpha^power for each power from 0 to order - 2, and the reverse, the power of each non-zero
+    // element indexed by its number, so that decoding never walks the field to find them
+    exp: Vec<PolyWithinGF<GF>>,
+    log: Vec<usize>,
+    _locator: PhantomData<L>
 }
 
-impl<GF: IsGaloisField> ReedSolomonEncoder<GF>
+impl<GF: IsGaloisField, L: ErrorLocator<GF>> Default for ReedSolomonEncoder<GF, L>
 where
 for<'a> &'a GF::CoeffType: Add<Output = GF::CoeffType>
 + Sub<Output = GF::CoeffType>
 + Mul<Output = GF::CoeffType>
 + Div<Output = GF::CoeffType>,
+u32: From<Polynomial<GF::CoeffType>>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<GF: IsGaloisField, L: ErrorLocator<GF>> ReedSolomonEncoder<GF, L>
+where
+for<'a> &'a GF::CoeffType: Add<Output = GF::CoeffType>
++ Sub<Output = GF::CoeffType>
++ Mul<Output = GF::CoeffType>
++ Div<Output = GF::CoeffType>,
+u32: From<Polynomial<GF::CoeffType>>,
 {
     pub fn new() -> Self {
-        Self { _gf: PhantomData }
+        let exp = GF::all_elements().collect::<Vec<PolyWithinGF<GF>>>();
+        let mut log = vec![0; GF::order()];
+        for (power, element) in exp.iter().enumerate() {
+            log[u32::from(element.poly.clone()) as usize] = power;
+        }
+        Self { exp, log, _locator: PhantomData }
     }
     // Works over Polynomial<GaloisField::Element>
     // That is, Polynomial<Polynomial<IntMod<n>>>
@@ -31,6 +54,7 @@ for<'a> &'a GF::CoeffType: Add<Output = GF::CoeffType>
     pub fn encode(&self, mut message: Vec<Polynomial<GF::CoeffType>>, ec_count: usize) -> Vec<Polynomial<GF::CoeffType>> {
         // Pad the message coefficients to make space for the ec codewords
         message.append(&mut vec![Polynomial::<GF::CoeffType>::zero(); ec_count]);
+        let codeword_count = message.len();
 
         // Generate the message polynomial (over polynomials)
         let message_poly = Polynomial::from(
@@ -55,11 +79,45 @@ for<'a> &'a GF::CoeffType: Add<Output = GF::CoeffType>
 
         let remainder = &message_poly % &generator;
 
-        (&message_poly - &remainder).coefficients.into_iter().rev().map(|c| c.poly).collect()
+        // Leading zero codewords don't appear in the polynomial, so put them back
+        let encoded = (&message_poly - &remainder).coefficients;
+        let leading_zeros = codeword_count.saturating_sub(encoded.len());
+        std::iter::repeat_n(Polynomial::<GF::CoeffType>::zero(), leading_zeros)
+            .chain(encoded.into_iter().rev().map(|c| c.poly))
+            .collect()
     }
 
-    #[allow(dead_code)]
-    fn decode(&self, rcvd: Vec<Polynomial<GF::CoeffType>>, ec_count: usize) -> Vec<Polynomial<GF::CoeffType>> {
+    // Corrects up to ec_count / 2 errors in a received block, highest-coefficient first as for encode,
+    // and returns the message codewords along with the errors that were found
+    pub fn decode(&self, rcvd: Vec<Polynomial<GF::CoeffType>>, ec_count: usize) -> Result<Corrected<GF::CoeffType>, RsError> {
+        self.decode_with_erasures(rcvd, ec_count, &[])
+    }
+
+    // As decode, for a block in which some codewords are known to be unreliable, such as those under a
+    // logo or a tear. Erasures are positions in the received block, counting from its first codeword.
+    // Each costs one error correction codeword, rather than two for an error in an unknown place, so
+    // e errors and f erasures can be corrected while 2e + f <= ec_count.
+    pub fn decode_with_erasures(
+        &self,
+        rcvd: Vec<Polynomial<GF::CoeffType>>,
+        ec_count: usize,
+        erasures: &[usize],
+    ) -> Result<Corrected<GF::CoeffType>, RsError> {
+        if rcvd.len() < ec_count {
+            return Err(RsError::BlockTooShort);
+        }
+        let length = rcvd.len();
+        if erasures.iter().any(|&position| position >= length) {
+            return Err(RsError::ErasureOutOfRange);
+        }
+        let mut erasure_powers = erasures.iter().map(|&position| length - 1 - position).collect::<Vec<usize>>();
+        erasure_powers.sort_unstable();
+        erasure_powers.dedup();
+        if erasure_powers.len() > ec_count {
+            return Err(RsError::TooManyErasures);
+        }
+
+        let data_cw_count = length - ec_count;
         let mut rcvd_poly = Polynomial::from(
             rcvd
             .clone()
@@ -69,80 +127,273 @@ for<'a> &'a GF::CoeffType: Add<Output = GF::CoeffType>
             .collect::<Vec<PolyWithinGF<GF>>>()
         );
 
-        // Euclidean algorithm decoder:
-        // https://en.wikipedia.org/wiki/Reed%E2%80%93Solomon_error_correction#Euclidean_decoder
         let gf_zero = GF::make_polynomial(Polynomial::<GF::CoeffType>::zero());
         let gf_one = GF::make_polynomial(Polynomial::<GF::CoeffType>::one());
-        let mut r_prev_coeffs = vec![gf_zero.clone(); ec_count];
-        r_prev_coeffs.push(gf_one.clone());
-        let mut r_prev = Polynomial::from(r_prev_coeffs);
+        let s_coeffs = self.syndromes(&rcvd_poly, ec_count);
+        if s_coeffs.iter().all(|c| c.is_zero()) {
+            // No transmission errors
+            return Ok(Corrected {
+                data: rcvd.into_iter().take(data_cw_count).collect(),
+                error_positions: vec![],
+                error_magnitudes: vec![],
+            });
+        }
+        let s = Polynomial::from(s_coeffs);
 
-        let mut s_coeffs = vec![];
-        let mut alpha_pow = gf_one.clone();
-        for _ in 1..=ec_count {
-            s_coeffs.push(rcvd_poly.evaluate(&alpha_pow));
-            alpha_pow = alpha_pow * GF::alpha_poly();
+        // The erasure locator, Product_i(1 - X_i x) for each erased codeword's x^power X_i. The error
+        // locator is then found from the syndromes with the erasures taken out, Gamma * S mod x^ec_count.
+        let mut gamma = Polynomial::<PolyWithinGF<GF>>::one();
+        for &power in &erasure_powers {
+            gamma = &gamma * &Polynomial::from(vec![gf_one.clone(), &gf_zero - &self.alpha_to(power)]);
+        }
+        let mut modified_syndromes: Polynomial<PolyWithinGF<GF>> = &s * &gamma;
+        modified_syndromes.coefficients.resize(ec_count, gf_zero.clone());
+        let lambda = <L as ErrorLocator<GF>>::error_locator(&modified_syndromes.coefficients, erasure_powers.len())
+            .ok_or(RsError::Uncorrectable)?;
+        let error_powers = self.chien_search(&lambda, length);
+
+        // Lambda has one root per error, all within the block and apart from the erasures, unless there
+        // were more errors than it could account for
+        let lambda_degree = lambda.coefficients.len().saturating_sub(1);
+        if error_powers.len() != lambda_degree || error_powers.iter().any(|power| erasure_powers.contains(power))
+        {
+            return Err(RsError::Uncorrectable);
         }
 
-        if s_coeffs.iter().all(|c| c.is_zero()) {
-            // No transmission errors
-            let data_cw_count = rcvd.len() - ec_count;
-            return rcvd.into_iter().take(data_cw_count).collect();
+        // Calculate the error values using the Forney algorithm, over the errors and erasures together
+        let mut psi: Polynomial<PolyWithinGF<GF>> = &lambda * &gamma;
+        psi.reduce();
+        let mut omega: Polynomial<PolyWithinGF<GF>> = &s * &psi;
+        omega.coefficients.truncate(ec_count);
+        let psi_prime = Polynomial::<PolyWithinGF<GF>> {
+            coefficients: psi.coefficients.iter().skip(1).enumerate().map(|(pow, val)| {
+                let prod = val.poly.scalar_mul(pow as u32 + 1);
+                GF::make_polynomial(prod)
+            }).collect::<Vec<PolyWithinGF<GF>>>()
+        };
+        let (omega_logs, psi_prime_logs) = (self.logs(&omega), self.logs(&psi_prime));
+        let period = GF::order() - 1;
+        let mut errors = vec![];
+        for power in error_powers.into_iter().chain(erasure_powers) {
+            // Both are evaluated at the root alpha^-power
+            let root_power = period - power % period;
+            let omega_at = self.evaluate_at_power(&omega_logs, root_power);
+            let psi_prime_at = self.evaluate_at_power(&psi_prime_logs, root_power);
+            if psi_prime_at.is_zero() {
+                return Err(RsError::Uncorrectable);
+            }
+            // An erased codeword may have been read correctly after all
+            if omega_at.is_zero() {
+                continue;
+            }
+            // -alpha^power * Omega / Psi', multiplied as powers of alpha. The sign only matters outside
+            // characteristic 2.
+            let error = &gf_zero - &self.alpha_to(power + self.log_of(&omega_at) + period - self.log_of(&psi_prime_at));
+            let orig = rcvd_poly.coefficients[power].clone();
+            rcvd_poly.coefficients[power] = &orig - &error;
+            errors.push((length - 1 - power, error.poly));
         }
 
-        let mut r_now = Polynomial::from(s_coeffs);
-        let s = r_now.clone();
+        // A block with too many errors can still look correctable, so check the result is a codeword
+        if self.syndromes(&rcvd_poly, ec_count).iter().any(|c| !c.is_zero()) {
+            return Err(RsError::Uncorrectable);
+        }
+
+        errors.sort_by_key(|(position, _)| *position);
+        let (error_positions, error_magnitudes) = errors.into_iter().unzip();
+        Ok(Corrected {
+            data: rcvd_poly.coefficients.iter().skip(ec_count).rev().map(|c| c.poly.clone()).collect(),
+            error_positions,
+            error_magnitudes,
+        })
+    }
+
+    // Finds the roots of Lambda among the inverses of the block's x^power, alpha^-power for each power
+    // in 0..length, returning the power of each
+    fn chien_search(&self, lambda: &Polynomial<PolyWithinGF<GF>>, length: usize) -> Vec<usize> {
+        let period = GF::order() - 1;
+        let lambda_logs = self.logs(lambda);
+        (0..length).filter(|power| self.evaluate_at_power(&lambda_logs, period - power % period).is_zero()).collect()
+    }
+
+    // The power of alpha giving each non-zero coefficient of the polynomial, with the coefficient's index
+    fn logs(&self, poly: &Polynomial<PolyWithinGF<GF>>) -> Vec<(usize, usize)> {
+        poly.coefficients
+            .iter()
+            .enumerate()
+            .filter(|(_, coefficient)| !coefficient.is_zero())
+            .map(|(i, coefficient)| (i, self.log_of(coefficient)))
+            .collect()
+    }
+
+    // A polynomial, given by the logs of its coefficients, evaluated at alpha^power. Each term
+    // c_i * alpha^(power * i) is looked up from its power, so evaluating costs one addition per term.
+    fn evaluate_at_power(&self, logs: &[(usize, usize)], power: usize) -> PolyWithinGF<GF> {
+        let period = GF::order() - 1;
+        logs.iter().fold(GF::make_polynomial(Polynomial::<GF::CoeffType>::zero()), |sum, &(i, log)| {
+            &sum + &self.alpha_to(log + power * i % period)
+        })
+    }
+
+    // The primitive element raised to the power
+    fn alpha_to(&self, power: usize) -> PolyWithinGF<GF> {
+        self.exp[power % (GF::order() - 1)].clone()
+    }
+
+    // The power of the primitive element giving a non-zero element
+    fn log_of(&self, element: &PolyWithinGF<GF>) -> usize {
+        self.log[u32::from(element.poly.clone()) as usize]
+    }
+
+    // The received polynomial evaluated at alpha^0 to alpha^(ec_count - 1), all zero for a codeword
+    fn syndromes(&self, rcvd_poly: &Polynomial<PolyWithinGF<GF>>, ec_count: usize) -> Vec<PolyWithinGF<GF>> {
+        let rcvd_logs = self.logs(rcvd_poly);
+        (0..ec_count).map(|power| self.evaluate_at_power(&rcvd_logs, power)).collect()
+    }
+}
+
+// Finds the error locator, Lambda(x) = Product_k(1 - X_k x) over the errors' x^power X_k, lowest
+// coefficient first. The syndromes come with any erasures already taken out, and the first
+// erasure_count of them only tell of the erasures. Gives None if no locator fits, as happens with more
+// errors than can be corrected.
+pub trait ErrorLocator<GF: IsGaloisField>
+where
+for<'a> &'a GF::CoeffType: Add<Output = GF::CoeffType>
++ Sub<Output = GF::CoeffType>
++ Mul<Output = GF::CoeffType>
++ Div<Output = GF::CoeffType>,
+{
+    fn error_locator(syndromes: &[PolyWithinGF<GF>], erasure_count: usize) -> Option<Polynomial<PolyWithinGF<GF>>>;
+}
+
+// Euclidean algorithm decoder:
+// https://en.wikipedia.org/wiki/Reed%E2%80%93Solomon_error_correction#Euclidean_decoder
+pub struct Euclidean;
+
+impl<GF: IsGaloisField> ErrorLocator<GF> for Euclidean
+where
+for<'a> &'a GF::CoeffType: Add<Output = GF::CoeffType>
++ Sub<Output = GF::CoeffType>
++ Mul<Output = GF::CoeffType>
++ Div<Output = GF::CoeffType>,
+{
+    fn error_locator(syndromes: &[PolyWithinGF<GF>], erasure_count: usize) -> Option<Polynomial<PolyWithinGF<GF>>> {
+        let ec_count = syndromes.len();
+        let gf_zero = GF::make_polynomial(Polynomial::<GF::CoeffType>::zero());
+        let gf_one = GF::make_polynomial(Polynomial::<GF::CoeffType>::one());
+        let mut r_prev_coeffs = vec![gf_zero.clone(); ec_count];
+        r_prev_coeffs.push(gf_one);
+        let mut r_prev = Polynomial::from(r_prev_coeffs);
+
+        let mut r_now = Polynomial::from(syndromes.to_vec());
+        // Division needs a non-zero leading coefficient, and the highest syndromes may be zero
+        r_now.reduce();
         let mut a_prev = Polynomial::<PolyWithinGF<GF>>::zero();
         let mut a_now = Polynomial::<PolyWithinGF<GF>>::one();
 
-        while r_now.coefficients.len() > ec_count / 2 {
+        // Each division lowers the degree of the remainder, so this takes at most ec_count steps
+        for _ in 0..ec_count {
+            if 2 * r_now.coefficients.len().saturating_sub(1) < ec_count + erasure_count || r_now.is_zero() {
+                break;
+            }
             let (quotient, r_next) = r_prev.clone().full_divide(&r_now);
             (r_now, r_prev) = (r_next, r_now);
             (a_now, a_prev) = (a_prev - &quotient * &a_now, a_now);
         }
-        let a_lead_inverse = a_now.coefficients.first().unwrap().clone().inv();
-        let lambda: Polynomial<PolyWithinGF<GF>> = &a_now * &a_lead_inverse;
+        let a_lead = a_now.coefficients.first().cloned().unwrap_or(gf_zero);
+        if a_lead.is_zero() {
+            return None;
+        }
+        let mut lambda: Polynomial<PolyWithinGF<GF>> = &a_now * &a_lead.inv();
+        lambda.reduce();
+        Some(lambda)
+    }
+}
 
-        let mut omega: Polynomial<PolyWithinGF<GF>> = &s * &lambda;
-        omega.coefficients.truncate(ec_count);
+// Berlekamp-Massey algorithm, building the shortest linear feedback shift register which generates the
+// syndromes: https://en.wikipedia.org/wiki/Berlekamp%E2%80%93Massey_algorithm
+pub struct BerlekampMassey;
 
-        // Find which powers of the GF's Primitive element give 0s for Lambda (-> error locations)
-        let error_roots_and_powers = GF::all_elements().enumerate().filter_map(|(ix, val)| {
-            if lambda.evaluate(&val).is_zero() {
-                let pow = if ix == 0 {
-                    0
-                } else {
-                    GF::order() - 1 - ix
-                };
-                Some((val, pow))
+impl<GF: IsGaloisField> ErrorLocator<GF> for BerlekampMassey
+where
+for<'a> &'a GF::CoeffType: Add<Output = GF::CoeffType>
++ Sub<Output = GF::CoeffType>
++ Mul<Output = GF::CoeffType>
++ Div<Output = GF::CoeffType>,
+{
+    fn error_locator(syndromes: &[PolyWithinGF<GF>], erasure_count: usize) -> Option<Polynomial<PolyWithinGF<GF>>> {
+        // Past the erasures, the syndromes follow the recurrence given by the errors alone
+        let syndromes = syndromes.get(erasure_count..)?;
+        let gf_zero = GF::make_polynomial(Polynomial::<GF::CoeffType>::zero());
+        let gf_one = GF::make_polynomial(Polynomial::<GF::CoeffType>::one());
+
+        // The current locator and its length, and the locator before the last length change with the
+        // discrepancy it had then, and how many steps ago that was
+        let (mut locator, mut length) = (vec![gf_one.clone()], 0);
+        let (mut previous, mut previous_discrepancy, mut shift) = (vec![gf_one.clone()], gf_one, 1);
+        for n in 0..syndromes.len() {
+            let discrepancy = locator.iter().enumerate().skip(1).take(n)
+                .fold(syndromes[n].clone(), |sum, (i, coefficient)| &sum + &(coefficient * &syndromes[n - i]));
+            if discrepancy.is_zero() {
+                shift += 1;
+                continue;
+            }
+
+            // Take discrepancy / previous_discrepancy * x^shift * previous away, which makes the
+            // discrepancy at this step zero
+            let scale = &discrepancy / &previous_discrepancy;
+            let mut next = locator.clone();
+            next.resize(next.len().max(previous.len() + shift), gf_zero.clone());
+            for (i, coefficient) in previous.iter().enumerate() {
+                next[i + shift] = &next[i + shift] - &(&scale * coefficient);
+            }
+            if 2 * length <= n {
+                length = n + 1 - length;
+                (previous, previous_discrepancy, shift) = (locator, discrepancy, 1);
             } else {
-                None
+                shift += 1;
             }
-        }).collect::<Vec<(PolyWithinGF<GF>, usize)>>();
+            locator = next;
+        }
 
-        // Calculate the error values using the Forney algorithm
-        let lambda_prime = Polynomial::<PolyWithinGF<GF>> {
-            coefficients: lambda.coefficients.iter().skip(1).enumerate().map(|(pow, val)| {
-                let prod = val.poly.scalar_mul(pow as u32 + 1);
-                GF::make_polynomial(prod)
-            }).collect::<Vec<PolyWithinGF<GF>>>()
-        };
-        let error_vals_and_powers = error_roots_and_powers.iter().map(|(root, power)| {
-            let omega_at = omega.evaluate(root);
-            let lambda_prime_at = lambda_prime.evaluate(root);
-            let alpha_power = GF::all_elements().nth(*power).unwrap();
-            let error: PolyWithinGF<GF> = alpha_power * (omega_at * lambda_prime_at.inv());
-            (error, *power)
-        }).collect::<Vec<(PolyWithinGF<GF>, usize)>>();
-
-        error_vals_and_powers.iter().for_each(|(error, power)|{
-            let orig = rcvd_poly.coefficients[*power].clone();
-            rcvd_poly.coefficients[*power] = &orig - error;
-        });
-
-        rcvd_poly.coefficients.iter().skip(ec_count).rev().map(|c| c.poly.clone()).collect()
+        // A locator of lower degree than its register length can't have a root for every error
+        let mut lambda = Polynomial::from(locator);
+        lambda.reduce();
+        (lambda.coefficients.len() == length + 1 && 2 * length <= syndromes.len()).then_some(lambda)
+    }
+}
+
+// A block after decoding. Errors are listed by their position in the received block, counting from its
+// first (highest-coefficient) codeword, with the value which had been added to the codeword there.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Corrected<T> {
+    pub data: Vec<Polynomial<T>>,
+    pub error_positions: Vec<usize>,
+    pub error_magnitudes: Vec<Polynomial<T>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RsError {
+    // The block has fewer codewords than it should have error correction codewords
+    BlockTooShort,
+    // The block has more errors than its error correction codewords can correct
+    Uncorrectable,
+    // An erasure is past the end of the block
+    ErasureOutOfRange,
+    // There are more erasures than error correction codewords
+    TooManyErasures,
+}
+impl fmt::Display for RsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RsError::BlockTooShort => write!(f, "The block is shorter than its error correction codewords"),
+            RsError::Uncorrectable => write!(f, "The block has too many errors to correct"),
+            RsError::ErasureOutOfRange => write!(f, "An erasure is outside the block"),
+            RsError::TooManyErasures => write!(f, "The block has more erasures than error correction codewords"),
+        }
     }
 }
+impl Error for RsError {}
 
 #[cfg(test)]
 mod tests {
@@ -150,10 +401,11 @@ mod tests {
     use polynomial_arithmetic::{Polynomial, int_mod::IntMod};
     use super::*;
 
-    use rand::Rng;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
 
     type GF256 = GaloisField<2, 8, 285, 2>;
     type GF16 = GaloisField<2, 4, 19, 2>;
+    type GF9 = GaloisField<3, 2, 17, 3>;
 
     #[test]
     fn test_encoding_as_for_qr() {
@@ -190,6 +442,33 @@ mod tests {
         assert_eq!(encoded, expected.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>());
     }
 
+    #[test]
+    fn test_encoding_keeps_leading_zero_codewords() {
+        type Element = Polynomial<IntMod<2>>;
+
+        let rs = ReedSolomonEncoder::<GF16>::new();
+
+        let message = [0u32, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+        let message_as_poly = message.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>();
+
+        let encoded = rs.encode(message_as_poly, 4);
+
+        // Leading zeros don't change the remainder
+        let expected = [0u32, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 3, 3, 12, 12];
+        assert_eq!(encoded, expected.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>());
+    }
+
+    #[test]
+    fn test_exp_and_log_tables_are_inverses() {
+        let rs = ReedSolomonEncoder::<GF16>::new();
+        // alpha^4 = alpha + 1 under x^4 + x + 1
+        assert_eq!(u32::from(rs.alpha_to(4).poly), 0b0011);
+        assert_eq!(rs.alpha_to(15), rs.alpha_to(0));
+        for power in 0..15 {
+            assert_eq!(rs.log_of(&rs.alpha_to(power)), power);
+        }
+    }
+
     #[test]
     fn test_decoding_with_errors_in_gf16() {
         type Element = Polynomial<IntMod<2>>;
@@ -201,10 +480,12 @@ mod tests {
 
         let encoded_as_poly = encoded.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>();
 
-        let decoded = rs.decode(encoded_as_poly, 4);
+        let decoded = rs.decode(encoded_as_poly, 4).unwrap();
 
         let message = [1u32, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
-        assert_eq!(decoded, message.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>());
+        assert_eq!(decoded.data, message.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>());
+        assert_eq!(decoded.error_positions, vec![5, 12]);
+        assert_eq!(decoded.error_magnitudes, vec![Element::from(6 ^ 11), Element::from(3 ^ 1)]);
     }
 
     #[test]
@@ -220,10 +501,11 @@ mod tests {
         196, 35, 39, 119, 235, 215, 231, 226, 93, 23];
         let encoded_as_poly = encoded.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>();
 
-        let decoded = rs.decode(encoded_as_poly, 10);
+        let decoded = rs.decode(encoded_as_poly, 10).unwrap();
 
         let expected = [32u32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17];
-        assert_eq!(decoded, expected.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>());
+        assert_eq!(decoded.data, expected.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>());
+        assert!(decoded.error_positions.is_empty() && decoded.error_magnitudes.is_empty());
     }
 
     #[test]
@@ -251,11 +533,219 @@ mod tests {
 
         let encoded_as_poly = encoded.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>();
 
-        let decoded = rs.decode(encoded_as_poly, 10);
+        let decoded = rs.decode(encoded_as_poly, 10).unwrap();
+
+        let message = [32u32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17];
+        let message_as_poly = message.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>();
+
+        assert_eq!(decoded.data, message_as_poly);
+        assert_eq!(decoded.error_positions, vec![0, 1, msg_len - 7]);
+        assert_eq!(decoded.error_magnitudes, [32 ^ 33, 91 ^ 38, 119 ^ 199].map(Element::from).to_vec());
+    }
+
+    #[test]
+    fn test_decoding_when_highest_syndrome_is_zero() {
+        type Element = Polynomial<IntMod<2>>;
+        let rs = ReedSolomonEncoder::<GF256>::new();
+        let mut encoded = [32u32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17,
+        196, 35, 39, 119, 235, 215, 231, 226, 93, 23];
+
+        // Syndromes are taken at alpha^0 to alpha^9. An error of 1 in the x^0 codeword and alpha^-9 in the
+        // x^1 codeword cancel out in the last of them.
+        let alpha_246 = (0..246).fold(1u32, |power, _| if power & 0x80 != 0 { (power << 1) ^ 285 } else { power << 1 });
+        encoded[25] ^= 1;
+        encoded[24] ^= alpha_246;
+
+        let encoded_as_poly = encoded.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>();
+        let decoded = rs.decode(encoded_as_poly, 10).unwrap();
 
         let message = [32u32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17];
         let message_as_poly = message.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>();
+        assert_eq!(decoded.data, message_as_poly);
+    }
+
+    #[test]
+    fn test_decoding_reports_too_many_errors() {
+        type Element = Polynomial<IntMod<2>>;
+        let rs = ReedSolomonEncoder::<GF256>::new();
+        let encoded = [32u32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17,
+        196, 35, 39, 119, 235, 215, 231, 226, 93, 23];
+
+        // 10 EC codewords can correct 5 errors, but not 6 or more
+        for error_count in 6..=10 {
+            let mut corrupted = encoded;
+            for (ix, codeword) in corrupted.iter_mut().step_by(4).take(error_count).enumerate() {
+                *codeword ^= 17 * (ix as u32 + 1);
+            }
+            let corrupted_as_poly = corrupted.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>();
+            assert_eq!(rs.decode(corrupted_as_poly, 10), Err(RsError::Uncorrectable), "{error_count} errors");
+        }
+    }
+
+    #[test]
+    fn test_decoding_never_returns_a_non_codeword() {
+        type Element = Polynomial<IntMod<2>>;
+        let rs = ReedSolomonEncoder::<GF16>::new();
+        let encoded = [1u32, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 3, 3, 12, 12];
+
+        // Beyond 2 errors, 4 EC codewords may still land near enough another codeword to decode to it,
+        // but whatever decodes must re-encode to the corrected block
+        let mut rng = StdRng::seed_from_u64(15);
+        for _ in 0..50 {
+            let mut corrupted = encoded;
+            for _ in 0..3 {
+                corrupted[rng.gen_range(0..15)] ^= rng.gen_range(1..16);
+            }
+            let corrupted_as_poly = corrupted.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>();
+            if let Ok(decoded) = rs.decode(corrupted_as_poly.clone(), 4) {
+                let mut reencoded = rs.encode(decoded.data, 4);
+                for (position, magnitude) in decoded.error_positions.iter().zip(&decoded.error_magnitudes) {
+                    reencoded[*position] = Element::from(u32::from(reencoded[*position].clone()) ^ u32::from(magnitude.clone()));
+                }
+                assert_eq!(reencoded, corrupted_as_poly);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decoding_reports_blocks_shorter_than_their_ec_codewords() {
+        type Element = Polynomial<IntMod<2>>;
+        let rs = ReedSolomonEncoder::<GF16>::new();
+        let block = [3u32, 3, 12].map(Element::from).to_vec();
+        assert_eq!(rs.decode(block, 4), Err(RsError::BlockTooShort));
+    }
+
+    #[test]
+    fn test_decoding_erasures_up_to_the_ec_count() {
+        type Element = Polynomial<IntMod<2>>;
+        let rs = ReedSolomonEncoder::<GF256>::new();
+        let encoded = [32u32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17,
+        196, 35, 39, 119, 235, 215, 231, 226, 93, 23];
+        let message = encoded[..16].iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>();
+
+        // 10 EC codewords can only correct 5 errors, but can fill in 10 erasures. One of the erased
+        // codewords was read correctly, so isn't reported.
+        let erasures = [0, 3, 4, 5, 11, 12, 17, 20, 24, 25];
+        let mut corrupted = encoded;
+        for &position in &erasures[1..] {
+            corrupted[position] = 0;
+        }
+        let corrupted_as_poly = corrupted.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>();
+        assert_eq!(rs.decode(corrupted_as_poly.clone(), 10), Err(RsError::Uncorrectable));
+
+        let decoded = rs.decode_with_erasures(corrupted_as_poly, 10, &erasures).unwrap();
+        assert_eq!(decoded.data, message);
+        assert_eq!(decoded.error_positions, erasures[1..].to_vec());
+        assert_eq!(decoded.error_magnitudes, erasures[1..].iter().map(|&position| Element::from(encoded[position])).collect::<Vec<Element>>());
+    }
+
+    #[test]
+    fn test_decoding_errors_and_erasures() {
+        type Element = Polynomial<IntMod<2>>;
+        let rs = ReedSolomonEncoder::<GF256>::new();
+        let encoded = [32u32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17,
+        196, 35, 39, 119, 235, 215, 231, 226, 93, 23];
+        let message = encoded[..16].iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>();
+
+        // 2 errors and 6 erasures use all 10 EC codewords
+        let erasures = [2, 8, 9, 10, 16, 21];
+        let mut corrupted = encoded;
+        for &position in &erasures {
+            corrupted[position] ^= 0xFF;
+        }
+        corrupted[1] ^= 1;
+        corrupted[23] ^= 100;
+        let corrupted_as_poly = corrupted.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>();
+
+        let decoded = rs.decode_with_erasures(corrupted_as_poly.clone(), 10, &erasures).unwrap();
+        assert_eq!(decoded.data, message);
+        assert_eq!(decoded.error_positions, vec![1, 2, 8, 9, 10, 16, 21, 23]);
+        assert_eq!(decoded.error_magnitudes[0], Element::from(1));
+        assert_eq!(decoded.error_magnitudes[7], Element::from(100));
+
+        // A third error is one too many
+        let mut too_many = corrupted_as_poly;
+        too_many[12] = Element::from(u32::from(too_many[12].clone()) ^ 7);
+        assert_eq!(rs.decode_with_erasures(too_many, 10, &erasures), Err(RsError::Uncorrectable));
+    }
+
+    #[test]
+    fn test_decoding_reports_invalid_erasures() {
+        type Element = Polynomial<IntMod<2>>;
+        let rs = ReedSolomonEncoder::<GF16>::new();
+        let encoded = [1u32, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 3, 3, 12, 12].map(Element::from).to_vec();
+
+        assert_eq!(rs.decode_with_erasures(encoded.clone(), 4, &[3, 15]), Err(RsError::ErasureOutOfRange));
+        assert_eq!(rs.decode_with_erasures(encoded.clone(), 4, &[0, 1, 2, 3, 4]), Err(RsError::TooManyErasures));
+        // Repeated erasures count once
+        let decoded = rs.decode_with_erasures(encoded.clone(), 4, &[0, 0, 1, 2, 3]).unwrap();
+        assert_eq!(decoded.data, encoded[..11].to_vec());
+    }
+
+    #[test]
+    fn test_euclidean_and_berlekamp_massey_locators_agree() {
+        type Element = Polynomial<IntMod<2>>;
+        let euclidean = ReedSolomonEncoder::<GF256, Euclidean>::new();
+        let berlekamp_massey = ReedSolomonEncoder::<GF256, BerlekampMassey>::new();
+        let encoded = [32u32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17,
+        196, 35, 39, 119, 235, 215, 231, 226, 93, 23];
 
-        assert_eq!(decoded, message_as_poly);
+        // From no errors up to the EC codewords' capacity, then one error too many
+        let mut rng = StdRng::seed_from_u64(26);
+        for (error_count, erasure_count) in [(0, 0), (1, 0), (5, 0), (0, 10), (3, 3), (4, 3), (6, 0)] {
+            let mut positions = (0..encoded.len()).collect::<Vec<usize>>();
+            for i in 0..positions.len() {
+                positions.swap(i, rng.gen_range(i..encoded.len()));
+            }
+            let mut corrupted = encoded;
+            for &position in &positions[..error_count + erasure_count] {
+                corrupted[position] ^= rng.gen_range(1..=255);
+            }
+            let corrupted_as_poly = corrupted.iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>();
+            let erasures = &positions[error_count..error_count + erasure_count];
+
+            let decoded = euclidean.decode_with_erasures(corrupted_as_poly.clone(), 10, erasures);
+            assert_eq!(decoded, berlekamp_massey.decode_with_erasures(corrupted_as_poly, 10, erasures));
+            if 2 * error_count + erasure_count <= 10 {
+                assert_eq!(decoded.unwrap().data, encoded[..16].iter().map(|&cw| Element::from(cw)).collect::<Vec<Element>>());
+            }
+        }
+    }
+
+    #[test]
+    fn test_decoding_outside_characteristic_two() {
+        // In GF(9), -1 = 2, so the sign of each error magnitude matters
+        type Element = Polynomial<IntMod<3>>;
+        let euclidean = ReedSolomonEncoder::<GF9, Euclidean>::new();
+        let berlekamp_massey = ReedSolomonEncoder::<GF9, BerlekampMassey>::new();
+        let message = [1u32, 2, 3, 4].map(Element::from).to_vec();
+        let encoded = berlekamp_massey.encode(message.clone(), 4);
+        assert_eq!(encoded, euclidean.encode(message.clone(), 4));
+        assert_eq!(berlekamp_massey.decode(encoded.clone(), 4).unwrap().data, message);
+        assert_eq!(euclidean.decode(encoded.clone(), 4).unwrap().data, message);
+
+        // 4 EC codewords correct any 2 errors, of any magnitude
+        for position in 0..encoded.len() {
+            for magnitude in (1..9).map(Element::from) {
+                let mut corrupted = encoded.clone();
+                corrupted[position] = &corrupted[position] + &magnitude;
+                corrupted[(position + 3) % 8] = &corrupted[(position + 3) % 8] + &Element::from(1);
+                for decoded in [euclidean.decode(corrupted.clone(), 4), berlekamp_massey.decode(corrupted.clone(), 4)] {
+                    let decoded = decoded.unwrap();
+                    assert_eq!(decoded.data, message);
+                    let magnitude_at = decoded.error_positions.iter().position(|&p| p == position).unwrap();
+                    assert_eq!(decoded.error_magnitudes[magnitude_at], magnitude);
+                }
+            }
+        }
+
+        // or 4 erasures
+        let erasures = [0, 2, 5, 7];
+        let mut corrupted = encoded.clone();
+        for &position in &erasures {
+            corrupted[position] = Element::zero();
+        }
+        assert_eq!(euclidean.decode_with_erasures(corrupted.clone(), 4, &erasures).unwrap().data, message);
+        assert_eq!(berlekamp_massey.decode_with_erasures(corrupted, 4, &erasures).unwrap().data, message);
     }
 }
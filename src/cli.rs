@@ -1,5 +1,5 @@
 pub use clap::{Parser, ValueEnum};
-use qr_generator::{EncodingModes, CorrectionLevels};
+use qr_generator::{EncodingModes, CorrectionLevels, QRSymbolTypes, CharacterSets, OutputFormats, Lengths, Colour, ModuleStyle};
 
 macro_rules! bidir_from {
   ( $owned:ident, $foreign:ident; $( $variant:ident ),+ ) => {
@@ -32,7 +32,7 @@ pub enum CliEncoding {
   AlphaNumeric,
   #[value(alias("b"), help("(abbrev: b) Data will be encoded as bytes. Least compact"))]
   Byte,
-  #[value(skip)] //, alias("k"), help("(abbrev: k) Data will be encoded as Kanji from Shift-JIS"))]
+  #[value(alias("k"), help("(abbrev: k) Data must consist only of double-byte Shift-JIS characters (Kanji, kana, etc.)"))]
   Kanji,
   #[value(alias("d"), help("(abbrev: d) Default. Data encoding will change as necessary to obtain the smallest possible code"))]
   Dynamic
@@ -50,17 +50,198 @@ pub enum CliCorrectionLevels {
 }
 bidir_from!(CliCorrectionLevels, CorrectionLevels; DetectionOnly, L, M, Q, H);
 
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum CliSymbolTypes {
+    #[value(name("qr"), alias("q"), help("(abbrev: q) Default. A standard QR Code, versions 1-40"))]
+    QRCode,
+    #[value(name("micro"), alias("m"), help("(abbrev: m) A Micro QR Code, versions M1-M4. Holds much less data"))]
+    MicroQRCode,
+}
+bidir_from!(CliSymbolTypes, QRSymbolTypes; QRCode, MicroQRCode);
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum CliCharacterSets {
+    #[value(name("iso-8859-1"))]
+    Iso8859_1,
+    #[value(name("iso-8859-2"))]
+    Iso8859_2,
+    #[value(name("iso-8859-3"))]
+    Iso8859_3,
+    #[value(name("iso-8859-4"))]
+    Iso8859_4,
+    #[value(name("iso-8859-5"))]
+    Iso8859_5,
+    #[value(name("iso-8859-6"))]
+    Iso8859_6,
+    #[value(name("iso-8859-7"))]
+    Iso8859_7,
+    #[value(name("iso-8859-8"))]
+    Iso8859_8,
+    #[value(name("iso-8859-9"))]
+    Iso8859_9,
+    #[value(name("iso-8859-10"))]
+    Iso8859_10,
+    #[value(name("iso-8859-11"))]
+    Iso8859_11,
+    #[value(name("iso-8859-13"))]
+    Iso8859_13,
+    #[value(name("iso-8859-14"))]
+    Iso8859_14,
+    #[value(name("iso-8859-15"))]
+    Iso8859_15,
+    #[value(name("iso-8859-16"))]
+    Iso8859_16,
+    #[value(name("shift-jis"))]
+    ShiftJIS,
+    #[value(name("utf-8"))]
+    UTF8,
+}
+bidir_from!(CliCharacterSets, CharacterSets; Iso8859_1, Iso8859_2, Iso8859_3, Iso8859_4, Iso8859_5, Iso8859_6,
+  Iso8859_7, Iso8859_8, Iso8859_9, Iso8859_10, Iso8859_11, Iso8859_13, Iso8859_14, Iso8859_15, Iso8859_16, ShiftJIS, UTF8);
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum CliOutputFormats {
+    #[value(help("A PNG image"))]
+    Png,
+    #[value(alias("jpg"), help("A JPEG image, at the quality given by --quality"))]
+    Jpeg,
+    #[value(help("A GIF image"))]
+    Gif,
+    #[value(help("A BMP image"))]
+    Bmp,
+    #[value(alias("tif"), help("A TIFF image"))]
+    Tiff,
+    #[value(help("A lossless WebP image"))]
+    Webp,
+    #[value(help("An SVG vector image"))]
+    Svg,
+    #[value(help("A PDF page at the module size given by --module-size"))]
+    Pdf,
+    #[value(help("An Encapsulated PostScript file at the module size given by --module-size"))]
+    Eps,
+}
+bidir_from!(CliOutputFormats, OutputFormats; Png, Jpeg, Gif, Bmp, Tiff, Webp, Svg, Pdf, Eps);
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum CliStyles {
+    #[value(help("Default. Plain square modules"))]
+    Squares,
+    #[value(help("Round dots, with circular finder patterns"))]
+    Dots,
+    #[value(help("Squares with rounded corners, with rounded finder patterns"))]
+    Rounded,
+}
+impl From<CliStyles> for ModuleStyle {
+    fn from(style: CliStyles) -> ModuleStyle {
+        match style {
+            CliStyles::Squares => ModuleStyle::default(),
+            CliStyles::Dots => ModuleStyle::dots(),
+            CliStyles::Rounded => ModuleStyle::rounded(),
+        }
+    }
+}
+
 #[derive(Parser)]
 #[command(author, about, long_about = None)]
 pub struct Cli {
-  // qr_type: SomeTypeHere,
+  /// What type of QR code to generate
+  #[arg(long, short='t', value_enum, default_value = "qr")]
+  pub qr_type: CliSymbolTypes,
   /// What encoding to use for the data
   #[arg(long, short, value_enum, default_value = "dynamic")]
   pub encoding: CliEncoding,
-  #[arg(long, short='l', value_enum, default_value = "q")]
-  pub correction_level: CliCorrectionLevels,
+  /// Error correction level. If omitted, Q is used for QR Codes and M for Micro QR Codes
+  #[arg(long, short='l', value_enum)]
+  pub correction_level: Option<CliCorrectionLevels>,
+  #[arg(long, value_enum, help = "Character set to declare with an ECI segment. If omitted, UTF-8 is declared when the data isn't ISO-8859-1")]
+  pub eci: Option<CliCharacterSets>,
+  #[arg(long, conflicts_with = "fnc1_second", help = "Encode GS1 data, given as (AI)data(AI)data... with brackets in data written \\( and \\), using FNC1 in first position")]
+  pub gs1: bool,
+  #[arg(long, value_parser = parse_application_indicator, help = "Use FNC1 in second position with this AIM application indicator (00-99, or a letter)")]
+  pub fnc1_second: Option<u8>,
+  #[arg(long, short='s', help = "Split data too large for one QR Code across up to 16 linked codes")]
+  pub structured_append: bool,
   #[arg(long, short, help = "The 'size' of the QR Code. If omitted, the smallest size that fits will be used")]
   pub version: Option<u32>,
+  #[arg(long, value_parser = clap::value_parser!(u8).range(0..=7), help = "Use this mask pattern (0-7, or 0-3 for Micro QR Codes) rather than the best scoring one")]
+  pub mask: Option<u8>,
+  #[arg(long, help = "Print how every mask pattern scored, marking the one used")]
+  pub mask_report: bool,
+  #[arg(long, short, help = "Where to save the image, or 'terminal' to print it. Defaults to ./qr_code.png; linked codes are numbered qr_code_1.png, ...")]
+  pub output: Option<String>,
+  #[arg(long, short='f', value_enum, help = "Image format. If omitted, it's chosen by the output file's extension, or PNG")]
+  pub format: Option<CliOutputFormats>,
+  #[arg(long, help = "Width of the light border around the code, in modules. Defaults to 4")]
+  pub quiet_zone: Option<u32>,
+  #[arg(long, conflicts_with = "size", value_parser = clap::value_parser!(u32).range(1..), help = "Pixels per module in raster images. Defaults to 10")]
+  pub scale: Option<u32>,
+  #[arg(long, help = "Exact width of raster images in pixels. Spare pixels beyond a whole number per module widen the quiet zone")]
+  pub size: Option<u32>,
+  #[arg(long, help = "Resolution to record in PNG and JPEG images, in dots per inch")]
+  pub dpi: Option<u32>,
+  #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100), help = "JPEG quality, from 1 to 100. Defaults to 90")]
+  pub quality: Option<u8>,
+  #[arg(long, value_parser = parse_colour, help = "Colour of the dark modules, as #rrggbb or #rrggbbaa. Defaults to black")]
+  pub foreground: Option<Colour>,
+  #[arg(long, value_parser = parse_colour, help = "Colour of the light modules, as #rrggbb, #rrggbbaa or 'transparent'. Defaults to white")]
+  pub background: Option<Colour>,
+  #[arg(long, value_enum, help = "Shape of the modules in raster and SVG images")]
+  pub style: Option<CliStyles>,
+  #[arg(long, value_parser = parse_length, help = "Size of each module in PDF and EPS output, in mm or pt (e.g. 0.5mm, 2pt). Defaults to 0.5mm")]
+  pub module_size: Option<Lengths>,
+  #[arg(long, help = "When printing to the terminal, draw the light modules as blocks, for dark terminals")]
+  pub invert: bool,
+  #[arg(long, help = "When printing to the terminal, set black on white with ANSI colour codes")]
+  pub ansi_colours: bool,
+  #[arg(long, help = "Image file to place over the centre of raster and SVG output. Fails if it covers more than error correction can recover")]
+  pub logo: Option<String>,
+  #[arg(long, requires = "logo", value_parser = parse_fraction, help = "Width of the logo as a fraction of the code's width. Defaults to 0.2")]
+  pub logo_width: Option<f64>,
+  #[arg(long, requires = "logo", help = "Light modules to leave clear around the logo. Defaults to 1")]
+  pub logo_margin: Option<u32>,
+  #[arg(long, requires = "logo", help = "Raise the error correction level as far as needed for the logo to fit, rather than failing")]
+  pub raise_correction: bool,
   #[arg()]
   pub data: String
-}
\ No newline at end of file
+}
+fn parse_application_indicator(indicator: &str) -> Result<u8, String> {
+  match indicator.as_bytes() {
+    [letter] if letter.is_ascii_alphabetic() => Ok(letter + 100),
+    [tens, units] if tens.is_ascii_digit() && units.is_ascii_digit() => Ok((tens - b'0') * 10 + units - b'0'),
+    _ => Err("Application indicators are two digits (00-99) or a single letter".to_string()),
+  }
+}
+fn parse_fraction(fraction: &str) -> Result<f64, String> {
+  match fraction.parse::<f64>() {
+    Ok(fraction) if fraction > 0.0 && fraction <= 1.0 => Ok(fraction),
+    _ => Err("Expected a fraction above 0 and at most 1, such as 0.2".to_string()),
+  }
+}
+fn parse_length(length: &str) -> Result<Lengths, String> {
+  let parse_number = |number: &str| match number.trim().parse::<f64>() {
+    Ok(number) if number > 0.0 => Ok(number),
+    _ => Err("Lengths are a positive number followed by mm or pt".to_string()),
+  };
+  if let Some(mm) = length.strip_suffix("mm") {
+    Ok(Lengths::Millimetres(parse_number(mm)?))
+  } else if let Some(points) = length.strip_suffix("pt") {
+    Ok(Lengths::Points(parse_number(points)?))
+  } else {
+    Err("Lengths need a unit: mm or pt".to_string())
+  }
+}
+fn parse_colour(colour: &str) -> Result<Colour, String> {
+  if colour == "transparent" {
+    return Ok(Colour::TRANSPARENT);
+  }
+  let hex = colour.strip_prefix('#').unwrap_or(colour);
+  let components = (0..hex.len())
+    .step_by(2)
+    .map(|index| hex.get(index..index + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+    .collect::<Option<Vec<u8>>>();
+  match components.as_deref() {
+    Some(&[r, g, b]) => Ok(Colour::rgb(r, g, b)),
+    Some(&[r, g, b, a]) => Ok(Colour::rgba(r, g, b, a)),
+    _ => Err("Colours are given as #rrggbb or #rrggbbaa".to_string()),
+  }
+}
@@ -1,5 +1,7 @@
-use qr_generator::{QRGenerator, QRSymbolTypes, EncodingModes, CorrectionLevels};
-use std::process;
+use qr_generator::{gs1, Logo, MaskScores, QRCode, QRGenerator, EncodingModes, CorrectionLevels, QRSymbolTypes, CharacterSets, Fnc1Modes, OutputFormats, ModuleStyle};
+use std::{fs, process};
+
+const TERMINAL_OUTPUT: &str = "terminal";
 
 mod cli;
 use cli::{Cli, Parser};
@@ -7,18 +9,109 @@ use cli::{Cli, Parser};
 fn main() {
     let cli = Cli::parse();
 
+    let fnc1 = if cli.gs1 {
+        Some(Fnc1Modes::First)
+    } else {
+        cli.fnc1_second.map(Fnc1Modes::Second)
+    };
+    let data = if cli.gs1 {
+        gs1::parse_element_string(&cli.data).unwrap_or_else(|err| {
+            println!("Invalid GS1 data: {}", err);
+            process::exit(1);
+        })
+    } else {
+        cli.data
+    };
+
+    let logo = cli.logo.as_ref().map(|path| {
+        let image = fs::read(path).unwrap_or_else(|err| {
+            println!("Couldn't read logo {}: {}", path, err);
+            process::exit(1);
+        });
+        let default = Logo::new(image);
+        Logo {
+            width: cli.logo_width.unwrap_or(default.width),
+            margin: cli.logo_margin.unwrap_or(default.margin),
+            raise_correction: cli.raise_correction,
+            ..default
+        }
+    });
+
     let options = qr_generator::Options {
+        qr_type: Some(QRSymbolTypes::from(cli.qr_type)),
         mode: Some(EncodingModes::from(cli.encoding)),
         version: cli.version,
-        correction_level: Some(CorrectionLevels::from(cli.correction_level)),
+        correction_level: cli.correction_level.map(CorrectionLevels::from),
+        eci: cli.eci.map(CharacterSets::from),
+        fnc1,
+        mask: cli.mask,
+        filepath: cli.output.clone().filter(|output| output != TERMINAL_OUTPUT),
+        format: cli.format.map(OutputFormats::from),
+        quiet_zone: cli.quiet_zone,
+        pixels_per_module: cli.scale,
+        size: cli.size,
+        dpi: cli.dpi,
+        jpeg_quality: cli.quality,
+        foreground: cli.foreground,
+        background: cli.background,
+        style: cli.style.map(ModuleStyle::from),
+        module_size: cli.module_size,
+        inverted: Some(cli.invert),
+        ansi_colours: Some(cli.ansi_colours),
+        logo,
         ..Default::default()
     };
     let mut generator = QRGenerator { options };
-    let ret = generator.make_qr_code(cli.data);
+    let qr_codes = if cli.structured_append {
+        generator.make_structured_append_qr_codes(data)
+    } else {
+        generator.make_qr_code(data).map(|qr_code| vec![qr_code])
+    };
+
+    if cli.mask_report {
+        if let Ok(qr_codes) = &qr_codes {
+            qr_codes.iter().for_each(print_mask_report);
+        }
+    }
+
+    if cli.output.as_deref() == Some(TERMINAL_OUTPUT) {
+        match qr_codes {
+            Ok(qr_codes) => {
+                for qr_code in qr_codes {
+                    print!("{}", generator.render_terminal(&qr_code));
+                }
+            }
+            Err(err) => {
+                println!("make_qr_code failed with {}", err);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let ret = qr_codes.and_then(|qr_codes| generator.save_qr_codes(&qr_codes));
     if let Err(err) = ret {
         println!("save_qr_image failed with {}", err);
         process::exit(1);
     };
 
-    println!("Successfully saved {}", ret.unwrap());
+    for filepath in ret.unwrap() {
+        println!("Successfully saved {}", filepath);
+    }
+}
+
+// One line per mask, with the one used marked by *
+fn print_mask_report(qr_code: &QRCode) {
+    for (mask, scores) in qr_code.mask_scores.iter().enumerate() {
+        let marker = if mask == qr_code.mask as usize { '*' } else { ' ' };
+        match scores {
+            MaskScores::Penalties { n1, n2, n3, n4 } => println!(
+                "{}mask {}: N1 {:4}  N2 {:4}  N3 {:4}  N4 {:4}  penalty {:5}",
+                marker, mask, n1, n2, n3, n4, scores.total()
+            ),
+            MaskScores::EdgeDarkness { sum1, sum2 } => {
+                println!("{}mask {}: SUM1 {:2}  SUM2 {:2}  score {:3}", marker, mask, sum1, sum2, scores.total())
+            }
+        }
+    }
 }